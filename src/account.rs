@@ -1,3 +1,4 @@
+use crate::balance;
 use crate::pool::Pool;
 use crate::{block::Block, claim::Claim, txn::Txn};
 use ritelinked::LinkedHashMap;
@@ -38,45 +39,18 @@ impl AccountState {
     }
 
     pub fn pending_credits(&self, address: String) -> Option<u128> {
-        let mut receipts = self.txn_pool.pending.clone();
-        receipts.retain(|_, v| v.receiver_address == address);
-
-        if receipts.is_empty() {
-            return None;
-        } else {
-            let amounts: Vec<u128> = receipts.iter().map(|(_, v)| return v.txn_amount).collect();
-            let pending_credits = amounts.iter().sum();
-            Some(pending_credits)
-        }
+        Some(balance::pending_credits(&self.txn_pool, &address)).filter(|amount| *amount > 0)
     }
 
     pub fn pending_debits(&self, address: String) -> Option<u128> {
-        let mut receipts = self.txn_pool.pending.clone();
-        receipts.retain(|_, v| v.sender_address == address);
-
-        if receipts.is_empty() {
-            return None;
-        } else {
-            let amounts: Vec<u128> = receipts.iter().map(|(_, v)| return v.txn_amount).collect();
-            let pending_debits = amounts.iter().sum();
-            Some(pending_debits)
-        }
+        Some(balance::pending_debits(&self.txn_pool, &address)).filter(|amount| *amount > 0)
     }
 
     pub fn pending_balance(&self, address: String) -> Option<(u128, u128)> {
-        let pending_credits = if let Some(amount) = self.pending_credits(address.clone()) {
-            amount
-        } else {
-            0
-        };
-
-        let pending_debits = if let Some(amount) = self.pending_debits(address.clone()) {
-            amount
-        } else {
-            0
-        };
-
-        Some((pending_credits, pending_debits))
+        Some((
+            balance::pending_credits(&self.txn_pool, &address),
+            balance::pending_debits(&self.txn_pool, &address),
+        ))
     }
 
     pub fn add_miner_to_claim_counter(&mut self, pubkey: String) {
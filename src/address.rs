@@ -0,0 +1,275 @@
+use crate::multisig::MultisigPolicy;
+use bech32::{u5, FromBase32, ToBase32, Variant};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sha256::digest_bytes;
+use std::fmt;
+use thiserror::Error;
+
+/// Version of an address controlled by a single secp256k1 key.
+pub const ADDRESS_VERSION_KEY: u8 = 0;
+/// Version of an address controlled by an M-of-N multisig policy.
+pub const ADDRESS_VERSION_MULTISIG: u8 = 1;
+/// Length of the pubkey or policy hash committed to by an address.
+pub const ADDRESS_HASH_LEN: usize = 32;
+/// Length of the hex hash that follows the prefix in a legacy address.
+pub const LEGACY_HASH_LEN: usize = 64;
+/// Whether addresses in the legacy "0x192" + hex encoding are still accepted.
+/// They carry no checksum, turn this off once the migration window closes.
+pub const ACCEPT_LEGACY_ADDRESSES: bool = true;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum AddressError {
+    #[error("invalid bech32 address: {0}")]
+    Bech32(#[from] bech32::Error),
+    #[error("addresses must use the bech32m checksum")]
+    InvalidVariant,
+    #[error("address is for network {found}, expected {expected}")]
+    WrongNetwork { expected: String, found: String },
+    #[error("unsupported address version {0}")]
+    UnsupportedVersion(u8),
+    #[error("address hash must be {} bytes, got {0}", ADDRESS_HASH_LEN)]
+    InvalidLength(usize),
+    #[error("legacy addresses are no longer accepted")]
+    LegacyAddress,
+    #[error("{0} is not a valid address")]
+    Invalid(String),
+}
+
+/// A parsed address. New addresses are bech32m encoded: a human readable part
+/// naming the network, a version and the hash of the key that controls the
+/// address, protected by a checksum that catches typos. Legacy addresses are a
+/// network prefix followed by a hex hash, accepted during the migration.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Address {
+    Bech32 {
+        hrp: String,
+        version: u8,
+        hash: Vec<u8>,
+    },
+    Legacy {
+        prefix: String,
+        hash: String,
+    },
+}
+
+impl Address {
+    /// The version 0 address controlled by `pubkey` (the string encoding of a
+    /// secp256k1 public key) on the network using `hrp`.
+    pub fn from_pubkey(hrp: &str, pubkey: &str) -> Address {
+        Address::Bech32 {
+            hrp: hrp.to_string(),
+            version: ADDRESS_VERSION_KEY,
+            hash: hash_pubkey(pubkey),
+        }
+    }
+
+    /// The version 1 address controlled by a multisig `policy`.
+    pub fn from_multisig(hrp: &str, policy: &MultisigPolicy) -> Address {
+        Address::Bech32 {
+            hrp: hrp.to_string(),
+            version: ADDRESS_VERSION_MULTISIG,
+            hash: hash_pubkey(&policy.to_string()),
+        }
+    }
+
+    /// Parses an address of any network, checking its checksum and structure.
+    pub fn parse(address: &str) -> Result<Address, AddressError> {
+        match bech32::decode(address) {
+            Ok((hrp, data, variant)) => {
+                if variant != Variant::Bech32m {
+                    return Err(AddressError::InvalidVariant);
+                }
+                if data.is_empty() {
+                    return Err(AddressError::Invalid(address.to_string()));
+                }
+
+                let version = data[0].to_u8();
+                if version != ADDRESS_VERSION_KEY && version != ADDRESS_VERSION_MULTISIG {
+                    return Err(AddressError::UnsupportedVersion(version));
+                }
+
+                let hash = Vec::<u8>::from_base32(&data[1..])?;
+                if hash.len() != ADDRESS_HASH_LEN {
+                    return Err(AddressError::InvalidLength(hash.len()));
+                }
+
+                Ok(Address::Bech32 { hrp, version, hash })
+            }
+            Err(e) => match Address::parse_legacy(address) {
+                Some(legacy) if ACCEPT_LEGACY_ADDRESSES => Ok(legacy),
+                Some(_) => Err(AddressError::LegacyAddress),
+                None => Err(AddressError::Bech32(e)),
+            },
+        }
+    }
+
+    /// Parses an address and checks that it belongs to the network using
+    /// `hrp` for new addresses and `legacy_prefix` for legacy ones.
+    pub fn parse_for_network(
+        address: &str,
+        hrp: &str,
+        legacy_prefix: &str,
+    ) -> Result<Address, AddressError> {
+        let parsed = Address::parse(address)?;
+        let (expected, found) = match &parsed {
+            Address::Bech32 { hrp: found, .. } => (hrp, found),
+            Address::Legacy { prefix: found, .. } => (legacy_prefix, found),
+        };
+
+        if expected != found {
+            return Err(AddressError::WrongNetwork {
+                expected: expected.to_string(),
+                found: found.to_string(),
+            });
+        }
+
+        Ok(parsed)
+    }
+
+    pub fn validate(address: &str) -> Result<(), AddressError> {
+        Address::parse(address).map(|_| ())
+    }
+
+    pub fn is_legacy(&self) -> bool {
+        matches!(self, Address::Legacy { .. })
+    }
+
+    pub fn is_multisig(&self) -> bool {
+        matches!(self, Address::Bech32 { version, .. } if *version == ADDRESS_VERSION_MULTISIG)
+    }
+
+    /// Whether `pubkey` is the key that controls this address.
+    pub fn matches_pubkey(&self, pubkey: &str) -> bool {
+        match self {
+            Address::Bech32 { version, hash, .. } => {
+                *version == ADDRESS_VERSION_KEY && *hash == hash_pubkey(pubkey)
+            }
+            Address::Legacy { hash, .. } => (1..=u8::MAX)
+                .any(|address_number| *hash == legacy_hash_pubkey(pubkey, address_number)),
+        }
+    }
+
+    /// Whether `policy` is the multisig policy that controls this address.
+    pub fn matches_policy(&self, policy: &MultisigPolicy) -> bool {
+        self.is_multisig() && *self == Address::from_multisig(self.hrp(), policy)
+    }
+
+    fn hrp(&self) -> &str {
+        match self {
+            Address::Bech32 { hrp, .. } => hrp,
+            Address::Legacy { prefix, .. } => prefix,
+        }
+    }
+
+    fn parse_legacy(address: &str) -> Option<Address> {
+        if address.len() <= LEGACY_HASH_LEN {
+            return None;
+        }
+
+        let (prefix, hash) = address.split_at(address.len() - LEGACY_HASH_LEN);
+        if hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            Some(Address::Legacy {
+                prefix: prefix.to_string(),
+                hash: hash.to_string(),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Address::Bech32 { hrp, version, hash } => {
+                let mut data = vec![u5::try_from_u8(*version).map_err(|_| fmt::Error)?];
+                data.extend(hash.to_base32());
+                let encoded =
+                    bech32::encode(hrp, data, Variant::Bech32m).map_err(|_| fmt::Error)?;
+                write!(f, "{}", encoded)
+            }
+            Address::Legacy { prefix, hash } => write!(f, "{}{}", prefix, hash),
+        }
+    }
+}
+
+fn hash_pubkey(pubkey: &str) -> Vec<u8> {
+    Sha256::digest(&Sha256::digest(pubkey.as_bytes())).to_vec()
+}
+
+// Legacy wallets appended the number of the address to the pubkey before
+// hashing it, so every address of a wallet has its own hash.
+fn legacy_hash_pubkey(pubkey: &str, address_number: u8) -> String {
+    let mut address_bytes = pubkey.as_bytes().to_vec();
+    address_bytes.push(address_number);
+    digest_bytes(digest_bytes(&address_bytes).as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUBKEY: &str = "03a2ba9939ae9355af62b76ad80feb23b9482102f510875227b350db30cd1ee0ac";
+
+    #[test]
+    fn test_address_round_trip() {
+        let address = Address::from_pubkey("tvrrb", PUBKEY);
+        let encoded = address.to_string();
+        assert!(encoded.starts_with("tvrrb1"));
+        assert_eq!(Address::parse(&encoded).unwrap(), address);
+        assert!(address.matches_pubkey(PUBKEY));
+    }
+
+    #[test]
+    fn test_typo_fails_checksum() {
+        let encoded = Address::from_pubkey("tvrrb", PUBKEY).to_string();
+        let last = encoded.chars().last().unwrap();
+        let typo = format!(
+            "{}{}",
+            &encoded[..encoded.len() - 1],
+            if last == 'q' { 'p' } else { 'q' }
+        );
+        assert!(Address::parse(&typo).is_err());
+    }
+
+    #[test]
+    fn test_wrong_network_rejected() {
+        let encoded = Address::from_pubkey("vrrb", PUBKEY).to_string();
+        assert!(matches!(
+            Address::parse_for_network(&encoded, "tvrrb", "0x192"),
+            Err(AddressError::WrongNetwork { .. })
+        ));
+    }
+
+    #[test]
+    fn test_multisig_address_matches_policy_only() {
+        let policy = MultisigPolicy::new(
+            1,
+            vec![
+                "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798".to_string(),
+                "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5".to_string(),
+            ],
+        )
+        .unwrap();
+        let encoded = Address::from_multisig("tvrrb", &policy).to_string();
+        let address = Address::parse(&encoded).unwrap();
+
+        assert!(address.is_multisig());
+        assert!(address.matches_policy(&policy));
+        assert!(!address.matches_pubkey(&policy.to_string()));
+    }
+
+    #[test]
+    fn test_legacy_address_accepted() {
+        // The second address of a wallet with PUBKEY, as derived by a legacy
+        // wallet.
+        let encoded = "0x192cfb05f812795519ccc9fc1b785513d95147d3177e4cb0f39cc880ad65db01f98";
+        let address = Address::parse_for_network(encoded, "tvrrb", "0x192").unwrap();
+        assert!(address.is_legacy());
+        assert!(address.matches_pubkey(PUBKEY));
+        assert!(!address.matches_pubkey(&PUBKEY.replace('a', "b")));
+        assert_eq!(address.to_string(), encoded);
+        assert_eq!(encoded, format!("0x192{}", legacy_hash_pubkey(PUBKEY, 2)));
+    }
+}
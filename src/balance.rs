@@ -0,0 +1,201 @@
+use crate::pool::Pool;
+use crate::state::NetworkState;
+use crate::txn::Txn;
+use ritelinked::LinkedHashMap;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The native token, the only one txns can move for now.
+pub const NATIVE_TOKEN: &str = "VRRB";
+
+/// The balance of one token held by one address.
+///
+/// `confirmed` is what the network state credits the address with, the
+/// `pending_in` and `pending_out` amounts are from txns in the pool that are
+/// not in a block yet. Only confirmed coins can be spent and coins already
+/// promised to a pending txn can't be spent twice, so `spendable` is the
+/// confirmed amount minus the pending outgoing amount.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Balance {
+    pub confirmed: u128,
+    pub pending_in: u128,
+    pub pending_out: u128,
+    pub spendable: u128,
+}
+
+/// Computes balances from the network state and the txn pool of a node. This
+/// is the one place balances are worked out, the wallet, txn validation and
+/// the balance command all go through it.
+#[derive(Debug, Clone, Copy)]
+pub struct BalanceService<'a> {
+    network_state: &'a NetworkState,
+    txn_pool: &'a Pool<String, Txn>,
+}
+
+impl<'a> BalanceService<'a> {
+    pub fn new(network_state: &'a NetworkState, txn_pool: &'a Pool<String, Txn>) -> Self {
+        BalanceService {
+            network_state,
+            txn_pool,
+        }
+    }
+
+    pub fn balance(&self, address: &str, token: &str) -> Balance {
+        if token != NATIVE_TOKEN {
+            return Balance::default();
+        }
+
+        let confirmed = self.network_state.get_balance(address);
+        let pending_in = pending_credits(self.txn_pool, address);
+        let pending_out = pending_debits(self.txn_pool, address);
+
+        Balance {
+            confirmed,
+            pending_in,
+            pending_out,
+            spendable: confirmed.saturating_sub(pending_out),
+        }
+    }
+
+    /// The balance of every token held by each of `addresses`.
+    pub fn balances(
+        &self,
+        addresses: &[String],
+    ) -> LinkedHashMap<String, LinkedHashMap<String, Balance>> {
+        addresses
+            .iter()
+            .map(|address| {
+                let mut tokens = LinkedHashMap::new();
+                tokens.insert(
+                    NATIVE_TOKEN.to_string(),
+                    self.balance(address, NATIVE_TOKEN),
+                );
+                (address.clone(), tokens)
+            })
+            .collect()
+    }
+}
+
+/// The total of the txns in `txn_pool` paying `address` that are not in a
+/// block yet.
+pub fn pending_credits(txn_pool: &Pool<String, Txn>, address: &str) -> u128 {
+    unmined_txns(txn_pool)
+        .filter(|txn| txn.receiver_address == address)
+        .map(|txn| txn.txn_amount)
+        .sum()
+}
+
+/// The total of the txns in `txn_pool` spending from `address` that are not
+/// in a block yet.
+pub fn pending_debits(txn_pool: &Pool<String, Txn>, address: &str) -> u128 {
+    unmined_txns(txn_pool)
+        .filter(|txn| txn.sender_address == address)
+        .map(|txn| txn.txn_amount)
+        .sum()
+}
+
+/// The txns waiting for validators and the ones validators confirmed, which
+/// stay in the pool until a block includes them.
+fn unmined_txns(txn_pool: &Pool<String, Txn>) -> impl Iterator<Item = &Txn> {
+    txn_pool.pending.values().chain(txn_pool.confirmed.values())
+}
+
+impl fmt::Display for Balance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "confirmed: {}, pending in: {}, pending out: {}, spendable: {}",
+            self.confirmed, self.pending_in, self.pending_out, self.spendable
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::network_config::NetworkConfig;
+    use crate::pool::PoolKind;
+    use crate::test_utils::TempDb;
+    use crate::txn::UnsignedTxn;
+    use crate::verifiable::Verifiable;
+    use crate::wallet::WalletAccount;
+
+    fn txn(wallet: &WalletAccount, sender: &str, receiver: &str, amount: u128) -> Txn {
+        let unsigned = UnsignedTxn::new(
+            wallet.chain_id,
+            sender.to_string(),
+            receiver.to_string(),
+            amount,
+            0,
+        );
+        wallet.sign_txn(unsigned).unwrap()
+    }
+
+    #[test]
+    fn test_balance_counts_pending_and_validator_confirmed_txns() {
+        let db = TempDb::new("balance_state");
+        let mut network_state = NetworkState::restore(db.path());
+        let mut txn_pool = Pool::new(PoolKind::Txn);
+        let mut wallet = WalletAccount::new(&NetworkConfig::testnet());
+        let sender = wallet.get_address(1);
+        let receiver = wallet.get_address(2);
+
+        let mut ledger = network_state.db_to_ledger();
+        ledger.credits.insert(sender.clone(), 100);
+        ledger.debits.insert(sender.clone(), 20);
+        network_state.update_ledger(ledger, network_state.reward_state);
+
+        let pending = txn(&wallet, &sender, &receiver, 30);
+        let confirmed = txn(&wallet, &sender, &receiver, 15);
+        txn_pool.pending.insert(pending.txn_id.clone(), pending);
+        txn_pool
+            .confirmed
+            .insert(confirmed.txn_id.clone(), confirmed);
+
+        let service = BalanceService::new(&network_state, &txn_pool);
+        assert_eq!(
+            service.balance(&sender, NATIVE_TOKEN),
+            Balance {
+                confirmed: 80,
+                pending_in: 0,
+                pending_out: 45,
+                spendable: 35,
+            }
+        );
+        assert_eq!(
+            service.balance(&receiver, NATIVE_TOKEN),
+            Balance {
+                confirmed: 0,
+                pending_in: 45,
+                pending_out: 0,
+                spendable: 0,
+            }
+        );
+        assert_eq!(service.balance(&sender, "OTHER"), Balance::default());
+    }
+
+    #[test]
+    fn test_valid_amount_does_not_count_the_txn_against_itself() {
+        let db = TempDb::new("balance_state");
+        let mut network_state = NetworkState::restore(db.path());
+        let mut txn_pool = Pool::new(PoolKind::Txn);
+        let mut wallet = WalletAccount::new(&NetworkConfig::testnet());
+        let sender = wallet.get_address(1);
+        let receiver = wallet.get_address(2);
+
+        let mut ledger = network_state.db_to_ledger();
+        ledger.credits.insert(sender.clone(), 50);
+        network_state.update_ledger(ledger, network_state.reward_state);
+
+        let spend_all = txn(&wallet, &sender, &receiver, 50);
+        assert!(spend_all.valid_amount(&network_state, &txn_pool));
+        txn_pool
+            .pending
+            .insert(spend_all.txn_id.clone(), spend_all.clone());
+        assert!(spend_all.valid_amount(&network_state, &txn_pool));
+
+        // Coins promised to the pending txn can't be spent again.
+        let spend_again = txn(&wallet, &sender, &receiver, 1);
+        assert!(!spend_again.valid_amount(&network_state, &txn_pool));
+    }
+}
@@ -1,84 +1,265 @@
-use hex;
+use crossterm::event::{self as terminal_event, Event as TerminalEvent};
+use crossterm::execute;
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
 use libp2p::gossipsub::IdentTopic as Topic;
-use libp2p::multiaddr::multiaddr;
-use libp2p::Multiaddr;
+use libp2p::swarm::Swarm;
+use libp2p::PeerId;
 use log::info;
-use rand::Rng;
 use ritelinked::LinkedHashMap;
-use simplelog::{Config, LevelFilter, WriteLogger};
-use std::fs::File;
+use serde_json::json;
+use simplelog::{Config, WriteLogger};
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::thread;
+use structopt::StructOpt;
 use tokio::io::AsyncBufReadExt;
 use tokio::sync::mpsc;
+use tui::backend::CrosstermBackend;
+use tui::Terminal;
+use vrrb_lib::address::Address;
+use vrrb_lib::balance::{BalanceService, NATIVE_TOKEN};
 use vrrb_lib::block::Block;
 use vrrb_lib::blockchain::{Blockchain, InvalidBlockErrorReason, StateComponent};
+use vrrb_lib::chain_spec::ChainSpec;
+use vrrb_lib::chain_tools::{self, ChainImport, ImportStep};
+use vrrb_lib::cli::{
+    ChainCommand, Cli, CliCommand, DataDir, NodeCommand, RunArgs, StateCommand, WalletCommand,
+};
+use vrrb_lib::config::NodeConfig;
+use vrrb_lib::dashboard::{App, KeyAction, REFRESH_INTERVAL};
+use vrrb_lib::genesis::GenesisSpec;
 use vrrb_lib::handler::{CommandHandler, MessageHandler};
+use vrrb_lib::history::{TxnStatus, WalletHistory};
+use vrrb_lib::keystore::{Keystore, KeystoreError};
 use vrrb_lib::miner::Miner;
 use vrrb_lib::network::chunkable::Chunkable;
-use vrrb_lib::network::command_utils::Command;
+use vrrb_lib::network::command_parser::{self, Input};
+use vrrb_lib::network::command_utils::{Command, CommandResult, GETSTATE};
 use vrrb_lib::network::config_utils;
+use vrrb_lib::network::connection_manager::{self, CONNECTION_CHECK_INTERVAL};
 use vrrb_lib::network::message_types::MessageType;
-use vrrb_lib::network::node::{Node, NodeAuth};
-use vrrb_lib::reward::Category;
-use vrrb_lib::reward::RewardState;
+use vrrb_lib::network::network_config::NetworkConfig;
+use vrrb_lib::network::node::{Node, NodeAuth, DISCONNECT_GRACE_PERIOD};
+use vrrb_lib::network::protocol::VrrbNetworkBehavior;
+use vrrb_lib::pool::{Pool, PoolKind};
+use vrrb_lib::reward::{Category, RewardState};
+use vrrb_lib::rpc::events::{Event, EventBus, EVENT_BUFFER_SIZE};
+use vrrb_lib::rpc::server::RpcServer;
+use vrrb_lib::rpc::view::NodeView;
+use vrrb_lib::rpc::{RpcResponse, JSONRPC_VERSION};
+use vrrb_lib::signer::Signer;
 use vrrb_lib::state::Components;
 use vrrb_lib::state::Ledger;
 use vrrb_lib::state::NetworkState;
+use vrrb_lib::txn::{Txn, UnsignedTxn};
+use vrrb_lib::verifiable::Verifiable;
 use vrrb_lib::wallet::WalletAccount;
 
-pub const VALIDATOR_THRESHOLD: f64 = 0.60;
 pub const NANO: u128 = 1;
 pub const MICRO: u128 = NANO * 1000;
 pub const MILLI: u128 = MICRO * 1000;
 pub const SECOND: u128 = MILLI * 1000;
+pub const MAX_UNLOCK_ATTEMPTS: u32 = 3;
 
 #[async_std::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::from_args();
+    let data_dir = DataDir::new(cli.data_dir);
+    // The node config can keep some of the files of the data dir elsewhere.
+    let data_dir = data_dir.with_paths(&NodeConfig::restore(&data_dir.node_config())?.paths);
+    match cli.command {
+        CliCommand::Node(NodeCommand::Run(args)) => run_node(&data_dir, args).await,
+        CliCommand::Wallet(WalletCommand::New) => new_wallet(&data_dir, false),
+        CliCommand::Wallet(WalletCommand::Restore) => new_wallet(&data_dir, true),
+        CliCommand::Wallet(WalletCommand::Balance) => wallet_balance(&data_dir),
+        CliCommand::Wallet(WalletCommand::Send {
+            address_number,
+            receiver,
+            amount,
+            rpc,
+        }) => send_txn(&data_dir, address_number, receiver, amount, rpc),
+        // Sign a txn exported by a networked node and exit, without ever
+        // starting the network, so that the wallet can live on an air-gapped
+        // machine.
+        CliCommand::Wallet(WalletCommand::Sign { txn }) => sign_offline(&data_dir, txn),
+        CliCommand::Wallet(WalletCommand::Multisig { threshold, pubkeys }) => {
+            create_multisig(&data_dir, threshold, pubkeys)
+        }
+        CliCommand::Wallet(WalletCommand::ExportWatchOnly { file }) => {
+            export_watch_only(&data_dir, &file)
+        }
+        CliCommand::Wallet(WalletCommand::Watch { entries }) => watch(&data_dir, entries),
+        CliCommand::Chain(ChainCommand::Export { file, from, to }) => {
+            export_chain(&data_dir, &file, from, to)
+        }
+        CliCommand::Chain(ChainCommand::Import { file }) => import_chain(&data_dir, &file),
+        CliCommand::Chain(ChainCommand::Verify) => verify_stored_chain(&data_dir),
+        CliCommand::State(StateCommand::Inspect { addresses }) => {
+            inspect_state(&data_dir, addresses)
+        }
+    }
+}
+
+/// Runs the node from `data_dir` until the user quits.
+async fn run_node(data_dir: &DataDir, args: RunArgs) -> Result<(), Box<dyn std::error::Error>> {
     //____________________________________________________________________________________________________
-    // Setup log file and db files
-    let mut rng = rand::thread_rng();
-    let node_type = NodeAuth::Full;
-    let log_file_suffix: u8 = rng.gen();
-    let log_file_path = if let Some(path) = std::env::args().nth(3) {
-        path
-    } else {
-        std::fs::create_dir_all("./data/vrrb")?;
-        format!("./data/vrrb/vrrb_log_file_{}.log", log_file_suffix)
-    };
-    let _ = WriteLogger::init(
-        LevelFilter::Info,
-        Config::default(),
-        File::create(log_file_path).unwrap(),
-    );
+    // Setup log file and options
+    data_dir.create()?;
+    let node_config_path = args
+        .config
+        .clone()
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_else(|| data_dir.node_config());
+    let node_config = NodeConfig::restore(&node_config_path)?;
+    let data_dir = &data_dir.with_paths(&node_config.paths);
+    let options = args.resolve(&node_config)?;
+    let chain_spec = ChainSpec::restore(&data_dir.chain_spec())?;
+    let node_type = options.node_type.clone();
+    let log_file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(data_dir.log_file())?;
+    let _ = WriteLogger::init(options.log_level, Config::default(), log_file);
     //____________________________________________________________________________________________________
 
     // ___________________________________________________________________________________________________
     // setup message and command sender/receiver channels for communication betwen various threads
-    let (to_blockchain_sender, mut to_blockchain_receiver) = mpsc::unbounded_channel();
-    let (to_miner_sender, mut to_miner_receiver) = mpsc::unbounded_channel();
+    let (to_blockchain_sender, to_blockchain_receiver) = mpsc::unbounded_channel();
+    let (to_miner_sender, to_miner_receiver) = mpsc::unbounded_channel();
     let (to_message_sender, to_message_receiver) = mpsc::unbounded_channel();
     let (from_message_sender, from_message_receiver) = mpsc::unbounded_channel();
     let (command_sender, command_receiver) = mpsc::unbounded_channel();
-    let (to_swarm_sender, mut to_swarm_receiver) = mpsc::unbounded_channel();
-    let (to_state_sender, mut to_state_receiver) = mpsc::unbounded_channel();
+    let (to_swarm_sender, to_swarm_receiver) = mpsc::unbounded_channel();
+    let (to_state_sender, to_state_receiver) = mpsc::unbounded_channel();
+    let (result_sender, mut result_receiver) = mpsc::unbounded_channel();
+    let (to_app_sender, mut to_app_receiver) = mpsc::unbounded_channel();
+    let (shutdown_sender, mut shutdown_receiver) = mpsc::unbounded_channel();
+    // The threads only send updates when there is a dashboard to show them.
+    let app_sender = if options.dashboard {
+        Some(to_app_sender)
+    } else {
+        None
+    };
     //____________________________________________________________________________________________________
 
-    let wallet = if let Some(secret_key) = std::env::args().nth(4) {
-        WalletAccount::restore_from_private_key(secret_key)
-    } else {
-        WalletAccount::new()
+    let mut network_config = NetworkConfig::restore(&data_dir.network_config())?;
+    network_config.set_chain_spec(&chain_spec);
+    let chain_id = network_config.chain_id;
+    // A genesis spec decides the genesis block, which is then committed to in
+    // the protocol id like a genesis hash set in the network config.
+    let genesis = match GenesisSpec::restore(&data_dir.genesis_spec())? {
+        Some(spec) => {
+            let block = spec.block();
+            if spec.chain_id != chain_id {
+                return Err(format!(
+                    "The genesis spec in {} is for chain {}, not {}",
+                    data_dir.genesis_spec(),
+                    spec.chain_id,
+                    chain_id
+                )
+                .into());
+            }
+            if let Some(genesis_hash) = &network_config.genesis_hash {
+                if *genesis_hash != block.hash {
+                    return Err(format!(
+                        "The genesis spec in {} builds genesis block {}, not {} as in {}",
+                        data_dir.genesis_spec(),
+                        block.hash,
+                        genesis_hash,
+                        data_dir.network_config()
+                    )
+                    .into());
+                }
+            }
+            network_config.genesis_hash = Some(block.hash.clone());
+            Some((spec, block))
+        }
+        None => None,
     };
+    let network_topic = network_config.topic();
 
-    let mut rng = rand::thread_rng();
-    let file_suffix: u32 = rng.gen();
-    let path = if let Some(path) = std::env::args().nth(2) {
-        path
+    let keystore_path = data_dir.keystore();
+    let mut wallet = if Keystore::exists(&keystore_path) {
+        unlock_wallet(&keystore_path)?
     } else {
-        format!("./data/vrrb/test_{}.db", file_suffix)
+        let wallet = setup_wallet(&network_config)?;
+        save_new_wallet(&wallet, &keystore_path)?;
+        wallet
     };
 
-    let network_state = NetworkState::restore(&path);
-    let reward_state = RewardState::start();
+    if wallet.chain_id != network_config.chain_id {
+        return Err(format!(
+            "The wallet in {} belongs to chain {}, not {}",
+            keystore_path, wallet.chain_id, network_config.chain_id
+        )
+        .into());
+    }
+
+    let mut network_state = NetworkState::restore(&data_dir.state_db());
+    // Resume from the chain in the data dir, unless the node stopped between
+    // storing a block and the state after it. The state is then requested
+    // from the network again.
+    let mut blockchain = Blockchain::restore(&data_dir.chain_db(), chain_id, chain_spec.clone());
+    blockchain.genesis_hash = network_config.genesis_hash.clone();
+    let stored_genesis = blockchain.genesis.as_ref().map(|block| block.hash.clone());
+    match (stored_genesis, blockchain.genesis_hash.clone(), genesis) {
+        (Some(stored), Some(genesis_hash), _) if stored != genesis_hash => {
+            return Err(format!(
+                "The chain in {} starts from genesis block {}, not {}",
+                data_dir.chain_db(),
+                stored,
+                genesis_hash
+            )
+            .into());
+        }
+        (None, _, Some((spec, block))) => {
+            println!(
+                "Starting the chain from the genesis spec in {}",
+                data_dir.genesis_spec()
+            );
+            network_state = spec.network_state(&data_dir.state_db(), &block);
+            let reward_state = network_state.reward_state;
+            if let Err(e) = blockchain.process_block(&network_state, &reward_state, &block) {
+                return Err(format!("Error starting the genesis block: {}", e).into());
+            }
+        }
+        _ => {}
+    }
+    if let Some(last_block) = blockchain.child.clone() {
+        if network_state.state_hash == Some(last_block.hash.clone()) {
+            println!(
+                "Resuming from block {} in {}",
+                last_block.header.block_height,
+                data_dir.chain_db()
+            );
+        } else {
+            println!(
+                "The chain in {} doesn't match the state in {}, requesting the state from the network",
+                data_dir.chain_db(),
+                data_dir.state_db()
+            );
+            blockchain = Blockchain::new(&data_dir.chain_db(), chain_id, chain_spec.clone());
+            blockchain.genesis_hash = network_config.genesis_hash.clone();
+        }
+    }
+    let last_block = blockchain.child.clone();
+    wallet.discover_addresses(&network_state);
+    let wallet_history = Arc::new(Mutex::new(WalletHistory::restore(
+        &data_dir.wallet_history(),
+    )));
+    let rpc_view = Arc::new(Mutex::new(NodeView::new()));
+    let event_bus = EventBus::new(EVENT_BUFFER_SIZE);
+    if let Err(e) = wallet.update_keystore(&keystore_path) {
+        println!("Error updating the wallet keystore: {:?}", e);
+    }
+    let reward_state = network_state.reward_state;
 
     //____________________________________________________________________________________________________
     // Node initialization
@@ -93,9 +274,26 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         command_receiver,
     );
 
-    let mut node = Node::new(node_type.clone(), command_handler, to_message_handler);
+    let mut node = Node::new(
+        node_type.clone(),
+        command_handler,
+        to_message_handler,
+        wallet.pubkey.clone().to_string(),
+    );
     let node_id = node.id.clone();
     let node_key = node.key.clone();
+    let threads = ThreadContext {
+        node_id,
+        node_type: node_type.clone(),
+        to_blockchain_sender: to_blockchain_sender.clone(),
+        to_miner_sender: to_miner_sender.clone(),
+        to_swarm_sender: to_swarm_sender.clone(),
+        to_state_sender: to_state_sender.clone(),
+        result_sender: result_sender.clone(),
+        app_sender: app_sender.clone(),
+        rpc_view: rpc_view.clone(),
+        event_bus: event_bus.clone(),
+    };
     //____________________________________________________________________________________________________
 
     //____________________________________________________________________________________________________
@@ -107,15 +305,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         node_key.clone(),
         wallet.pubkey.clone().to_string(),
         wallet.clone().get_address(1),
-        "events.db".to_string(),
+        data_dir.events_db(),
+        network_config.clone(),
+        data_dir.address_book(),
+        &node_config,
     )
     .await;
 
-    let port = rand::thread_rng().gen_range(9292, 19292);
-    let addr: Multiaddr = multiaddr!(Ip4([0, 0, 0, 0]), Tcp(port as u16));
+    let addr = options.listen_address.clone();
     println!("{:?}", &addr);
 
-    swarm.listen_on(addr.clone()).unwrap();
+    swarm.listen_on(addr.clone())?;
     swarm
         .behaviour_mut()
         .kademlia
@@ -123,24 +323,191 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     //____________________________________________________________________________________________________
 
     //____________________________________________________________________________________________________
-    // Dial peer if provided
-    if let Some(to_dial) = std::env::args().nth(1) {
-        let dialing = to_dial.clone();
-        match to_dial.parse() {
-            Ok(to_dial) => match swarm.dial_addr(to_dial) {
-                Ok(_) => {
-                    println!("Dialed {:?}", dialing);
-                }
-                Err(e) => println!("Dial {:?} failed: {:?}", dialing, e), //
-            },
-            Err(err) => println!("Failed to parse address to dial {:?}", err), //
-        }
-    }
+    // Redial peers from the address book, the network's bootstrap nodes and
+    // the bootstrap peers of this node
+    let mut bootstrap_nodes = network_config.bootstrap_nodes.clone();
+    bootstrap_nodes.extend(options.bootstrap_peers.iter().cloned());
+    node.redial_known_peers(&mut swarm, &bootstrap_nodes);
     //____________________________________________________________________________________________________
 
     //____________________________________________________________________________________________________
     // Swarm event thread
+    let swarm_handle = spawn_swarm_thread(swarm, network_topic, to_swarm_receiver, threads.clone());
+    //____________________________________________________________________________________________________
+
+    //____________________________________________________________________________________________________
+    // Node thread
+    let node_handle = tokio::task::spawn(async move {
+        if let Err(_) = node.start().await {
+            panic!("Unable to start node!")
+        };
+    });
+    //____________________________________________________________________________________________________
+
+    //____________________________________________________________________________________________________
+    // JSON-RPC server
+    spawn_rpc_server(&network_config, command_sender.clone(), &threads);
+    //____________________________________________________________________________________________________
+
+    //____________________________________________________________________________________________________
+    // Blockchain thread
+    let blockchain_handle = spawn_blockchain_thread(
+        blockchain,
+        network_state.clone(),
+        reward_state,
+        to_blockchain_receiver,
+        threads.clone(),
+    );
+    //____________________________________________________________________________________________________
+
+    //____________________________________________________________________________________________________
+    // Mining thread
+    let miner_signer: Arc<dyn Signer> = Arc::new(wallet.signer()?);
+    let mut miner = Miner::start(
+        miner_signer,
+        wallet.clone().get_address(1),
+        reward_state,
+        network_state,
+        0,
+        &network_config,
+        chain_spec,
+    );
+    // A resumed node mines on top of the chain it stored instead of
+    // mining a new genesis block. The time it was stopped doesn't count
+    // towards abandoning the claim that is due to mine.
+    if let Some(block) = last_block {
+        miner.current_nonce_timer = miner.get_timestamp();
+        miner.last_block = Some(block);
+        miner.claim_map = miner.network_state.get_claims();
+    }
+    let miner_handle = spawn_miner_thread(
+        miner,
+        wallet.clone(),
+        wallet_history.clone(),
+        data_dir.txns_dir(),
+        to_miner_receiver,
+        threads.clone(),
+    );
+    //____________________________________________________________________________________________________
+    // State Sending Thread
+    //____________________________________________________________________________________________________
+    let state_handle = spawn_state_thread(to_state_receiver, threads.clone());
+
+    //____________________________________________________________________________________________________
+    // Ctrl-C stops the node like QUIT does, a second Ctrl-C stops it right
+    // away. The dashboard reads Ctrl-C as a key, this catches it on the plain
+    // command line and SIGINT sent by other programs.
+    let interrupted = AtomicBool::new(false);
+    ctrlc::set_handler(move || {
+        if interrupted.swap(true, Ordering::SeqCst) {
+            std::process::exit(130);
+        }
+        if let Err(e) = shutdown_sender.send(()) {
+            println!("Error sending shutdown to the interface: {:?}", e);
+        }
+    })?;
+    //____________________________________________________________________________________________________
+
+    //____________________________________________________________________________________________________
+    // Terminal Interface loop
+    let mut console = Console {
+        wallet: wallet.clone(),
+        keystore_path,
+        wallet_history: wallet_history.clone(),
+        node_id: node_id.to_string(),
+        swarm_sender: to_swarm_sender.clone(),
+        command_sender: command_sender.clone(),
+        network_config: network_config.clone(),
+        view: rpc_view.clone(),
+    };
+    let interface_result = if options.dashboard {
+        let app = App::new(
+            options.listen_address.clone(),
+            wallet,
+            wallet_history,
+            data_dir.events_db(),
+        );
+        run_dashboard(
+            app,
+            &mut console,
+            &mut to_app_receiver,
+            &mut result_receiver,
+            &mut shutdown_receiver,
+        )
+        .await
+    } else {
+        run_console(&mut console, &mut result_receiver, &mut shutdown_receiver).await
+    };
+    //____________________________________________________________________________________________________
+
+    //____________________________________________________________________________________________________
+    // Shutdown
+    // The node thread tells the peers this node is leaving, so that claim
+    // lowest pointers are calculated without it, and passes QUIT on to the
+    // swarm, blockchain, miner and state threads, which store what they hold
+    // and stop.
+    println!("Stopping the node");
+    if let Err(e) = command_sender.send(Command::Quit) {
+        println!("Error sending quit command to command receiver: {:?}", e);
+    }
+    if let Err(e) = node_handle.await {
+        println!("Error stopping the node thread: {:?}", e);
+    }
+    if let Err(e) = swarm_handle.await {
+        println!("Error stopping the swarm thread: {:?}", e);
+    }
+    let handles = vec![
+        ("blockchain", blockchain_handle),
+        ("mining", miner_handle),
+        ("state", state_handle),
+    ];
+    for (name, handle) in handles {
+        if handle.join().is_err() {
+            println!("The {} thread stopped with an error", name);
+        }
+    }
+    console.flush();
+
+    println!(
+        "The node stopped. Run it again with --data-dir {} to resume from",
+        data_dir.path().display()
+    );
+    println!("    wallet keystore: {}", data_dir.keystore());
+    println!("    chain db:        {}", data_dir.chain_db());
+    println!("    state db:        {}", data_dir.state_db());
+    println!("    wallet history:  {}", data_dir.wallet_history());
+    //____________________________________________________________________________________________________
+
+    interface_result
+}
+
+/// What every thread of the node gets a clone of: the senders of the
+/// channels between the threads and the views the RPC server and the
+/// dashboard read.
+#[derive(Clone)]
+struct ThreadContext {
+    node_id: PeerId,
+    node_type: NodeAuth,
+    to_blockchain_sender: mpsc::UnboundedSender<Command>,
+    to_miner_sender: mpsc::UnboundedSender<Command>,
+    to_swarm_sender: mpsc::UnboundedSender<Command>,
+    to_state_sender: mpsc::UnboundedSender<Command>,
+    result_sender: mpsc::UnboundedSender<CommandResult>,
+    app_sender: Option<mpsc::UnboundedSender<Command>>,
+    rpc_view: Arc<Mutex<NodeView>>,
+    event_bus: EventBus,
+}
+
+/// Spawns the task that drives the swarm: it publishes the messages of the
+/// other threads, disconnects peers and keeps the connections maintained.
+fn spawn_swarm_thread(
+    mut swarm: Swarm<VrrbNetworkBehavior>,
+    network_topic: String,
+    mut to_swarm_receiver: mpsc::UnboundedReceiver<Command>,
+    ctx: ThreadContext,
+) -> tokio::task::JoinHandle<()> {
     tokio::task::spawn(async move {
+        let mut connection_interval = tokio::time::interval(CONNECTION_CHECK_INTERVAL);
         loop {
             let evt = {
                 tokio::select! {
@@ -148,62 +515,106 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         info!("Unhandled Swarm Event: {:?}", event);
                         None
                     },
+                    _ = connection_interval.tick() => {
+                        connection_manager::maintain_connections(&mut swarm);
+                        let peers = swarm.behaviour().connection_manager.connected_peers();
+                        update_app(&ctx.app_sender, || Command::UpdateAppPeers(peers.clone()));
+                        ctx.rpc_view.lock().unwrap().peers = peers;
+                        None
+                    },
                     command = to_swarm_receiver.recv() => {
-                        if let Some(command) = command {
-                            match command {
-                                Command::SendMessage(message) => {
-                                    Some(message)
-                                }
-                                _ => {None}
-                            }
-                        } else {
-                            None
-                        }
+                        command
                     }
                 }
             };
 
-            if let Some(message) = evt {
-                let encoded = hex::encode(message);
-                if let Err(e) = swarm
-                    .behaviour_mut()
-                    .gossipsub
-                    .publish(Topic::new("test-net"), encoded)
-                {
-                    info!("Error sending to network: {:?}", e);
-                };
+            if let Some(command) = evt {
+                match command {
+                    Command::SendMessage(message) => {
+                        if let Err(e) = swarm
+                            .behaviour_mut()
+                            .gossipsub
+                            .publish(Topic::new(network_topic.clone()), message)
+                        {
+                            info!("Error sending to network: {:?}", e);
+                        };
+                    }
+                    Command::DisconnectPeer(peer_id) => {
+                        // This libp2p version can only close the connections
+                        // to a peer by banning it. The ban is lifted right
+                        // away, the address book keeps the peer from being
+                        // dialed again too soon.
+                        if let Ok(peer_id) = PeerId::from_str(&peer_id) {
+                            swarm.ban_peer_id(peer_id);
+                            swarm.unban_peer_id(peer_id);
+                        }
+                    }
+                    Command::Quit => {
+                        // Keep the swarm running a moment to deliver the
+                        // disconnect message published before QUIT.
+                        let grace_period = tokio::time::sleep(DISCONNECT_GRACE_PERIOD);
+                        tokio::pin!(grace_period);
+                        loop {
+                            tokio::select! {
+                                event = swarm.next() => info!("Unhandled Swarm Event: {:?}", event),
+                                _ = &mut grace_period => break,
+                            }
+                        }
+                        break;
+                    }
+                    _ => {}
+                }
             }
         }
-    });
-    //____________________________________________________________________________________________________
+    })
+}
 
-    //____________________________________________________________________________________________________
-    // Node thread
-    tokio::task::spawn(async move {
-        if let Err(_) = node.start().await {
-            panic!("Unable to start node!")
-        };
-    });
-    //____________________________________________________________________________________________________
+/// Serves JSON-RPC on the RPC address of the network config, if it has one.
+fn spawn_rpc_server(
+    network_config: &NetworkConfig,
+    command_sender: mpsc::UnboundedSender<Command>,
+    ctx: &ThreadContext,
+) {
+    if let Some(rpc_address) = network_config.rpc_address.clone() {
+        let rpc_server = RpcServer::new(
+            ctx.rpc_view.clone(),
+            ctx.event_bus.clone(),
+            command_sender,
+            ctx.to_swarm_sender.clone(),
+            ctx.node_id.to_string(),
+            network_config.clone(),
+        );
+        tokio::task::spawn(async move {
+            if let Err(e) = rpc_server.serve(&rpc_address).await {
+                println!(
+                    "Error running the JSON-RPC server on {}: {:?}",
+                    rpc_address, e
+                );
+            }
+        });
+    }
+}
 
-    //____________________________________________________________________________________________________
-    // Blockchain thread
-    let mut blockchain_network_state = network_state.clone();
-    let mut blockchain_reward_state = reward_state.clone();
-    let blockchain_to_miner_sender = to_miner_sender.clone();
-    let blockchain_to_swarm_sender = to_swarm_sender.clone();
-    let blockchain_to_blockchain_sender = to_blockchain_sender.clone();
-    let blockchain_to_state_sender = to_state_sender.clone();
+/// Spawns the thread that processes blocks into the chain and the network
+/// state, and answers and applies requests for the state.
+fn spawn_blockchain_thread(
+    mut blockchain: Blockchain,
+    mut network_state: NetworkState,
+    mut reward_state: RewardState,
+    mut to_blockchain_receiver: mpsc::UnboundedReceiver<Command>,
+    ctx: ThreadContext,
+) -> thread::JoinHandle<()> {
     thread::spawn(move || {
-        let mut rng = rand::thread_rng();
-        let file_suffix: u32 = rng.gen();
-        let mut blockchain = Blockchain::new(&format!("./data/vrrb/test_{}.db", file_suffix));
+        ctx.rpc_view.lock().unwrap().chain_db = Some(blockchain.chain_db.clone());
+        // The peer that sent the last confirmed block, the state is requested
+        // from it by default.
+        let mut last_block_sender: Option<String> = None;
         loop {
-            let miner_sender = blockchain_to_miner_sender.clone();
-            let swarm_sender = blockchain_to_swarm_sender.clone();
-            let state_sender = blockchain_to_state_sender.clone();
-            let blockchain_sender = blockchain_to_blockchain_sender.clone();
-            // let blockchain_sender = blockchain_to_blockchain_sender.clone();
+            let miner_sender = ctx.to_miner_sender.clone();
+            let swarm_sender = ctx.to_swarm_sender.clone();
+            let state_sender = ctx.to_state_sender.clone();
+            let blockchain_sender = ctx.to_blockchain_sender.clone();
+            // let blockchain_sender = ctx.to_blockchain_sender.clone();
             if let Ok(command) = to_blockchain_receiver.try_recv() {
                 match command {
                     Command::PendingBlock(block, sender_id) => {
@@ -212,11 +623,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 .future_blocks
                                 .insert(block.clone().header.last_hash, block.clone());
                         } else {
-                            if let Err(e) = blockchain.process_block(
-                                &blockchain_network_state,
-                                &blockchain_reward_state,
-                                &block,
-                            ) {
+                            if let Err(e) =
+                                blockchain.process_block(&network_state, &reward_state, &block)
+                            {
+                                if !matches!(e.details, InvalidBlockErrorReason::BlockOutOfSequence)
+                                {
+                                    ctx.event_bus
+                                        .publish(Event::invalid_block(&block, e.details.clone()));
+                                }
                                 match e.details {
                                     InvalidBlockErrorReason::BlockOutOfSequence => {
                                         // Stash block in blockchain.future_blocks
@@ -228,9 +642,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                             println!("Error: {:?}", e);
                                             if let Some((_, v)) = blockchain.future_blocks.front() {
                                                 let message = MessageType::GetNetworkStateMessage {
-                                                    sender_id: node_id.clone().to_string(),
+                                                    sender_id: ctx.node_id.clone().to_string(),
                                                     requested_from: sender_id,
-                                                    requestor_node_type: node_type.clone(),
+                                                    requestor_node_type: ctx.node_type.clone(),
                                                     lowest_block: v.header.block_height,
                                                     component: StateComponent::All,
                                                 };
@@ -259,9 +673,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                                 > lowest_block.header.block_height + 1
                                             {
                                                 let message = MessageType::GetNetworkStateMessage {
-                                                    sender_id: node_id.clone().to_string(),
+                                                    sender_id: ctx.node_id.clone().to_string(),
                                                     requested_from: sender_id,
-                                                    requestor_node_type: node_type.clone(),
+                                                    requestor_node_type: ctx.node_type.clone(),
                                                     lowest_block: lowest_block.header.block_height,
                                                     component: StateComponent::All,
                                                 };
@@ -279,7 +693,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                                     block_height: block.header.block_height,
                                                     reason: e.details,
                                                     miner_id: sender_id,
-                                                    sender_id: node_id.clone().to_string(),
+                                                    sender_id: ctx.node_id.clone().to_string(),
                                                 };
                                                 if let Err(e) = swarm_sender
                                                     .send(Command::SendMessage(message.as_bytes()))
@@ -301,16 +715,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                     println!("Error sending command to receiver");
                                 };
                             } else {
-                                blockchain_network_state.dump(&block);
+                                network_state.dump(&block);
+                                last_block_sender = Some(sender_id);
                                 if let Err(_) =
                                     miner_sender.send(Command::ConfirmedBlock(block.clone()))
                                 {
                                     println!("Error sending command to receiver");
                                 }
 
-                                if let Err(_) = miner_sender.send(Command::StateUpdateCompleted(
-                                    blockchain_network_state.clone(),
-                                )) {
+                                if let Err(_) = miner_sender
+                                    .send(Command::StateUpdateCompleted(network_state.clone()))
+                                {
                                     println!(
                                         "Error sending state update completed command to receiver"
                                     );
@@ -336,9 +751,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 None
                             };
                             let current_ledger =
-                                Some(blockchain_network_state.clone().db_to_ledger().as_bytes());
-                            let current_network_state =
-                                Some(blockchain_network_state.clone().as_bytes());
+                                Some(network_state.clone().db_to_ledger().as_bytes());
+                            let current_network_state = Some(network_state.clone().as_bytes());
                             let components = Components {
                                 genesis: genesis_bytes,
                                 child: child_bytes,
@@ -362,7 +776,19 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     },
                     Command::StateUpdateComponents(components) => {
                         if let Some(bytes) = components.genesis {
-                            blockchain.genesis = Some(Block::from_bytes(&bytes))
+                            let genesis = Block::from_bytes(&bytes);
+                            if genesis.valid_genesis(
+                                &network_state,
+                                &reward_state,
+                                blockchain.genesis_hash.as_deref(),
+                            ) {
+                                blockchain.genesis = Some(genesis)
+                            } else {
+                                println!(
+                                    "Ignoring genesis block {} from another chain",
+                                    genesis.hash
+                                );
+                            }
                         }
 
                         if let Some(bytes) = components.child {
@@ -375,19 +801,22 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             let mut new_blockchain = Blockchain::from_bytes(&bytes);
                             new_blockchain.future_blocks = blockchain.clone().future_blocks;
                             new_blockchain.chain_db = blockchain.clone().chain_db;
+                            // The rules and the genesis block are this node's,
+                            // not the peer's.
+                            new_blockchain.chain_spec = blockchain.chain_spec.clone();
+                            new_blockchain.genesis_hash = blockchain.genesis_hash.clone();
                             blockchain = new_blockchain;
                         }
                         if let Some(bytes) = components.network_state {
                             let mut new_network_state = NetworkState::from_bytes(&bytes);
-                            new_network_state.path = blockchain_network_state.path;
-                            blockchain_reward_state = new_network_state.reward_state;
-                            blockchain_network_state = new_network_state;
+                            new_network_state.path = network_state.path;
+                            reward_state = new_network_state.reward_state;
+                            network_state = new_network_state;
                         }
 
                         if let Some(bytes) = components.ledger {
                             let new_ledger = Ledger::from_bytes(&bytes);
-                            blockchain_network_state
-                                .update_ledger(new_ledger, blockchain_reward_state);
+                            network_state.update_ledger(new_ledger, reward_state);
                         }
 
                         if let Some(bytes) = components.archive {
@@ -407,17 +836,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             if last_block.header.block_height == block.header.block_height {
                                 println!("Block already processed, skipping")
                             } else {
-                                if let Err(e) = blockchain.process_block(
-                                    &blockchain_network_state,
-                                    &blockchain_reward_state,
-                                    &block,
-                                ) {
+                                if let Err(e) =
+                                    blockchain.process_block(&network_state, &reward_state, &block)
+                                {
                                     println!(
                                         "Error trying to process backlogged future blocks: {:?}",
                                         e
                                     );
                                 } else {
-                                    blockchain_network_state.dump(&block);
+                                    network_state.dump(&block);
                                     if let Err(e) =
                                         miner_sender.send(Command::ConfirmedBlock(block.clone()))
                                     {
@@ -430,67 +857,142 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             }
                         }
                         println!("Backlog processed");
-                        if let Err(e) = miner_sender.send(Command::StateUpdateCompleted(
-                            blockchain_network_state.clone(),
-                        )) {
+                        if let Err(e) =
+                            miner_sender.send(Command::StateUpdateCompleted(network_state.clone()))
+                        {
                             println!("Error sending updated network state to miner: {:?}", e);
                         }
                         blockchain.updating_state = false;
                     }
-                    Command::StateUpdateCompleted(network_state) => {
-                        blockchain_network_state = network_state.clone();
+                    Command::StateUpdateCompleted(new_network_state) => {
+                        network_state = new_network_state.clone();
                     }
                     Command::ClaimAbandoned(_, claim) => {
-                        blockchain_network_state.abandoned_claim(claim.hash);
-                        if let Err(e) = miner_sender.send(Command::StateUpdateCompleted(
-                            blockchain_network_state.clone(),
-                        )) {
+                        network_state.abandoned_claim(claim.hash);
+                        if let Err(e) =
+                            miner_sender.send(Command::StateUpdateCompleted(network_state.clone()))
+                        {
                             println!("Error sending updated network state to miner: {:?}", e);
                         }
                     }
                     Command::SlashClaims(bad_validators) => {
-                        blockchain_network_state.slash_claims(bad_validators);
+                        network_state.slash_claims(bad_validators);
                     }
                     Command::GetHeight => {
-                        println!("Blockchain Height: {}", blockchain.chain.len());
+                        let height = blockchain
+                            .child
+                            .as_ref()
+                            .or(blockchain.genesis.as_ref())
+                            .map(|block| block.header.block_height);
+                        if let Err(e) = ctx.result_sender.send(CommandResult::Height(height)) {
+                            println!("Error sending command result: {:?}", e);
+                        }
+                    }
+                    Command::GetState(peer_id) => {
+                        let result = match peer_id.or_else(|| last_block_sender.clone()) {
+                            Some(requested_from) => {
+                                let lowest_block = blockchain
+                                    .child
+                                    .as_ref()
+                                    .or(blockchain.genesis.as_ref())
+                                    .map_or(0, |block| block.header.block_height);
+                                let message = MessageType::GetNetworkStateMessage {
+                                    sender_id: ctx.node_id.clone().to_string(),
+                                    requested_from: requested_from.clone(),
+                                    requestor_node_type: ctx.node_type.clone(),
+                                    lowest_block,
+                                    component: StateComponent::All,
+                                };
+                                match swarm_sender.send(Command::SendMessage(message.as_bytes())) {
+                                    Ok(_) => {
+                                        blockchain.updating_state = true;
+                                        CommandResult::Message(format!(
+                                            "Requested the network state from {}",
+                                            requested_from
+                                        ))
+                                    }
+                                    Err(e) => CommandResult::Error(format!(
+                                        "Error sending state request to swarm sender: {:?}",
+                                        e
+                                    )),
+                                }
+                            }
+                            None => CommandResult::Error(format!(
+                                "No block has been received yet, pass the id of a peer to request the state from: {} <peer id>",
+                                GETSTATE
+                            )),
+                        };
+                        if let Err(e) = ctx.result_sender.send(result) {
+                            println!("Error sending command result: {:?}", e);
+                        }
+                    }
+                    Command::Quit => {
+                        // Blocks and state are stored as they are processed,
+                        // store the state hashes to resume from and stop.
+                        network_state.flush();
+                        break;
                     }
                     _ => {}
                 }
+
+                update_app(&ctx.app_sender, || {
+                    Command::UpdateAppBlockchain(blockchain.as_bytes())
+                });
             }
         }
-    });
-    //____________________________________________________________________________________________________
+    })
+}
 
-    //____________________________________________________________________________________________________
-    // Mining thread
-    let mut mining_wallet = wallet.clone();
-    let miner_network_state = network_state.clone();
-    let miner_reward_state = reward_state.clone();
-    let miner_to_miner_sender = to_miner_sender.clone();
-    let miner_to_blockchain_sender = to_blockchain_sender.clone();
-    let miner_to_swarm_sender = to_swarm_sender.clone();
+/// Spawns the thread that mines, validates txns and claims and answers the
+/// wallet commands that need the state of the miner.
+fn spawn_miner_thread(
+    mut miner: Miner,
+    mut wallet: WalletAccount,
+    wallet_history: Arc<Mutex<WalletHistory>>,
+    txns_dir: String,
+    mut to_miner_receiver: mpsc::UnboundedReceiver<Command>,
+    ctx: ThreadContext,
+) -> thread::JoinHandle<()> {
     thread::spawn(move || {
-        let mut miner = Miner::start(
-            mining_wallet.clone().get_secretkey(),
-            mining_wallet.clone().get_pubkey(),
-            mining_wallet.clone().get_address(1),
-            miner_reward_state,
-            miner_network_state,
-            0,
-        );
+        update_app(&ctx.app_sender, || {
+            Command::UpdateAppMiner(miner.as_bytes())
+        });
         loop {
-            let blockchain_sender = miner_to_blockchain_sender.clone();
-            let swarm_sender = miner_to_swarm_sender.clone();
-            let miner_sender = miner_to_miner_sender.clone();
+            let blockchain_sender = ctx.to_blockchain_sender.clone();
+            let swarm_sender = ctx.to_swarm_sender.clone();
+            let miner_sender = ctx.to_miner_sender.clone();
             if let Ok(command) = to_miner_receiver.try_recv() {
+                // The mining loop itself doesn't change anything the RPC
+                // server or the dashboard show.
+                let update_rpc_view = !matches!(
+                    command,
+                    Command::MineBlock | Command::NonceUp | Command::SendMessage(_)
+                );
                 match command {
                     Command::SendMessage(message) => {
                         if let Err(e) = swarm_sender.send(Command::SendMessage(message)) {
                             println!("Error sending to swarm receiver: {:?}", e);
                         }
                     }
-                    Command::MineBlock => {
+                    Command::StartMiner => {
                         miner.mining = true;
+                        if let Err(e) = miner_sender.send(Command::MineBlock) {
+                            println!("Error sending MineBlock command to miner: {:?}", e);
+                        }
+                        if let Err(e) = ctx.result_sender.send(CommandResult::Mining(true)) {
+                            println!("Error sending command result: {:?}", e);
+                        }
+                    }
+                    Command::StopMine => {
+                        miner.mining = false;
+                        if let Err(e) = ctx.result_sender.send(CommandResult::Mining(false)) {
+                            println!("Error sending command result: {:?}", e);
+                        }
+                    }
+                    Command::Quit => break,
+                    // Mining was stopped, drop the rest of the mining loop.
+                    Command::MineBlock if !miner.mining => {}
+                    Command::MineBlock => {
                         if let Some(last_block) = miner.last_block.clone() {
                             if let Some(claim) =
                                 miner.clone().claim_map.get(&miner.clone().claim.pubkey)
@@ -503,7 +1005,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                         if let Some(block) = block {
                                             let message = MessageType::BlockMessage {
                                                 block: block.clone(),
-                                                sender_id: node_id.clone().to_string(),
+                                                sender_id: ctx.node_id.clone().to_string(),
                                             };
 
                                             if let Err(e) = swarm_sender
@@ -515,7 +1017,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                             if let Err(_) =
                                                 blockchain_sender.send(Command::PendingBlock(
                                                     block.clone(),
-                                                    node_id.clone().to_string(),
+                                                    ctx.node_id.clone().to_string(),
                                                 ))
                                             {
                                                 println!("Error sending PendingBlock command to blockchain");
@@ -529,7 +1031,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                             }
                                         }
                                     } else {
-                                        if miner.check_time_elapsed() > 30 {
+                                        if miner.check_time_elapsed()
+                                            > miner.chain_spec.claim_abandon_timeout_secs as u128
+                                        {
                                             miner.current_nonce_timer = miner.get_timestamp();
                                             let mut abandoned_claim_map = miner.claim_map.clone();
                                             abandoned_claim_map.retain(|_, v| v.hash == hash);
@@ -556,7 +1060,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
                                                 if abandoned_claim_map.len() as f64
                                                     / (miner.claim_map.len() as f64 - 1.0)
-                                                    > VALIDATOR_THRESHOLD
+                                                    > miner.chain_spec.validator_threshold
                                                 {
                                                     miner.claim_map.retain(|_, v| v.hash != hash);
                                                     if let Err(e) = blockchain_sender.send(
@@ -619,13 +1123,28 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             block.header.claim.clone().pubkey,
                             block.header.claim.clone(),
                         );
+
+                        wallet_history
+                            .lock()
+                            .unwrap()
+                            .record_block(&block, &wallet.all_addresses());
+                        ctx.rpc_view.lock().unwrap().add_block(&block);
+                        ctx.event_bus.publish(Event::NewBlock {
+                            block: Box::new(block),
+                        });
                     }
                     Command::ProcessTxn(txn) => {
+                        ctx.event_bus
+                            .publish(Event::PendingTxn { txn: txn.clone() });
+                        wallet_history
+                            .lock()
+                            .unwrap()
+                            .record_txn(&txn, &wallet.all_addresses());
                         let txn_validator = miner.process_txn(txn.clone());
                         miner.check_confirmed(txn.txn_id.clone());
                         let message = MessageType::TxnValidatorMessage {
                             txn_validator,
-                            sender_id: node_id.to_string().clone(),
+                            sender_id: ctx.node_id.to_string().clone(),
                         };
                         if let Err(e) = miner_sender.send(Command::SendMessage(message.as_bytes()))
                         {
@@ -655,14 +1174,33 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             bad_validators.iter().for_each(|k| {
                                 miner.slash_claim(k.to_string());
                             });
+                            wallet_history
+                                .lock()
+                                .unwrap()
+                                .set_status(&validator.txn.txn_id, TxnStatus::Rejected);
                         } else {
                             miner.check_confirmed(validator.txn.txn_id.clone());
+                            if miner.txn_pool.confirmed.contains_key(&validator.txn.txn_id) {
+                                wallet_history
+                                    .lock()
+                                    .unwrap()
+                                    .set_status(&validator.txn.txn_id, TxnStatus::Confirmed);
+                            }
                         }
                     }
                     Command::InvalidBlock(_) => {}
                     Command::StateUpdateCompleted(network_state) => {
+                        if network_state.reward_state != miner.network_state.reward_state {
+                            ctx.event_bus.publish(Event::RewardUpdate {
+                                reward_state: network_state.reward_state,
+                            });
+                        }
                         miner.network_state = network_state.clone();
                         miner.claim_map = miner.network_state.get_claims();
+                        wallet.discover_addresses(&miner.network_state);
+                        update_app(&ctx.app_sender, || {
+                            Command::UpdateAppWallet(wallet.as_bytes())
+                        });
                         if miner.mining {
                             if let Err(e) = miner_sender.send(Command::MineBlock) {
                                 println!("Error sending MineBlock command to miner: {:?}", e);
@@ -674,7 +1212,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             miner.last_block = Some(block.clone());
                             let message = MessageType::BlockMessage {
                                 block: block.clone(),
-                                sender_id: node_id.to_string().clone(),
+                                sender_id: ctx.node_id.to_string().clone(),
                             };
 
                             if let Err(e) =
@@ -684,7 +1222,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             }
                             if let Err(_) = blockchain_sender.send(Command::PendingBlock(
                                 block.clone(),
-                                node_id.clone().to_string(),
+                                ctx.node_id.clone().to_string(),
                             )) {
                                 println!("Error sending to command receiver")
                             }
@@ -693,7 +1231,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     Command::SendAddress => {
                         let message = MessageType::ClaimMessage {
                             claim: miner.claim.clone(),
-                            sender_id: node_id.clone().to_string(),
+                            sender_id: ctx.node_id.clone().to_string(),
                         };
 
                         if let Err(e) = miner_sender.send(Command::SendMessage(message.as_bytes()))
@@ -707,6 +1245,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             println!("Error sending MineBlock command to miner: {:?}", e);
                         }
                     }
+                    Command::PruneMiner(pubkey) => {
+                        miner.prune_miner(&pubkey);
+                    }
                     Command::ClaimAbandoned(pubkey, claim) => {
                         if let Some(_) = miner.claim_map.get(&pubkey) {
                             miner
@@ -717,7 +1258,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
                             if abandoned_claim_map.len() as f64
                                 / (miner.claim_map.len() as f64 - 1.0)
-                                > VALIDATOR_THRESHOLD
+                                > miner.chain_spec.validator_threshold
                             {
                                 miner.claim_map.retain(|_, v| v.hash != claim.hash);
                                 if let Err(e) =
@@ -729,27 +1270,69 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         }
                     }
                     Command::GetBalance(address_number) => {
-                        println!(
-                            "Balance: {}",
-                            miner
-                                .network_state
-                                .get_balance(&mining_wallet.get_address(address_number))
-                        )
+                        let address = wallet.get_address(address_number);
+                        let balance_service =
+                            BalanceService::new(&miner.network_state, &miner.txn_pool);
+                        wallet.update_balances(&balance_service);
+                        let balance = balance_service.balance(&address, NATIVE_TOKEN);
+                        if let Err(e) = ctx
+                            .result_sender
+                            .send(CommandResult::Balance(address, balance))
+                        {
+                            println!("Error sending command result: {:?}", e);
+                        }
+                    }
+                    Command::BuildTxn(sender, receiver, amount) => {
+                        let unsigned = miner.build_txn(sender, receiver, amount);
+                        let exported = unsigned.export();
+                        let path = format!(
+                            "{}/{}_{}.unsigned",
+                            txns_dir, unsigned.sender_address, unsigned.nonce
+                        );
+                        let saved_to = match std::fs::create_dir_all(&txns_dir)
+                            .and_then(|_| std::fs::write(&path, &exported))
+                        {
+                            Ok(_) => Some(path),
+                            Err(e) => {
+                                if let Err(e) = ctx.result_sender.send(CommandResult::Error(
+                                    format!("Error saving the unsigned txn: {:?}", e),
+                                )) {
+                                    println!("Error sending command result: {:?}", e);
+                                }
+                                None
+                            }
+                        };
+                        if let Err(e) = ctx.result_sender.send(CommandResult::TxnBuilt(
+                            saved_to,
+                            format!("{}\n\n{}", unsigned, exported),
+                        )) {
+                            println!("Error sending command result: {:?}", e);
+                        }
                     }
                     _ => {}
                 }
+
+                if update_rpc_view {
+                    ctx.rpc_view.lock().unwrap().update_from_miner(&miner);
+                    update_app(&ctx.app_sender, || {
+                        Command::UpdateAppMiner(miner.as_bytes())
+                    });
+                }
             }
         }
-    });
-    //____________________________________________________________________________________________________
-    // State Sending Thread
-    //____________________________________________________________________________________________________
-    let state_to_swarm_sender = to_swarm_sender.clone();
-    let state_to_blockchain_sender = to_blockchain_sender.clone();
+    })
+}
+
+/// Spawns the thread that sends the state components peers request in
+/// chunks, and puts the chunks it receives back together.
+fn spawn_state_thread(
+    mut to_state_receiver: mpsc::UnboundedReceiver<Command>,
+    ctx: ThreadContext,
+) -> thread::JoinHandle<()> {
     let mut state_chunk_cache = LinkedHashMap::new();
     thread::spawn(move || loop {
-        let blockchain_sender = state_to_blockchain_sender.clone();
-        let swarm_sender = state_to_swarm_sender.clone();
+        let blockchain_sender = ctx.to_blockchain_sender.clone();
+        let swarm_sender = ctx.to_swarm_sender.clone();
         if let Ok(command) = to_state_receiver.try_recv() {
             match command {
                 Command::SendStateComponents(requestor, components) => {
@@ -773,7 +1356,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 chunk_number: idx + 1,
                                 total_chunks,
                                 requestor: requestor.clone(),
-                                sender_id: node_id.clone().to_string(),
+                                sender_id: ctx.node_id.clone().to_string(),
                             };
 
                             idx += 1;
@@ -810,59 +1393,720 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 Command::ConfirmedBlock(_) => {
                     // Dump block to block archive.
                 }
+                Command::Quit => break,
                 _ => {}
             }
         }
-    });
+    })
+}
 
-    //____________________________________________________________________________________________________
-    // Terminal Interface loop
-    let terminal_to_swarm_sender = to_swarm_sender.clone();
+/// Reads commands from stdin until QUIT, stdin is closed or Ctrl-C, and
+/// prints their results.
+async fn run_console(
+    console: &mut Console,
+    result_receiver: &mut mpsc::UnboundedReceiver<CommandResult>,
+    shutdown_receiver: &mut mpsc::UnboundedReceiver<()>,
+) -> Result<(), Box<dyn std::error::Error>> {
     let mut stdin = tokio::io::BufReader::new(tokio::io::stdin()).lines();
     loop {
-        let swarm_sender = terminal_to_swarm_sender.clone();
-        let evt = {
-            tokio::select! {
-                // await an input from the user
-                line = stdin.next_line() => Some(
-                    line.expect("can get line").expect("can read line from stdin")
-                )
-            }
+        tokio::select! {
+            // await an input from the user
+            line = stdin.next_line() => {
+                // Stdin closed, e.g. the node runs in the background, is
+                // handled like QUIT.
+                let line = match line {
+                    Ok(Some(line)) => line,
+                    Ok(None) => break,
+                    Err(e) => {
+                        println!("Error reading from stdin: {:?}", e);
+                        break;
+                    }
+                };
+                match console.handle_line(&line) {
+                    LineOutcome::Quit => break,
+                    LineOutcome::Output(output) => println!("{}", output),
+                    LineOutcome::Forwarded => {}
+                }
+            },
+            // or the result of a command the user entered
+            result = result_receiver.recv() => {
+                if let Some(result) = result {
+                    println!("{}", result);
+                }
+            },
+            // or Ctrl-C
+            _ = shutdown_receiver.recv() => break,
+        }
+    }
+    Ok(())
+}
+
+/// Sends the dashboard, if there is one, the update built by `update`.
+fn update_app<F: FnOnce() -> Command>(
+    app_sender: &Option<mpsc::UnboundedSender<Command>>,
+    update: F,
+) {
+    if let Some(sender) = app_sender {
+        if let Err(e) = sender.send(update()) {
+            info!("Error sending update to the dashboard: {:?}", e);
+        }
+    }
+}
+
+/// What the interface does with a line typed into it.
+enum LineOutcome {
+    Quit,
+    Output(String),
+    // The command went to the node's threads, which send its result back.
+    Forwarded,
+}
+
+/// Runs the lines typed into the plain command line or the dashboard. The
+/// commands that use the wallet are run here, the others are forwarded to the
+/// command handler.
+struct Console {
+    wallet: WalletAccount,
+    keystore_path: String,
+    wallet_history: Arc<Mutex<WalletHistory>>,
+    node_id: String,
+    swarm_sender: mpsc::UnboundedSender<Command>,
+    command_sender: mpsc::UnboundedSender<Command>,
+    network_config: NetworkConfig,
+    // The state and txn pool of the miner, for the nonces of sent txns.
+    view: Arc<Mutex<NodeView>>,
+}
+
+impl Console {
+    fn handle_line(&mut self, line: &str) -> LineOutcome {
+        let command = match command_parser::parse(line, &self.network_config) {
+            Ok(Input::Command(command)) => command,
+            Ok(Input::Help(command)) => return LineOutcome::Output(command_parser::help(command)),
+            Ok(Input::Test) => return LineOutcome::Output("The node is running".to_string()),
+            Err(e) => return LineOutcome::Output(e.to_string()),
         };
-        if let Some(line) = evt {
-            if line == "QUIT" {
-                // Clean up and inform the network that you are no longer mining so that
-                // claim lowest pointers will be properly calculated.
-                break;
-            }
-            // If there is some input from the user, attemmpt to convert the input to
-            // a command and send to the command handler.
-            if let Some(command) = Command::from_str(&line) {
-                match command.clone() {
-                    Command::SendTxn(addr_num, receiver, amount) => {
-                        let txn = wallet.clone().send_txn(addr_num, receiver, amount);
-                        if let Ok(txn) = txn {
-                            let message = MessageType::TxnMessage {
-                                txn,
-                                sender_id: node_id.to_string().clone(),
-                            };
-                            if let Err(e) =
-                                swarm_sender.send(Command::SendMessage(message.as_bytes()))
-                            {
-                                println!("Error sending to command receiver: {:?}", e);
-                            };
-                        }
+
+        let result = match command {
+            Command::Quit => return LineOutcome::Quit,
+            Command::SendTxn(addr_num, receiver, amount) => {
+                let view = self.view.lock().unwrap();
+                let network_state = match view.network_state.as_ref() {
+                    Some(network_state) => network_state,
+                    None => {
+                        return LineOutcome::Output(
+                            "The network state isn't loaded yet, try again shortly".to_string(),
+                        )
                     }
-                    _ => {
-                        if let Err(_) = command_sender.send(command) {
-                            println!("Error sending command to command receiver");
-                        };
+                };
+                match self.wallet.send_txn(
+                    addr_num,
+                    receiver,
+                    amount,
+                    network_state,
+                    &view.txn_pool,
+                ) {
+                    Ok(txn) => {
+                        if let Err(e) = self.wallet.update_keystore(&self.keystore_path) {
+                            info!("Error updating the wallet keystore: {:?}", e);
+                        }
+                        self.wallet_history
+                            .lock()
+                            .unwrap()
+                            .record_txn(&txn, &self.wallet.all_addresses());
+                        broadcast_txn(&self.swarm_sender, txn, &self.node_id)
                     }
+                    Err(e) => CommandResult::Error(e.to_string()),
                 }
             }
+            Command::SendRawTxn(txn) => {
+                self.wallet_history
+                    .lock()
+                    .unwrap()
+                    .record_txn(&txn, &self.wallet.all_addresses());
+                broadcast_txn(&self.swarm_sender, txn, &self.node_id)
+            }
+            Command::GetHistory(address_number) => {
+                let address = self.wallet.get_address(address_number);
+                let history = self.wallet_history.lock().unwrap();
+                let records = history
+                    .for_address(&address)
+                    .into_iter()
+                    .map(|record| {
+                        let confirmations = history.confirmations(&record);
+                        (record, confirmations)
+                    })
+                    .collect();
+                CommandResult::History(address, records)
+            }
+            command => {
+                if let Err(e) = self.command_sender.send(command) {
+                    return LineOutcome::Output(format!(
+                        "Error sending command to command receiver: {:?}",
+                        e
+                    ));
+                };
+                return LineOutcome::Forwarded;
+            }
+        };
+
+        LineOutcome::Output(result.to_string())
+    }
+
+    /// Stores the wallet and its history as they are when the node stops.
+    fn flush(&mut self) {
+        if let Err(e) = self.wallet.update_keystore(&self.keystore_path) {
+            println!("Error updating the wallet keystore: {:?}", e);
         }
+        self.wallet_history.lock().unwrap().dump();
     }
-    //____________________________________________________________________________________________________
+}
+
+/// Shows the dashboard until the user quits, running the lines typed into its
+/// command line with `console`.
+async fn run_dashboard(
+    mut app: App,
+    console: &mut Console,
+    app_receiver: &mut mpsc::UnboundedReceiver<Command>,
+    result_receiver: &mut mpsc::UnboundedReceiver<CommandResult>,
+    shutdown_receiver: &mut mpsc::UnboundedReceiver<()>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Reading the terminal blocks, so keys are read on their own thread.
+    let (key_sender, mut key_receiver) = mpsc::unbounded_channel();
+    thread::spawn(move || loop {
+        match terminal_event::read() {
+            Ok(TerminalEvent::Key(key)) => {
+                if key_sender.send(key).is_err() {
+                    break;
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                info!("Error reading the terminal: {:?}", e);
+                break;
+            }
+        }
+    });
+
+    enable_raw_mode()?;
+    let mut stdout = std::io::stdout();
+    execute!(stdout, EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout))?;
+    terminal.clear()?;
+
+    let mut refresh = tokio::time::interval(REFRESH_INTERVAL);
+    let result = loop {
+        if let Err(e) = terminal.draw(|f| app.draw(f)) {
+            break Err(e);
+        }
+        tokio::select! {
+            key = key_receiver.recv() => match key.map(|key| app.on_key(key)) {
+                Some(KeyAction::Submit(line)) => match console.handle_line(&line) {
+                    LineOutcome::Quit => break Ok(()),
+                    LineOutcome::Output(output) => app.push_output(&output),
+                    LineOutcome::Forwarded => {}
+                },
+                Some(KeyAction::None) => {}
+                Some(KeyAction::Quit) | None => break Ok(()),
+            },
+            Some(update) = app_receiver.recv() => app.update(update),
+            Some(result) = result_receiver.recv() => app.push_output(&result.to_string()),
+            _ = shutdown_receiver.recv() => break Ok(()),
+            _ = refresh.tick() => {}
+        }
+    };
+
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+    Ok(result?)
+}
+
+/// Broadcasts a txn sent from the terminal to the network.
+fn broadcast_txn(
+    swarm_sender: &mpsc::UnboundedSender<Command>,
+    txn: Txn,
+    node_id: &str,
+) -> CommandResult {
+    let txn_id = txn.txn_id.clone();
+    let message = MessageType::TxnMessage {
+        txn,
+        sender_id: node_id.to_string(),
+    };
+    match swarm_sender.send(Command::SendMessage(message.as_bytes())) {
+        Ok(_) => CommandResult::TxnSent(txn_id),
+        Err(e) => CommandResult::Error(format!("Error sending to command receiver: {:?}", e)),
+    }
+}
+
+/// Walks the user through restoring a wallet from a mnemonic phrase or creating
+/// a new one.
+fn setup_wallet(
+    network_config: &NetworkConfig,
+) -> Result<WalletAccount, Box<dyn std::error::Error>> {
+    let phrase = prompt(
+        "Enter your mnemonic phrase to restore your wallet, or press enter to create a new one:",
+    )?;
+    if !phrase.is_empty() {
+        return restore_wallet(&phrase, network_config);
+    }
+    create_wallet(network_config)
+}
+
+/// Restores the wallet of a mnemonic phrase and its optional passphrase.
+fn restore_wallet(
+    phrase: &str,
+    network_config: &NetworkConfig,
+) -> Result<WalletAccount, Box<dyn std::error::Error>> {
+    let passphrase = prompt("Enter your wallet passphrase (press enter if it has none):")?;
+    Ok(WalletAccount::from_mnemonic(
+        phrase,
+        &passphrase,
+        network_config,
+    )?)
+}
+
+/// Creates a wallet from a new mnemonic phrase. The phrase has to be typed
+/// back before the wallet is used so that users don't run a wallet they can't
+/// restore.
+fn create_wallet(
+    network_config: &NetworkConfig,
+) -> Result<WalletAccount, Box<dyn std::error::Error>> {
+    let word_count = loop {
+        match prompt("Number of words in your new mnemonic phrase, 12 or 24 [12]:")?.as_str() {
+            "" | "12" => break 12,
+            "24" => break 24,
+            _ => println!("Please enter 12 or 24"),
+        }
+    };
+
+    let passphrase = prompt_new_secret(
+        "Choose an optional passphrase (press enter for none):",
+        "Confirm your passphrase:",
+        true,
+    )?;
+
+    let mnemonic = WalletAccount::generate_mnemonic(word_count)?;
+    println!(
+        "\n{}\n\n{}\n",
+        "WRITE DOWN YOUR MNEMONIC PHRASE AND DO NOT SHARE IT, IT IS THE ONLY WAY TO RESTORE YOUR WALLET:",
+        mnemonic
+    );
+
+    loop {
+        let confirmation = prompt("Type your mnemonic phrase to confirm you saved it:")?;
+        let confirmation = confirmation.split_whitespace().collect::<Vec<_>>().join(" ");
+        if confirmation == mnemonic.to_string() {
+            break;
+        }
+        println!("The phrase does not match, please try again");
+    }
+
+    Ok(WalletAccount::from_mnemonic(
+        &mnemonic.to_string(),
+        &passphrase,
+        network_config,
+    )?)
+}
 
+/// Encrypts a new wallet with a password chosen by the user and saves it to
+/// the keystore at `path`.
+fn save_new_wallet(wallet: &WalletAccount, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let password = prompt_new_secret(
+        "Choose a password to encrypt your wallet keystore:",
+        "Confirm your keystore password:",
+        false,
+    )?;
+    wallet.save(path, &password)?;
     Ok(())
 }
+
+/// Creates a new wallet, or restores one from its mnemonic phrase, into the
+/// keystore of `data_dir`. An existing keystore is never overwritten.
+fn new_wallet(data_dir: &DataDir, restore: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let keystore_path = data_dir.keystore();
+    if Keystore::exists(&keystore_path) {
+        return Err(format!(
+            "There already is a wallet in {}, move it away first",
+            keystore_path
+        )
+        .into());
+    }
+
+    data_dir.create()?;
+    let network_config = NetworkConfig::restore(&data_dir.network_config())?;
+    let mut wallet = if restore {
+        let phrase = prompt("Enter your mnemonic phrase:")?;
+        restore_wallet(&phrase, &network_config)?
+    } else {
+        create_wallet(&network_config)?
+    };
+    wallet.discover_addresses(&NetworkState::restore(&data_dir.state_db()));
+    let address = wallet.get_address(1);
+    save_new_wallet(&wallet, &keystore_path)?;
+    println!("Wallet saved to {}", keystore_path);
+    println!("Address: {}", address);
+    Ok(())
+}
+
+/// Prints the balances of the addresses in the keystore of `data_dir` from the
+/// ledger the node stored. The addresses aren't encrypted, so this doesn't need
+/// the password, and pending txns are only known to a running node.
+fn wallet_balance(data_dir: &DataDir) -> Result<(), Box<dyn std::error::Error>> {
+    let keystore = Keystore::load(&data_dir.keystore())?;
+    let network_state = NetworkState::restore(&data_dir.state_db());
+    let txn_pool = Pool::new(PoolKind::Txn);
+    let balance_service = BalanceService::new(&network_state, &txn_pool);
+    let addresses = keystore
+        .addresses
+        .values()
+        .chain(keystore.multisig_policies.keys())
+        .cloned()
+        .collect::<Vec<_>>();
+    for (address, balances) in balance_service.balances(&addresses) {
+        for (token, balance) in balances {
+            println!("{} {}: {}", address, token, balance);
+        }
+    }
+    Ok(())
+}
+
+/// Signs a txn with the wallet in the keystore of `data_dir` and hands it to
+/// a running node through its JSON-RPC server, which broadcasts it.
+fn send_txn(
+    data_dir: &DataDir,
+    address_number: u32,
+    receiver: String,
+    amount: u128,
+    rpc: Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let network_config = NetworkConfig::restore(&data_dir.network_config())?;
+    let rpc_address = rpc
+        .or(network_config.rpc_address)
+        .ok_or("The JSON-RPC server is turned off in the network config, pass --rpc")?;
+
+    let keystore_path = data_dir.keystore();
+    let mut wallet = unlock_wallet(&keystore_path)?;
+    Address::parse_for_network(&receiver, &wallet.address_hrp, &wallet.address_prefix)?;
+    // The node knows the nonces the sender used in blocks and in its txn pool,
+    // the wallet only the ones it used itself.
+    let sender = wallet.get_address(address_number);
+    let nonce = rpc_request::<u128>(&rpc_address, "getTxnNonce", json!([sender]))?
+        .max(wallet.txn_nonce(&sender));
+    let unsigned = UnsignedTxn::new(wallet.chain_id, sender, receiver, amount, nonce);
+    let txn = wallet.sign_txn(unsigned)?;
+    println!("{}", txn);
+
+    rpc_request::<String>(&rpc_address, "sendRawTxn", json!([txn.export()]))
+        .map_err(|e| format!("The node rejected the txn: {}", e))?;
+
+    // Only spend the nonce once the node took the txn.
+    wallet.set_txn_nonce(&txn.sender_address, nonce + 1);
+    wallet.update_keystore(&keystore_path)?;
+    let mut history = WalletHistory::restore(&data_dir.wallet_history());
+    history.record_txn(&txn, &wallet.all_addresses());
+    println!("Sent txn {}", txn.txn_id);
+    Ok(())
+}
+
+/// Sends a JSON-RPC request to the node at `rpc_address` and returns the
+/// result, or the error the node answered with.
+fn rpc_request<T: serde::de::DeserializeOwned>(
+    rpc_address: &str,
+    method: &str,
+    params: serde_json::Value,
+) -> Result<T, Box<dyn std::error::Error>> {
+    let request = json!({
+        "jsonrpc": JSONRPC_VERSION,
+        "id": 1,
+        "method": method,
+        "params": params,
+    });
+    let mut stream = TcpStream::connect(rpc_address)
+        .map_err(|e| format!("Error connecting to the node at {}: {}", rpc_address, e))?;
+    stream.write_all(format!("{}\n", request).as_bytes())?;
+    let mut response = String::new();
+    BufReader::new(stream).read_line(&mut response)?;
+    let response = serde_json::from_str::<RpcResponse>(&response)?;
+    match (response.result, response.error) {
+        (_, Some(error)) => Err(error.message.into()),
+        (Some(result), None) => Ok(serde_json::from_str(result.get())?),
+        (None, None) => Err(format!("The node sent no result for {}", method).into()),
+    }
+}
+
+/// Signs the unsigned txn in `input`, either a file or the exported txn itself,
+/// with the wallet in the keystore and exports the signed txn next to it. It is
+/// then broadcast from a networked node with SENDRAWTXN. A multisig txn signed
+/// by some of its cosigners is passed on to the next cosigner, who signs it the
+/// same way, until enough of them have signed.
+fn sign_offline(data_dir: &DataDir, input: String) -> Result<(), Box<dyn std::error::Error>> {
+    let (exported, path) = match std::fs::read_to_string(&input) {
+        Ok(exported) => {
+            let path = input
+                .trim_end_matches(".unsigned")
+                .trim_end_matches(".signed")
+                .to_string();
+            (exported, Some(path))
+        }
+        Err(_) => (input, None),
+    };
+
+    let wallet = unlock_wallet(&data_dir.keystore())?;
+    let txn = if let Ok(mut txn) = Txn::import(&exported) {
+        println!("{}", txn);
+        if prompt("Add your signature to this multisig txn? [y/N]")?.to_lowercase() != "y" {
+            return Ok(());
+        }
+        wallet.sign_multisig_txn(&mut txn)?;
+        txn
+    } else {
+        let unsigned = UnsignedTxn::import(&exported)?;
+        println!("{}", unsigned);
+        if prompt("Sign this txn? [y/N]")?.to_lowercase() != "y" {
+            return Ok(());
+        }
+        wallet.sign_txn(unsigned)?
+    };
+
+    if let Err(e) = txn.finalize() {
+        println!("{}, pass the signed txn on to the next cosigner", e);
+    }
+    let exported = txn.export();
+    if let Some(path) = path {
+        let path = format!("{}.signed", path);
+        std::fs::write(&path, &exported)?;
+        println!("Signed txn saved to {}", path);
+    }
+    println!("{}", exported);
+    Ok(())
+}
+
+/// Creates a multisig address controlled by any `threshold` of the cosigners'
+/// `pubkeys` and saves it to the wallet. Without a threshold it prints the
+/// pubkey this wallet cosigns with, to share with the other cosigners.
+fn create_multisig(
+    data_dir: &DataDir,
+    threshold: Option<usize>,
+    pubkeys: Vec<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let keystore_path = data_dir.keystore();
+    let mut wallet = unlock_wallet(&keystore_path)?;
+    let threshold = match threshold {
+        Some(threshold) => threshold,
+        None => {
+            println!("Cosigner pubkey: {}", wallet.get_address_pubkey(1)?);
+            return Ok(());
+        }
+    };
+
+    let address = wallet.create_multisig_address(threshold, pubkeys)?;
+    wallet.update_keystore(&keystore_path)?;
+    println!("Multisig address: {}", address);
+    Ok(())
+}
+
+/// Writes the addresses of the wallet in the keystore to `path` as a watch-only
+/// wallet, for `vrrb wallet watch` on a machine that must not hold the keys.
+fn export_watch_only(data_dir: &DataDir, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let wallet = unlock_wallet(&data_dir.keystore())?;
+    std::fs::write(path, wallet.export_watch_only())?;
+    println!("Watch-only wallet saved to {}", path.display());
+    Ok(())
+}
+
+/// Prints the balances and txn history of a watch-only wallet, either a file
+/// exported with `vrrb wallet export-watch-only` or a list of addresses and
+/// pubkeys, from the ledger and the blocks stored in `data_dir`.
+fn watch(data_dir: &DataDir, entries: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let network_config = NetworkConfig::restore(&data_dir.network_config())?;
+    let wallet = match std::fs::read_to_string(&entries[0]) {
+        Ok(exported) => WalletAccount::import_watch_only(&exported)?,
+        Err(_) => WalletAccount::watch_only(&entries, &network_config)?,
+    };
+
+    let network_state = NetworkState::restore(&data_dir.state_db());
+    let chain_spec = ChainSpec::restore(&data_dir.chain_spec())?;
+    let chain_db =
+        Blockchain::new(&data_dir.chain_db(), wallet.chain_id, chain_spec).get_chain_db();
+    let mut blocks = chain_db
+        .get_all()
+        .iter()
+        .filter_map(|key| chain_db.get::<Block>(key))
+        .collect::<Vec<_>>();
+    blocks.sort_by_key(|block| block.header.block_height);
+
+    let addresses = wallet.all_addresses();
+    let mut history = WalletHistory::restore(&data_dir.watch_history());
+    blocks
+        .iter()
+        .for_each(|block| history.record_block(block, &addresses));
+
+    // A watching machine isn't on the network, so only confirmed amounts are
+    // known.
+    let txn_pool = Pool::new(PoolKind::Txn);
+    let balance_service = BalanceService::new(&network_state, &txn_pool);
+    for address in addresses {
+        println!(
+            "{}: {}",
+            address,
+            balance_service.balance(&address, NATIVE_TOKEN)
+        );
+        for record in history.for_address(&address) {
+            println!(
+                "    {} {} {} {} {} ({} confirmations)",
+                record.txn_id,
+                record.direction,
+                record.counterparty,
+                record.amount,
+                record.status,
+                history.confirmations(&record)
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the blocks of the chain stored in `data_dir` from height `from` to
+/// height `to`, the whole chain by default, to `path` for `vrrb chain import`.
+fn export_chain(
+    data_dir: &DataDir,
+    path: &Path,
+    from: Option<u128>,
+    to: Option<u128>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let header = chain_tools::export_chain(data_dir, path, from, to)?;
+    println!(
+        "Exported blocks {} to {} of chain {} to {}",
+        header.from,
+        header.to,
+        header.chain_id,
+        path.display()
+    );
+    Ok(())
+}
+
+/// Applies the blocks in `path`, exported with `vrrb chain export`, on top of
+/// the chain and state stored in `data_dir`, see `ChainImport`.
+fn import_chain(data_dir: &DataDir, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let mut import = ChainImport::open(data_dir, path)?;
+    let header = import.header().clone();
+    println!(
+        "Importing blocks {} to {} of chain {} from {}",
+        header.from,
+        header.to,
+        header.chain_id,
+        path.display()
+    );
+    loop {
+        match import.import_next() {
+            Ok(Some(ImportStep::Imported(height))) => {
+                print!(
+                    "\rImported block {} ({}/{})",
+                    height,
+                    import.read(),
+                    header.block_count
+                );
+                std::io::stdout().flush()?;
+            }
+            Ok(Some(ImportStep::Skipped(_))) => {}
+            Ok(None) => break,
+            Err(e) => {
+                println!();
+                return Err(e.into());
+            }
+        }
+    }
+    println!();
+
+    match import.height() {
+        Some(height) => println!(
+            "Imported {} blocks, the chain in {} is at height {}",
+            import.imported(),
+            data_dir.chain_db(),
+            height
+        ),
+        None => println!("{} has no blocks to import", path.display()),
+    }
+    Ok(())
+}
+
+/// Re-verifies every block of the chain stored in `data_dir` from its genesis
+/// block, and compares the state they add up to with the stored network state
+/// and ledger, printing the first place they diverge.
+fn verify_stored_chain(data_dir: &DataDir) -> Result<(), Box<dyn std::error::Error>> {
+    println!(
+        "Verifying the chain in {} against the state in {}",
+        data_dir.chain_db(),
+        data_dir.state_db()
+    );
+    let verification = chain_tools::verify_stored_chain(data_dir)?;
+
+    println!("Blocks verified: {}", verification.blocks);
+    match verification.divergence {
+        Some(divergence) => {
+            println!("{}", divergence);
+            Err("The stored chain and state diverge from a replay of the chain".into())
+        }
+        None => {
+            println!("The chain and the state match");
+            Ok(())
+        }
+    }
+}
+
+/// Prints the network state stored in `data_dir`: its hash, the reward state,
+/// the last block and the ledger, or only the `addresses` of the ledger asked
+/// for.
+fn inspect_state(
+    data_dir: &DataDir,
+    addresses: Vec<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    println!("{}", chain_tools::inspect_state(data_dir, addresses)?);
+    Ok(())
+}
+
+/// Unlocks the wallet keystore, giving the user a few attempts at the password.
+fn unlock_wallet(path: &str) -> Result<WalletAccount, Box<dyn std::error::Error>> {
+    let mut attempts = 0;
+    loop {
+        let password = prompt("Enter your wallet keystore password:")?;
+        match WalletAccount::load(path, &password) {
+            Ok(wallet) => return Ok(wallet),
+            Err(KeystoreError::IncorrectPassword) if attempts + 1 < MAX_UNLOCK_ATTEMPTS => {
+                attempts += 1;
+                println!("Incorrect password, please try again");
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Asks for a new password or passphrase until the user types it the same way
+/// twice.
+fn prompt_new_secret(
+    message: &str,
+    confirm_message: &str,
+    allow_empty: bool,
+) -> std::io::Result<String> {
+    loop {
+        let secret = prompt(message)?;
+        if secret.is_empty() {
+            if allow_empty {
+                return Ok(secret);
+            }
+            println!("The password can't be empty");
+            continue;
+        }
+        if prompt(confirm_message)? == secret {
+            return Ok(secret);
+        }
+        println!("They do not match, please try again");
+    }
+}
+
+fn prompt(message: &str) -> std::io::Result<String> {
+    println!("{}", message);
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input)?;
+    Ok(input.trim().to_string())
+}
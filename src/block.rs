@@ -1,7 +1,9 @@
 use crate::blockchain::{InvalidBlockError, InvalidBlockErrorReason};
+use crate::chain_spec::ChainSpec;
 use crate::header::BlockHeader;
-use crate::network::chunkable::Chunkable;
-use crate::network::node::MAX_TRANSMIT_SIZE;
+use crate::network::chunkable::{chunk_bytes, Chunkable};
+use crate::network::node::MAX_CHUNK_SIZE;
+use crate::signer::Signer;
 use crate::state::NetworkState;
 use crate::verifiable::Verifiable;
 use crate::{claim::Claim, reward::RewardState, txn::Txn};
@@ -16,8 +18,6 @@ pub const MICRO: u128 = NANO * 1000;
 pub const MILLI: u128 = MICRO * 1000;
 pub const SECOND: u128 = MILLI * 1000;
 
-const VALIDATOR_THRESHOLD: f64 = 0.60;
-
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[repr(C)]
 pub struct Block {
@@ -35,27 +35,30 @@ pub struct Block {
 impl Block {
     // Returns a result with either a tuple containing the genesis block and the
     // updated account state (if successful) or an error (if unsuccessful)
-    pub fn genesis(reward_state: &RewardState, claim: Claim, secret_key: String) -> Option<Block> {
-        let header = BlockHeader::genesis(0, reward_state, claim.clone(), secret_key);
-        let state_hash = digest_bytes(
-            format!(
-                "{},{}",
-                header.last_hash,
-                digest_bytes("Genesis_State_Hash".as_bytes())
-            )
-            .as_bytes(),
-        );
-
+    pub fn genesis(
+        reward_state: &RewardState,
+        claim: Claim,
+        signer: &dyn Signer,
+        chain_id: u64,
+    ) -> Option<Block> {
+        let header = match BlockHeader::genesis(0, chain_id, reward_state, claim.clone(), signer)
+        {
+            Ok(header) => header,
+            Err(e) => {
+                info!("Error signing the genesis block header: {}", e);
+                return None;
+            }
+        };
         let mut claims = LinkedHashMap::new();
         claims.insert(claim.clone().pubkey.clone(), claim);
 
         let genesis = Block {
+            hash: Block::genesis_hash(&header),
             header,
             neighbors: None,
             height: 0,
             txns: LinkedHashMap::new(),
             claims,
-            hash: state_hash,
             received_at: None,
             received_from: None,
             abandoned_claim: None,
@@ -80,7 +83,8 @@ impl Block {
         network_state: &NetworkState,
         neighbors: Option<Vec<BlockHeader>>,
         abandoned_claim: Option<Claim>,
-        signature: String,
+        signer: &dyn Signer,
+        chain_spec: &ChainSpec, // The rules the block is mined by.
     ) -> Option<Block> {
 
         let txn_hash = {
@@ -103,18 +107,24 @@ impl Block {
             }
         };
 
-        let header = BlockHeader::new(
+        let header = match BlockHeader::new(
             last_block.clone(),
             reward_state,
             claim,
             txn_hash,
             claim_map_hash,
             neighbors_hash,
-            signature,
-        );
+            signer,
+        ) {
+            Ok(header) => header,
+            Err(e) => {
+                info!("Error signing the block header: {}", e);
+                return None;
+            }
+        };
 
         if let Some(time) = header.timestamp.checked_sub(last_block.header.timestamp) {
-            if (time / SECOND) < 1 {
+            if (time / SECOND) < chain_spec.min_block_time_secs as u128 {
                 return None
             }
         } else {
@@ -142,6 +152,22 @@ impl Block {
         Some(block)
     }
 
+    /// The hash of a genesis block, which has no state before it to hash.
+    pub fn genesis_hash(header: &BlockHeader) -> String {
+        digest_bytes(
+            format!(
+                "{},{}",
+                header.get_payload(),
+                digest_bytes("Genesis_State_Hash".as_bytes())
+            )
+            .as_bytes(),
+        )
+    }
+
+    pub fn claims_hash(claims: &LinkedHashMap<String, Claim>) -> String {
+        digest_bytes(serde_json::to_string(claims).unwrap().as_bytes())
+    }
+
     pub fn as_bytes(&self) -> Vec<u8> {
         self.to_string().as_bytes().to_vec()
     }
@@ -177,8 +203,28 @@ impl Verifiable for Block {
         true
     }
 
-    fn valid_genesis(&self, _network_state: &NetworkState, _reward_state: &RewardState) -> bool {
-        true
+    fn valid_genesis(
+        &self,
+        _network_state: &NetworkState,
+        _reward_state: &RewardState,
+        genesis_hash: Option<&str>,
+    ) -> bool {
+        if self.header.block_height != 0 || self.hash != Block::genesis_hash(&self.header) {
+            return false;
+        }
+
+        if let Some(claim_map_hash) = &self.header.claim_map_hash {
+            if *claim_map_hash != Block::claims_hash(&self.claims) {
+                return false;
+            }
+        }
+
+        // Without a genesis spec, the network's genesis block is whichever
+        // one a miner mined first.
+        match genesis_hash {
+            Some(genesis_hash) => self.hash == genesis_hash,
+            None => true,
+        }
     }
 
     fn valid_block(
@@ -186,7 +232,18 @@ impl Verifiable for Block {
         last_block: &Block,
         network_state: &NetworkState,
         reward_state: &RewardState,
+        chain_spec: &ChainSpec,
     ) -> Result<(), InvalidBlockError> {
+        if self.header.chain_id != last_block.header.chain_id {
+            let e = Err(InvalidBlockError {
+                details: InvalidBlockErrorReason::InvalidChainId,
+            });
+            info!("Invalid block: {:?}", e);
+            info!("Block that's invalid: {:?}", self);
+            info!("Last Valid Block: {:?}", &last_block);
+            return e;
+        }
+
         if !self.valid_last_hash(last_block) {
             let e = Err(InvalidBlockError {
                 details: InvalidBlockErrorReason::InvalidLastHash,
@@ -237,7 +294,7 @@ impl Verifiable for Block {
             return e;
         }
 
-        if !self.valid_txns() {
+        if !self.valid_txns(chain_spec.validator_threshold) {
             let e = Err(InvalidBlockError {
                 details: InvalidBlockErrorReason::InvalidTxns,
             });
@@ -296,12 +353,15 @@ impl Verifiable for Block {
         false
     }
 
-    fn valid_txns(&self) -> bool {
+    fn valid_txns(&self, validator_threshold: f64) -> bool {
         let mut valid_data: bool = true;
 
         self.txns.iter().for_each(|(_, txn)| {
+            if !txn.valid_chain_id(self.header.chain_id) {
+                valid_data = false
+            }
             let n_valid = txn.validators.iter().filter(|(_, &valid)| valid).count();
-            if (n_valid as f64 / txn.validators.len() as f64) < VALIDATOR_THRESHOLD {
+            if (n_valid as f64 / txn.validators.len() as f64) < validator_threshold {
                 valid_data = false
             }
         });
@@ -390,29 +450,6 @@ impl Verifiable for Block {
 
 impl Chunkable for Block {
     fn chunk(&self) -> Option<Vec<Vec<u8>>> {
-        let bytes_len = self.as_bytes().len();
-        if bytes_len > MAX_TRANSMIT_SIZE {
-            let mut n_chunks = bytes_len / MAX_TRANSMIT_SIZE;
-            if bytes_len % MAX_TRANSMIT_SIZE != 0 {
-                n_chunks += 1;
-            }
-            let mut chunks_vec = vec![];
-            let mut last_slice_end = 0;
-            (1..=bytes_len)
-                .map(|n| n * (MAX_TRANSMIT_SIZE))
-                .enumerate()
-                .for_each(|(index, slice_end)| {
-                    if index + 1 == n_chunks {
-                        chunks_vec.push(self.clone().as_bytes()[last_slice_end..].to_vec());
-                    } else {
-                        chunks_vec
-                            .push(self.clone().as_bytes()[last_slice_end..slice_end].to_vec());
-                        last_slice_end = slice_end;
-                    }
-                });
-            Some(chunks_vec)
-        } else {
-            Some(vec![self.clone().as_bytes()])
-        }
+        Some(chunk_bytes(&self.as_bytes(), MAX_CHUNK_SIZE))
     }
 }
@@ -1,10 +1,11 @@
 use crate::block::Block;
+use crate::chain_spec::ChainSpec;
 use crate::fields::GettableFields;
 use crate::header::BlockHeader;
-use crate::network::chunkable::Chunkable;
+use crate::network::chunkable::{chunk_bytes, Chunkable};
 use crate::network::command_utils::Command;
 use crate::network::message_types::MessageType;
-use crate::network::node::MAX_TRANSMIT_SIZE;
+use crate::network::node::MAX_CHUNK_SIZE;
 use crate::reward::RewardState;
 use crate::state::NetworkState;
 use crate::verifiable::Verifiable;
@@ -28,6 +29,11 @@ pub struct Blockchain {
     pub invalid: LinkedHashMap<String, Block>,
     pub updating_state: bool,
     pub state_update_cache: LinkedHashMap<u128, LinkedHashMap<u128, Vec<u8>>>,
+    pub chain_id: u64,
+    #[serde(default)]
+    pub chain_spec: ChainSpec, // The rules the blocks are validated by.
+    #[serde(default)]
+    pub genesis_hash: Option<String>, // Hash of the only genesis block accepted, if known.
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -41,6 +47,7 @@ pub enum InvalidBlockErrorReason {
     InvalidBlockReward,
     InvalidTxns,
     InvalidClaimPointers,
+    InvalidChainId,
     General,
 }
 
@@ -59,7 +66,7 @@ pub struct InvalidBlockError {
 }
 
 impl Blockchain {
-    pub fn new(path: &str) -> Blockchain {
+    pub fn new(path: &str, chain_id: u64, chain_spec: ChainSpec) -> Blockchain {
         Blockchain {
             genesis: None,
             child: None,
@@ -71,9 +78,42 @@ impl Blockchain {
             invalid: LinkedHashMap::new(),
             updating_state: false,
             state_update_cache: LinkedHashMap::new(),
+            chain_id,
+            chain_spec,
+            genesis_hash: None,
         }
     }
 
+    /// Restores the chain stored in the chain db at `path` by following it
+    /// from the genesis block. A missing or empty db gives an empty chain.
+    pub fn restore(path: &str, chain_id: u64, chain_spec: ChainSpec) -> Blockchain {
+        let mut blockchain = Blockchain::new(path, chain_id, chain_spec);
+        let db = blockchain.get_chain_db();
+        let genesis = db
+            .get_all()
+            .iter()
+            .filter_map(|key| db.get::<Block>(key))
+            .find(|block| block.header.block_height == 0);
+
+        if let Some(genesis) = genesis {
+            blockchain.chain.push_back(genesis.header.clone());
+            blockchain.genesis = Some(genesis.clone());
+            let mut last_block = genesis;
+            // Every block is stored under the hash of the block before it.
+            while let Some(block) = db.get::<Block>(&last_block.hash) {
+                if block.header.block_height != last_block.header.block_height + 1 {
+                    break;
+                }
+                blockchain.chain.push_back(block.header.clone());
+                blockchain.parent = Some(last_block);
+                last_block = block;
+            }
+            blockchain.child = Some(last_block);
+        }
+
+        blockchain
+    }
+
     pub fn check_next_block_height(&self, block: &Block) -> bool {
         if let Some(_) = self.genesis.as_ref() {
             if let Some(child) = self.child.as_ref() {
@@ -193,7 +233,9 @@ impl Blockchain {
     ) -> Result<(), InvalidBlockError> {
         if let Some(genesis_block) = &self.genesis {
             if let Some(last_block) = &self.child {
-                if let Err(e) = block.valid_block(&last_block, network_state, reward_state) {
+                if let Err(e) =
+                    block.valid_block(&last_block, network_state, reward_state, &self.chain_spec)
+                {
                     self.future_blocks
                         .insert(block.clone().header.last_hash, block.clone());
                     return Err(e);
@@ -213,7 +255,12 @@ impl Blockchain {
                     return Ok(());
                 }
             } else {
-                if let Err(e) = block.valid_block(&genesis_block, network_state, reward_state) {
+                if let Err(e) = block.valid_block(
+                    &genesis_block,
+                    network_state,
+                    reward_state,
+                    &self.chain_spec,
+                ) {
                     return Err(e);
                 } else {
                     self.child = Some(block.clone());
@@ -227,7 +274,14 @@ impl Blockchain {
         } else {
             // check that this is a valid genesis block.
             if block.header.block_height == 0 {
-                if block.valid_genesis(network_state, reward_state) {
+                if block.header.chain_id != self.chain_id {
+                    self.invalid.insert(block.hash.clone(), block.clone());
+                    return Err(InvalidBlockError {
+                        details: InvalidBlockErrorReason::InvalidChainId,
+                    });
+                }
+
+                if block.valid_genesis(network_state, reward_state, self.genesis_hash.as_deref()) {
                     self.genesis = Some(block.clone());
                     self.child = Some(block.clone());
                     self.block_cache.insert(block.hash.clone(), block.clone());
@@ -376,6 +430,9 @@ impl Blockchain {
             "invalid".to_string(),
             "updating_state".to_string(),
             "state_update_cache".to_string(),
+            "chain_id".to_string(),
+            "chain_spec".to_string(),
+            "genesis_hash".to_string(),
         ];
     }
 }
@@ -393,6 +450,7 @@ impl InvalidBlockErrorReason {
             Self::InvalidBlockReward => "invalid block reward",
             Self::InvalidTxns => "invalid txns in block",
             Self::InvalidClaimPointers => "invalid claim pointers",
+            Self::InvalidChainId => "invalid chain id",
         }
     }
 }
@@ -425,30 +483,7 @@ impl Error for InvalidBlockErrorReason {
 
 impl Chunkable for Blockchain {
     fn chunk(&self) -> Option<Vec<Vec<u8>>> {
-        let bytes_len = self.as_bytes().len();
-        if bytes_len > MAX_TRANSMIT_SIZE {
-            let mut n_chunks = bytes_len / MAX_TRANSMIT_SIZE;
-            if bytes_len % MAX_TRANSMIT_SIZE != 0 {
-                n_chunks += 1;
-            }
-            let mut chunks_vec = vec![];
-            let mut last_slice_end = 0;
-            (1..=bytes_len)
-                .map(|n| n * MAX_TRANSMIT_SIZE)
-                .enumerate()
-                .for_each(|(index, slice_end)| {
-                    if index + 1 == n_chunks {
-                        chunks_vec.push(self.clone().as_bytes()[last_slice_end..].to_vec());
-                    } else {
-                        chunks_vec
-                            .push(self.clone().as_bytes()[last_slice_end..slice_end].to_vec());
-                        last_slice_end = slice_end;
-                    }
-                });
-            Some(chunks_vec)
-        } else {
-            Some(vec![self.clone().as_bytes()])
-        }
+        Some(chunk_bytes(&self.as_bytes(), MAX_CHUNK_SIZE))
     }
 }
 
@@ -482,6 +517,9 @@ impl fmt::Display for InvalidBlockErrorReason {
             Self::InvalidClaimPointers => {
                 write!(f, "invalid claim pointers")
             }
+            Self::InvalidChainId => {
+                write!(f, "invalid chain id")
+            }
             Self::General => {
                 write!(f, "general invalid block error")
             }
@@ -519,6 +557,9 @@ impl GettableFields for Blockchain {
             "state_update_cache" => {
                 return Some(serde_json::to_string(&self.state_update_cache).unwrap())
             }
+            "chain_id" => Some(self.chain_id.to_string()),
+            "chain_spec" => Some(serde_json::to_string(&self.chain_spec).unwrap()),
+            "genesis_hash" => self.genesis_hash.clone(),
             _ => None,
         }
     }
@@ -0,0 +1,220 @@
+use crate::block::Block;
+use crate::network::wire::MAX_PAYLOAD_LEN;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+use thiserror::Error;
+
+/// First bytes of every file written by `vrrb chain export`.
+pub const CHAIN_FILE_MAGIC: &[u8; 8] = b"VRRBCHN\0";
+/// Version of the layout below. Bump this whenever the layout of the file or
+/// the encoding of the header or the blocks changes so that older nodes refuse
+/// the file instead of misreading it.
+pub const CHAIN_FILE_VERSION: u32 = 1;
+
+#[derive(Error, Debug)]
+pub enum ChainFileError {
+    #[error("error reading or writing the chain file: {0}")]
+    Io(#[from] io::Error),
+    #[error("not a chain file, it doesn't start with the chain file magic")]
+    NotAChainFile,
+    #[error("unsupported chain file version {0}, expected {}", CHAIN_FILE_VERSION)]
+    UnsupportedVersion(u32),
+    #[error("entry of {0} bytes exceeds {} bytes", MAX_PAYLOAD_LEN)]
+    EntryTooLarge(u64),
+    #[error("the chain file ends in the middle of an entry")]
+    Truncated,
+    #[error("error encoding or decoding an entry: {0}")]
+    Codec(#[from] serde_json::Error),
+}
+
+/// What a chain file holds, written before its blocks so that an import can
+/// refuse a chain for another network before applying any of it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChainFileHeader {
+    pub chain_id: u64,
+    pub genesis_hash: Option<String>,
+    // Heights of the first and last block in the file.
+    pub from: u128,
+    pub to: u128,
+    pub block_count: u64,
+}
+
+/// Writes blocks to a chain file:
+///
+/// ```text
+/// +-------+---------+------------+--------+------------+-------+-----+
+/// | magic | version | header len | header | block len  | block | ... |
+/// +-------+---------+------------+--------+------------+-------+-----+
+///  8 bytes  u32 BE     u32 BE      json      u32 BE      json
+/// ```
+///
+/// The blocks are in the order they were applied, each one prefixed with its
+/// length so that a reader can stop at the first one it can't decode.
+pub struct ChainFileWriter<W: Write> {
+    writer: W,
+}
+
+/// Reads the blocks of a chain file written by `ChainFileWriter`, one at a
+/// time so that a long chain never has to fit in memory.
+pub struct ChainFileReader<R: Read> {
+    reader: R,
+    header: ChainFileHeader,
+}
+
+impl<W: Write> ChainFileWriter<W> {
+    pub fn new(mut writer: W, header: &ChainFileHeader) -> Result<Self, ChainFileError> {
+        writer.write_all(CHAIN_FILE_MAGIC)?;
+        writer.write_all(&CHAIN_FILE_VERSION.to_be_bytes())?;
+        write_entry(&mut writer, &serde_json::to_vec(header)?)?;
+        Ok(ChainFileWriter { writer })
+    }
+
+    pub fn write_block(&mut self, block: &Block) -> Result<(), ChainFileError> {
+        write_entry(&mut self.writer, &serde_json::to_vec(block)?)
+    }
+
+    pub fn finish(mut self) -> Result<W, ChainFileError> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<R: Read> ChainFileReader<R> {
+    /// Checks the magic and the version and reads the header, before any of
+    /// the blocks.
+    pub fn new(mut reader: R) -> Result<Self, ChainFileError> {
+        let mut magic = [0u8; 8];
+        if read_exact_or_eof(&mut reader, &mut magic)? == ReadResult::Eof
+            || magic != *CHAIN_FILE_MAGIC
+        {
+            return Err(ChainFileError::NotAChainFile);
+        }
+
+        let mut version = [0u8; 4];
+        if read_exact_or_eof(&mut reader, &mut version)? == ReadResult::Eof {
+            return Err(ChainFileError::Truncated);
+        }
+        let version = u32::from_be_bytes(version);
+        if version != CHAIN_FILE_VERSION {
+            return Err(ChainFileError::UnsupportedVersion(version));
+        }
+
+        let header = match read_entry(&mut reader)? {
+            Some(entry) => serde_json::from_slice::<ChainFileHeader>(&entry)?,
+            None => return Err(ChainFileError::Truncated),
+        };
+
+        Ok(ChainFileReader { reader, header })
+    }
+
+    pub fn header(&self) -> &ChainFileHeader {
+        &self.header
+    }
+
+    /// The next block in the file, or None once all of them were read.
+    pub fn next_block(&mut self) -> Result<Option<Block>, ChainFileError> {
+        match read_entry(&mut self.reader)? {
+            Some(entry) => Ok(Some(serde_json::from_slice::<Block>(&entry)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum ReadResult {
+    Read,
+    Eof,
+}
+
+fn write_entry<W: Write>(writer: &mut W, entry: &[u8]) -> Result<(), ChainFileError> {
+    if entry.len() as u64 > MAX_PAYLOAD_LEN {
+        return Err(ChainFileError::EntryTooLarge(entry.len() as u64));
+    }
+    writer.write_all(&(entry.len() as u32).to_be_bytes())?;
+    writer.write_all(entry)?;
+    Ok(())
+}
+
+// None at the end of the file, an error if it ends within the entry.
+fn read_entry<R: Read>(reader: &mut R) -> Result<Option<Vec<u8>>, ChainFileError> {
+    let mut len = [0u8; 4];
+    if read_exact_or_eof(reader, &mut len)? == ReadResult::Eof {
+        return Ok(None);
+    }
+    // The length comes from a file someone sent, it is checked before it is
+    // allocated.
+    let len = u32::from_be_bytes(len) as u64;
+    if len > MAX_PAYLOAD_LEN {
+        return Err(ChainFileError::EntryTooLarge(len));
+    }
+
+    let mut entry = vec![0u8; len as usize];
+    if read_exact_or_eof(reader, &mut entry)? == ReadResult::Eof {
+        return Err(ChainFileError::Truncated);
+    }
+    Ok(Some(entry))
+}
+
+// Like `read_exact`, but tells a file that ends before the first byte apart
+// from one that ends part way through `buffer`.
+fn read_exact_or_eof<R: Read>(
+    reader: &mut R,
+    buffer: &mut [u8],
+) -> Result<ReadResult, ChainFileError> {
+    let mut read = 0;
+    while read < buffer.len() {
+        match reader.read(&mut buffer[read..]) {
+            Ok(0) if read == 0 => return Ok(ReadResult::Eof),
+            Ok(0) => return Err(ChainFileError::Truncated),
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(ReadResult::Read)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signer::MockSigner;
+    use crate::test_utils::genesis_block;
+
+    #[test]
+    fn test_chain_file_round_trip_and_rejects_damaged_files() {
+        let genesis = genesis_block(&MockSigner::new(1));
+        let header = ChainFileHeader {
+            chain_id: 0,
+            genesis_hash: Some(genesis.hash.clone()),
+            from: 0,
+            to: 0,
+            block_count: 1,
+        };
+
+        let mut writer = ChainFileWriter::new(Vec::new(), &header).unwrap();
+        writer.write_block(&genesis).unwrap();
+        let file = writer.finish().unwrap();
+
+        let mut reader = ChainFileReader::new(file.as_slice()).unwrap();
+        assert_eq!(*reader.header(), header);
+        assert_eq!(reader.next_block().unwrap().unwrap().hash, genesis.hash);
+        assert!(reader.next_block().unwrap().is_none());
+
+        let mut reader = ChainFileReader::new(&file[..file.len() - 1]).unwrap();
+        assert!(matches!(
+            reader.next_block(),
+            Err(ChainFileError::Truncated)
+        ));
+
+        let mut newer = file.clone();
+        newer[CHAIN_FILE_MAGIC.len() + 3] += 1;
+        assert!(matches!(
+            ChainFileReader::new(newer.as_slice()),
+            Err(ChainFileError::UnsupportedVersion(_))
+        ));
+        assert!(matches!(
+            ChainFileReader::new(&b"not a chain"[..]),
+            Err(ChainFileError::NotAChainFile)
+        ));
+    }
+}
@@ -0,0 +1,133 @@
+use serde::{Deserialize, Serialize};
+use sha256::digest_bytes;
+use std::fs;
+use thiserror::Error;
+
+/// Name of the chain spec file in the data dir.
+pub const CHAIN_SPEC_FILE: &str = "chain_spec.toml";
+pub const DEFAULT_VALIDATOR_THRESHOLD: f64 = 0.60;
+pub const DEFAULT_MIN_BLOCK_TIME: u64 = 1;
+pub const DEFAULT_CLAIM_ABANDON_TIMEOUT: u64 = 30;
+
+#[derive(Error, Debug)]
+pub enum ChainSpecError {
+    #[error("Error reading the chain spec file: {0}")]
+    ReadSpecError(#[from] std::io::Error),
+    #[error("Error parsing the chain spec file: {0}")]
+    ParseSpecError(#[from] toml::de::Error),
+    #[error("Invalid chain spec: {0}")]
+    InvalidSpec(String),
+}
+
+/// The consensus rules of a chain. Unlike the `NodeConfig`, which every node
+/// tunes for itself, every node on a network has to run the same chain spec
+/// or it forks off. A chain spec other than the testnet's is committed to in
+/// the identify handshake, see `NetworkConfig::protocol_id`, so nodes that
+/// disagree on it never connect:
+///
+/// ```toml
+/// validator_threshold = 0.6
+/// min_block_time_secs = 1
+/// claim_abandon_timeout_secs = 30
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChainSpec {
+    // Share of the validators that has to accept a txn to confirm it, or an
+    // abandoned claim to drop it from the claim map.
+    pub validator_threshold: f64,
+    // Least time between a block and the block mined on top of it.
+    pub min_block_time_secs: u64,
+    // Time the claim that is due to mine the next block has to mine it before
+    // the other miners vote to abandon it.
+    pub claim_abandon_timeout_secs: u64,
+}
+
+impl ChainSpec {
+    pub fn testnet() -> ChainSpec {
+        ChainSpec {
+            validator_threshold: DEFAULT_VALIDATOR_THRESHOLD,
+            min_block_time_secs: DEFAULT_MIN_BLOCK_TIME,
+            claim_abandon_timeout_secs: DEFAULT_CLAIM_ABANDON_TIMEOUT,
+        }
+    }
+
+    pub fn load(path: &str) -> Result<ChainSpec, ChainSpecError> {
+        let content = fs::read_to_string(path)?;
+        let spec = toml::from_str::<ChainSpec>(&content)?;
+        spec.validate()?;
+        Ok(spec)
+    }
+
+    /// Loads the chain spec at `path`, falling back to the testnet's if there
+    /// is no file at that path.
+    pub fn restore(path: &str) -> Result<ChainSpec, ChainSpecError> {
+        match ChainSpec::load(path) {
+            Err(ChainSpecError::ReadSpecError(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+                Ok(ChainSpec::testnet())
+            }
+            result => result,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ChainSpecError> {
+        // Below a half, a txn and a txn spending the same funds could both be
+        // confirmed. At one, a single offline validator stops every txn.
+        if !(0.5..1.0).contains(&self.validator_threshold) {
+            return Err(ChainSpecError::InvalidSpec(format!(
+                "validator_threshold is {}, it has to be at least 0.5 and below 1",
+                self.validator_threshold
+            )));
+        }
+        if self.min_block_time_secs == 0 {
+            return Err(ChainSpecError::InvalidSpec(
+                "min_block_time_secs has to be at least 1".to_string(),
+            ));
+        }
+        if self.claim_abandon_timeout_secs <= self.min_block_time_secs {
+            return Err(ChainSpecError::InvalidSpec(format!(
+                "claim_abandon_timeout_secs is {}, it has to be longer than min_block_time_secs",
+                self.claim_abandon_timeout_secs
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub fn hash(&self) -> String {
+        digest_bytes(serde_json::to_string(self).unwrap().as_bytes())
+    }
+}
+
+impl Default for ChainSpec {
+    fn default() -> ChainSpec {
+        ChainSpec::testnet()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chain_spec_defaults_to_the_testnet_and_rejects_unsafe_rules() {
+        assert_eq!(
+            toml::from_str::<ChainSpec>("").unwrap(),
+            ChainSpec::testnet()
+        );
+        assert!(ChainSpec::testnet().validate().is_ok());
+        assert!(toml::from_str::<ChainSpec>("validator_treshold = 0.7").is_err());
+
+        let spec = toml::from_str::<ChainSpec>("validator_threshold = 0.4").unwrap();
+        assert!(matches!(
+            spec.validate(),
+            Err(ChainSpecError::InvalidSpec(_))
+        ));
+        let spec = ChainSpec {
+            claim_abandon_timeout_secs: 1,
+            ..ChainSpec::testnet()
+        };
+        assert!(spec.validate().is_err());
+        assert_ne!(spec.hash(), ChainSpec::testnet().hash());
+    }
+}
@@ -0,0 +1,539 @@
+//! The `vrrb chain` and `vrrb state` commands, which work on the files of a
+//! data dir without starting the node. They return what they found and leave
+//! printing it to the command line.
+use crate::block::Block;
+use crate::blockchain::Blockchain;
+use crate::chain_file::{ChainFileError, ChainFileHeader, ChainFileReader, ChainFileWriter};
+use crate::chain_spec::{ChainSpec, ChainSpecError};
+use crate::chain_verifier::{self, Verification};
+use crate::cli::DataDir;
+use crate::genesis::{GenesisSpec, GenesisSpecError};
+use crate::network::network_config::{NetworkConfig, NetworkConfigError};
+use crate::reward::RewardState;
+use crate::state::NetworkState;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ChainToolError {
+    #[error(transparent)]
+    NetworkConfig(#[from] NetworkConfigError),
+    #[error(transparent)]
+    ChainSpec(#[from] ChainSpecError),
+    #[error(transparent)]
+    GenesisSpec(#[from] GenesisSpecError),
+    #[error(transparent)]
+    ChainFile(#[from] ChainFileError),
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    #[error("There is no chain in {0}")]
+    NoChain(String),
+    #[error("There is no network state in {0}")]
+    NoState(String),
+    #[error(
+        "The chain in {chain_db} has blocks 0 to {last_height}, there is no block {from} to {to}"
+    )]
+    OutOfRange {
+        chain_db: String,
+        last_height: u128,
+        from: u128,
+        to: u128,
+    },
+    #[error("Block {height} is missing from {chain_db}")]
+    MissingBlock { height: u128, chain_db: String },
+    #[error("{file} holds blocks of chain {found}, not {expected}")]
+    WrongChain {
+        file: String,
+        found: u64,
+        expected: u64,
+    },
+    #[error("{file} starts from genesis block {found}, not {expected}")]
+    WrongGenesis {
+        file: String,
+        found: String,
+        expected: String,
+    },
+    #[error("The chain in {chain_db} doesn't match the state in {state_db}, run the node to update the state first")]
+    StateMismatch { chain_db: String, state_db: String },
+    #[error("Block {hash} at height {height} forks from the chain in {chain_db}")]
+    Fork {
+        hash: String,
+        height: u128,
+        chain_db: String,
+    },
+    #[error("The next block in {file} is at height {height}, the chain in {chain_db} needs block {next_height} first")]
+    Gap {
+        file: String,
+        height: u128,
+        chain_db: String,
+        next_height: u128,
+    },
+    #[error("Block {hash} at height {height} is invalid: {reason}. Imported {imported} blocks before it")]
+    InvalidBlock {
+        hash: String,
+        height: u128,
+        reason: String,
+        imported: u64,
+    },
+}
+
+/// Writes the blocks of the chain stored in `data_dir` from height `from` to
+/// height `to`, the whole chain by default, to `path` for `vrrb chain import`.
+pub fn export_chain(
+    data_dir: &DataDir,
+    path: &Path,
+    from: Option<u128>,
+    to: Option<u128>,
+) -> Result<ChainFileHeader, ChainToolError> {
+    let network_config = NetworkConfig::restore(&data_dir.network_config())?;
+    let chain_spec = ChainSpec::restore(&data_dir.chain_spec())?;
+    let blockchain = Blockchain::restore(&data_dir.chain_db(), network_config.chain_id, chain_spec);
+    let last_height = match &blockchain.child {
+        Some(block) => block.header.block_height,
+        None => return Err(ChainToolError::NoChain(data_dir.chain_db())),
+    };
+    let from = from.unwrap_or(0);
+    let to = to.unwrap_or(last_height);
+    if from > to || to > last_height {
+        return Err(ChainToolError::OutOfRange {
+            chain_db: data_dir.chain_db(),
+            last_height,
+            from,
+            to,
+        });
+    }
+
+    let header = ChainFileHeader {
+        chain_id: network_config.chain_id,
+        genesis_hash: blockchain.genesis.as_ref().map(|block| block.hash.clone()),
+        from,
+        to,
+        block_count: (to - from + 1) as u64,
+    };
+    let mut writer = ChainFileWriter::new(BufWriter::new(File::create(path)?), &header)?;
+    let chain_db = blockchain.get_chain_db();
+    for block_header in blockchain
+        .chain
+        .iter()
+        .filter(|block_header| (from..=to).contains(&block_header.block_height))
+    {
+        match chain_db.get::<Block>(&block_header.last_hash) {
+            Some(block) => writer.write_block(&block)?,
+            None => {
+                return Err(ChainToolError::MissingBlock {
+                    height: block_header.block_height,
+                    chain_db: data_dir.chain_db(),
+                })
+            }
+        }
+    }
+    writer.finish()?;
+
+    Ok(header)
+}
+
+/// What happened to a block read from a chain file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportStep {
+    // The block at this height was validated and applied.
+    Imported(u128),
+    // The chain already had the block at this height.
+    Skipped(u128),
+}
+
+/// Applies the blocks of a chain file, exported with `vrrb chain export`, on
+/// top of the chain and state stored in a data dir. Every block is validated
+/// and applied like a block received from a peer, one block per call to
+/// `import_next`, and the import stops at the first invalid one.
+pub struct ChainImport {
+    file: String,
+    chain_db: String,
+    state_db: String,
+    reader: ChainFileReader<BufReader<File>>,
+    blockchain: Blockchain,
+    network_state: NetworkState,
+    genesis: Option<(GenesisSpec, Block)>,
+    read: u64,
+    imported: u64,
+}
+
+impl ChainImport {
+    /// Opens the chain file at `path` and checks that it holds blocks of the
+    /// chain stored in `data_dir`, before any of them is applied.
+    pub fn open(data_dir: &DataDir, path: &Path) -> Result<ChainImport, ChainToolError> {
+        data_dir.create()?;
+        let file = path.display().to_string();
+        let network_config = NetworkConfig::restore(&data_dir.network_config())?;
+        let chain_spec = ChainSpec::restore(&data_dir.chain_spec())?;
+        let chain_id = network_config.chain_id;
+        let genesis = GenesisSpec::restore(&data_dir.genesis_spec())?.map(|spec| {
+            let block = spec.block();
+            (spec, block)
+        });
+        let genesis_hash = genesis
+            .as_ref()
+            .map(|(_, block)| block.hash.clone())
+            .or_else(|| network_config.genesis_hash.clone());
+
+        let reader = ChainFileReader::new(BufReader::new(File::open(path)?))?;
+        let header = reader.header();
+        if header.chain_id != chain_id {
+            return Err(ChainToolError::WrongChain {
+                file,
+                found: header.chain_id,
+                expected: chain_id,
+            });
+        }
+        if let (Some(expected), Some(found)) = (&genesis_hash, &header.genesis_hash) {
+            if expected != found {
+                return Err(ChainToolError::WrongGenesis {
+                    file,
+                    found: found.clone(),
+                    expected: expected.clone(),
+                });
+            }
+        }
+
+        let network_state = NetworkState::restore(&data_dir.state_db());
+        let mut blockchain = Blockchain::restore(&data_dir.chain_db(), chain_id, chain_spec);
+        blockchain.genesis_hash = genesis_hash;
+        if let Some(last_block) = &blockchain.child {
+            if network_state.state_hash != Some(last_block.hash.clone()) {
+                return Err(ChainToolError::StateMismatch {
+                    chain_db: data_dir.chain_db(),
+                    state_db: data_dir.state_db(),
+                });
+            }
+        }
+
+        Ok(ChainImport {
+            file,
+            chain_db: data_dir.chain_db(),
+            state_db: data_dir.state_db(),
+            reader,
+            blockchain,
+            network_state,
+            genesis,
+            read: 0,
+            imported: 0,
+        })
+    }
+
+    pub fn header(&self) -> &ChainFileHeader {
+        self.reader.header()
+    }
+
+    /// How many blocks were read from the file so far.
+    pub fn read(&self) -> u64 {
+        self.read
+    }
+
+    /// How many of the blocks read were applied.
+    pub fn imported(&self) -> u64 {
+        self.imported
+    }
+
+    /// The height of the chain, None while it has no blocks.
+    pub fn height(&self) -> Option<u128> {
+        self.blockchain
+            .child
+            .as_ref()
+            .map(|last_block| last_block.header.block_height)
+    }
+
+    /// Reads the next block of the file and applies it, None once all of them
+    /// were read.
+    pub fn import_next(&mut self) -> Result<Option<ImportStep>, ChainToolError> {
+        let block = match self.reader.next_block()? {
+            Some(block) => block,
+            None => return Ok(None),
+        };
+        self.read += 1;
+        let height = block.header.block_height;
+        let next_height = self.height().map_or(0, |height| height + 1);
+        if height < next_height {
+            // Blocks the chain already has are skipped, as long as they are
+            // the same blocks.
+            let stored = self.blockchain.get_block(&block.header.last_hash);
+            if stored.map(|stored| stored.hash) != Some(block.hash.clone()) {
+                return Err(ChainToolError::Fork {
+                    hash: block.hash,
+                    height,
+                    chain_db: self.chain_db.clone(),
+                });
+            }
+            return Ok(Some(ImportStep::Skipped(height)));
+        }
+        if height > next_height {
+            return Err(ChainToolError::Gap {
+                file: self.file.clone(),
+                height,
+                chain_db: self.chain_db.clone(),
+                next_height,
+            });
+        }
+
+        let reward_state = self.network_state.reward_state;
+        if let Err(e) = self
+            .blockchain
+            .process_block(&self.network_state, &reward_state, &block)
+        {
+            return Err(ChainToolError::InvalidBlock {
+                hash: block.hash,
+                height,
+                reason: e.to_string(),
+                imported: self.imported,
+            });
+        }
+        match &self.genesis {
+            Some((spec, genesis)) if genesis.hash == block.hash => {
+                self.network_state = spec.network_state(&self.state_db, genesis)
+            }
+            _ => self.network_state.dump(&block),
+        }
+        self.imported += 1;
+
+        Ok(Some(ImportStep::Imported(height)))
+    }
+}
+
+/// Re-verifies every block of the chain stored in `data_dir` from its genesis
+/// block, and compares the state they add up to with the stored network state
+/// and ledger.
+pub fn verify_stored_chain(data_dir: &DataDir) -> Result<Verification, ChainToolError> {
+    let state_path = data_dir.state_db();
+    if !Path::new(&state_path).exists() {
+        return Err(ChainToolError::NoState(state_path));
+    }
+    let network_config = NetworkConfig::restore(&data_dir.network_config())?;
+    let chain_spec = ChainSpec::restore(&data_dir.chain_spec())?;
+    let genesis_spec = GenesisSpec::restore(&data_dir.genesis_spec())?;
+    let mut blockchain =
+        Blockchain::restore(&data_dir.chain_db(), network_config.chain_id, chain_spec);
+    blockchain.genesis_hash = genesis_spec
+        .as_ref()
+        .map(|spec| spec.block().hash)
+        .or_else(|| network_config.genesis_hash.clone());
+    if blockchain.genesis.is_none() {
+        return Err(ChainToolError::NoChain(data_dir.chain_db()));
+    }
+
+    let stored = NetworkState::restore(&state_path);
+    let replay_path = std::env::temp_dir()
+        .join(format!("vrrb_verify_{}.db", std::process::id()))
+        .to_string_lossy()
+        .into_owned();
+    let verification =
+        chain_verifier::verify_chain(&blockchain, genesis_spec.as_ref(), &stored, &replay_path);
+    let _ = std::fs::remove_file(&replay_path);
+
+    Ok(verification)
+}
+
+/// An account of the ledger, as `vrrb state inspect` shows it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountReport {
+    pub address: String,
+    pub credits: u128,
+    pub debits: u128,
+    pub balance: u128,
+    pub next_nonce: u128,
+}
+
+/// The network state stored in a data dir: its hash, the reward state, the
+/// last block and the accounts of the ledger.
+#[derive(Debug, Clone)]
+pub struct StateReport {
+    pub path: String,
+    pub state_hash: Option<String>,
+    pub reward_state: RewardState,
+    // Hash and height of the last block applied to the state.
+    pub last_block: Option<(String, u128)>,
+    pub claims: usize,
+    pub accounts: Vec<AccountReport>,
+}
+
+/// Reads the network state stored in `data_dir`, with every account of the
+/// ledger or only the ones of `addresses`.
+pub fn inspect_state(
+    data_dir: &DataDir,
+    addresses: Vec<String>,
+) -> Result<StateReport, ChainToolError> {
+    let path = data_dir.state_db();
+    if !Path::new(&path).exists() {
+        return Err(ChainToolError::NoState(path));
+    }
+
+    let network_state = NetworkState::restore(&path);
+    let addresses = if addresses.is_empty() {
+        let credits = network_state.get_credits();
+        let debits = network_state.get_debits();
+        credits
+            .keys()
+            .chain(
+                debits
+                    .keys()
+                    .filter(|address| !credits.contains_key(*address)),
+            )
+            .cloned()
+            .collect::<Vec<_>>()
+    } else {
+        addresses
+    };
+    let accounts = addresses
+        .into_iter()
+        .map(|address| AccountReport {
+            credits: network_state.get_account_credits(&address),
+            debits: network_state.get_account_debits(&address),
+            balance: network_state.get_balance(&address),
+            next_nonce: network_state.get_account_txn_nonce(&address),
+            address,
+        })
+        .collect();
+
+    Ok(StateReport {
+        state_hash: network_state.state_hash.clone(),
+        reward_state: network_state.get_reward_state(),
+        last_block: network_state
+            .get_last_block()
+            .map(|block| (block.hash, block.header.block_height)),
+        claims: network_state.get_claims().len(),
+        accounts,
+        path,
+    })
+}
+
+impl fmt::Display for StateReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "State db: {}", self.path)?;
+        writeln!(
+            f,
+            "State hash: {}",
+            self.state_hash.as_deref().unwrap_or("none")
+        )?;
+        writeln!(f, "Reward state: {:?}", self.reward_state)?;
+        match &self.last_block {
+            Some((hash, height)) => writeln!(f, "Last block: {} at height {}", hash, height)?,
+            None => writeln!(f, "Last block: none")?,
+        }
+        writeln!(f, "Claims: {}", self.claims)?;
+        write!(f, "Accounts: {}", self.accounts.len())?;
+        for account in self.accounts.iter() {
+            write!(
+                f,
+                "\n    {} credits: {}, debits: {}, balance: {}, next nonce: {}",
+                account.address,
+                account.credits,
+                account.debits,
+                account.balance,
+                account.next_nonce
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::claim::Claim;
+    use crate::network::network_config::TESTNET_CHAIN_ID;
+    use crate::signer::{MockSigner, Signer};
+    use crate::test_utils::{TempDb, TempDir};
+
+    // A genesis block of the chain a data dir without configs is on.
+    fn testnet_genesis() -> Block {
+        let signer = MockSigner::new(1);
+        let claim = Claim::new(signer.pubkey(), "address".to_string(), 0);
+        Block::genesis(&RewardState::start(), claim, &signer, TESTNET_CHAIN_ID).unwrap()
+    }
+
+    // A data dir holding a chain of only `genesis`, and the state it leads to.
+    fn data_dir_with_genesis(dir: &TempDir, genesis: &Block) -> DataDir {
+        let data_dir = DataDir::new(dir.path().to_path_buf());
+        data_dir.create().unwrap();
+        let mut blockchain =
+            Blockchain::new(&data_dir.chain_db(), TESTNET_CHAIN_ID, ChainSpec::testnet());
+        let mut network_state = NetworkState::restore(&data_dir.state_db());
+        let reward_state = network_state.reward_state;
+        blockchain
+            .process_block(&network_state, &reward_state, genesis)
+            .unwrap();
+        network_state.dump(genesis);
+        data_dir
+    }
+
+    #[test]
+    fn test_exported_chain_imports_into_another_data_dir() {
+        let genesis = testnet_genesis();
+        let (exporting_dir, importing_dir) = (TempDir::new("export"), TempDir::new("import"));
+        let exporting = data_dir_with_genesis(&exporting_dir, &genesis);
+        let file = TempDb::new("chain_file");
+        let file = Path::new(file.path());
+
+        assert!(matches!(
+            export_chain(&exporting, file, Some(0), Some(1)),
+            Err(ChainToolError::OutOfRange { last_height: 0, .. })
+        ));
+        let header = export_chain(&exporting, file, None, None).unwrap();
+        assert_eq!((header.from, header.to, header.block_count), (0, 0, 1));
+        assert_eq!(header.genesis_hash, Some(genesis.hash.clone()));
+
+        let importing = DataDir::new(importing_dir.path().to_path_buf());
+        let mut import = ChainImport::open(&importing, file).unwrap();
+        assert_eq!(*import.header(), header);
+        assert_eq!(import.import_next().unwrap(), Some(ImportStep::Imported(0)));
+        assert_eq!(import.import_next().unwrap(), None);
+        assert_eq!((import.read(), import.imported()), (1, 1));
+        assert_eq!(import.height(), Some(0));
+
+        // Importing the same file again skips the blocks the chain has.
+        let mut import = ChainImport::open(&importing, file).unwrap();
+        assert_eq!(import.import_next().unwrap(), Some(ImportStep::Skipped(0)));
+        assert_eq!(import.imported(), 0);
+
+        let verification = verify_stored_chain(&importing).unwrap();
+        assert_eq!(verification.blocks, 1);
+        assert_eq!(verification.divergence, None);
+        let report = inspect_state(&importing, vec!["nobody".to_string()]).unwrap();
+        assert_eq!(report.state_hash, Some(genesis.hash.clone()));
+        assert_eq!(report.claims, 1);
+        assert_eq!(report.accounts[0].balance, 0);
+    }
+
+    #[test]
+    fn test_import_refuses_a_chain_file_of_another_chain() {
+        let genesis = testnet_genesis();
+        let dir = TempDir::new("import");
+        let data_dir = DataDir::new(dir.path().to_path_buf());
+        let file = TempDb::new("chain_file");
+        let header = ChainFileHeader {
+            chain_id: 7,
+            genesis_hash: Some(genesis.hash.clone()),
+            from: 0,
+            to: 0,
+            block_count: 1,
+        };
+        let mut writer =
+            ChainFileWriter::new(BufWriter::new(File::create(file.path()).unwrap()), &header)
+                .unwrap();
+        writer.write_block(&genesis).unwrap();
+        writer.finish().unwrap();
+
+        assert!(matches!(
+            ChainImport::open(&data_dir, Path::new(file.path())),
+            Err(ChainToolError::WrongChain {
+                found: 7,
+                expected: TESTNET_CHAIN_ID,
+                ..
+            })
+        ));
+        assert!(matches!(
+            verify_stored_chain(&data_dir),
+            Err(ChainToolError::NoState(_))
+        ));
+    }
+}
@@ -0,0 +1,405 @@
+use crate::block::Block;
+use crate::blockchain::Blockchain;
+use crate::genesis::GenesisSpec;
+use crate::state::NetworkState;
+use crate::verifiable::Verifiable;
+use ritelinked::LinkedHashMap;
+use serde::Serialize;
+use std::fmt;
+
+/// The check a block of the stored chain failed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlockCheck {
+    Missing,
+    Genesis,
+    ChainId,
+    BlockHeight,
+    Signature,
+    LastHash,
+    BlockNonce,
+    ClaimPointer,
+    Claim,
+    BlockReward,
+    Txns,
+    StateHash,
+}
+
+/// The first place the stored chain and state disagree with a replay of the
+/// chain from genesis.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Divergence {
+    // A block fails a check against the state replayed up to the block
+    // before it.
+    Block {
+        height: u128,
+        hash: String,
+        check: BlockCheck,
+    },
+    // Every block is valid, but the stored state isn't the state they add up
+    // to. Each line of the diff is one entry that differs.
+    State {
+        field: String,
+        diff: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Verification {
+    // Blocks that passed every check.
+    pub blocks: u128,
+    pub divergence: Option<Divergence>,
+}
+
+/// Walks the chain db of `blockchain` from its genesis block up to the highest
+/// block stored, checks every block against the
+/// state replayed from the blocks before it, and compares the state the whole
+/// chain adds up to with `stored`, the network state and ledger of the node.
+/// The replayed ledger is kept in a db at `replay_path`, which is overwritten.
+///
+/// A genesis block built from `genesis_spec` isn't signed, its state comes
+/// from the spec. Claims dropped as abandoned are dropped from the replayed
+/// claims before the block that records them. Slashed claims aren't recorded
+/// in blocks, so their `eligible` flag shows up in the claims diff.
+pub fn verify_chain(
+    blockchain: &Blockchain,
+    genesis_spec: Option<&GenesisSpec>,
+    stored: &NetworkState,
+    replay_path: &str,
+) -> Verification {
+    let _ = std::fs::remove_file(replay_path);
+    let chain_db = blockchain.get_chain_db();
+    let mut replayed = NetworkState::restore(replay_path);
+    let mut last_block: Option<Block> = None;
+    let mut blocks = 0;
+
+    // `Blockchain::restore` stops at the first block it can't find, so the
+    // blocks stored past a gap are only found by looking at all of them.
+    let stored_blocks = chain_db
+        .get_all()
+        .iter()
+        .filter_map(|key| chain_db.get::<Block>(key))
+        .collect::<Vec<_>>();
+    let tip = stored_blocks
+        .iter()
+        .map(|block| block.header.block_height)
+        .max();
+    let mut next = stored_blocks
+        .into_iter()
+        .find(|block| block.header.block_height == 0);
+
+    for height in 0..tip.map_or(0, |tip| tip + 1) {
+        let block = match next.take() {
+            Some(block) if block.header.block_height == height => block,
+            _ => {
+                return Verification {
+                    blocks,
+                    divergence: Some(Divergence::Block {
+                        height,
+                        hash: String::new(),
+                        check: BlockCheck::Missing,
+                    }),
+                }
+            }
+        };
+        // Every block is stored under the hash of the block before it.
+        next = chain_db.get::<Block>(&block.hash);
+
+        let checked = match &last_block {
+            None => check_genesis(blockchain, genesis_spec, &block, &mut replayed, replay_path),
+            Some(last_block) => check_block(blockchain, last_block, &block, &mut replayed),
+        };
+        if let Err(check) = checked {
+            return Verification {
+                blocks,
+                divergence: Some(Divergence::Block {
+                    height: block.header.block_height,
+                    hash: block.hash,
+                    check,
+                }),
+            };
+        }
+
+        blocks += 1;
+        last_block = Some(block);
+    }
+
+    Verification {
+        blocks,
+        divergence: compare_states(stored, &replayed),
+    }
+}
+
+fn check_genesis(
+    blockchain: &Blockchain,
+    genesis_spec: Option<&GenesisSpec>,
+    block: &Block,
+    replayed: &mut NetworkState,
+    replay_path: &str,
+) -> Result<(), BlockCheck> {
+    if block.header.chain_id != blockchain.chain_id {
+        return Err(BlockCheck::ChainId);
+    }
+    let reward_state = replayed.reward_state;
+    if !block.valid_genesis(replayed, &reward_state, blockchain.genesis_hash.as_deref()) {
+        return Err(BlockCheck::Genesis);
+    }
+
+    match genesis_spec {
+        Some(spec) if spec.block().hash == block.hash => {
+            *replayed = spec.network_state(replay_path, block);
+        }
+        _ => {
+            if !block.valid_block_signature() {
+                return Err(BlockCheck::Signature);
+            }
+            replayed.dump(block);
+        }
+    }
+
+    Ok(())
+}
+
+// The checks of `Block::valid_block`, one at a time so that the report names
+// the one that failed, plus the ones a node only makes when mining.
+fn check_block(
+    blockchain: &Blockchain,
+    last_block: &Block,
+    block: &Block,
+    replayed: &mut NetworkState,
+) -> Result<(), BlockCheck> {
+    if let Some(claim) = &block.abandoned_claim {
+        replayed.abandoned_claim(claim.hash.clone());
+    }
+
+    let reward_state = replayed.reward_state;
+    let promised_reward = &last_block.header.next_block_reward;
+    let checks = [
+        (
+            block.header.chain_id == last_block.header.chain_id,
+            BlockCheck::ChainId,
+        ),
+        (
+            block.header.block_height == last_block.header.block_height + 1,
+            BlockCheck::BlockHeight,
+        ),
+        (block.valid_block_signature(), BlockCheck::Signature),
+        (block.valid_last_hash(last_block), BlockCheck::LastHash),
+        (block.valid_block_nonce(last_block), BlockCheck::BlockNonce),
+        (
+            block.valid_claim_pointer(replayed),
+            BlockCheck::ClaimPointer,
+        ),
+        (block.valid_block_claim(replayed), BlockCheck::Claim),
+        (
+            block.valid_block_reward(&reward_state)
+                && block.valid_next_block_reward(&reward_state)
+                && block.header.block_reward.category == promised_reward.category
+                && block.header.block_reward.amount == promised_reward.amount,
+            BlockCheck::BlockReward,
+        ),
+        (
+            block.valid_txns(blockchain.chain_spec.validator_threshold),
+            BlockCheck::Txns,
+        ),
+        (block.valid_state_hash(replayed), BlockCheck::StateHash),
+    ];
+    if let Some((_, check)) = checks.iter().find(|(valid, _)| !valid) {
+        return Err(*check);
+    }
+
+    replayed.dump(block);
+    Ok(())
+}
+
+fn compare_states(stored: &NetworkState, replayed: &NetworkState) -> Option<Divergence> {
+    let hashes = [
+        ("state hash", &stored.state_hash, &replayed.state_hash),
+        ("credits hash", &stored.credits, &replayed.credits),
+        ("debits hash", &stored.debits, &replayed.debits),
+    ];
+    for (field, stored, replayed) in hashes.iter() {
+        if stored != replayed {
+            return Some(Divergence::State {
+                field: field.to_string(),
+                diff: vec![format!(
+                    "stored {}, replayed {}",
+                    stored.as_deref().unwrap_or("none"),
+                    replayed.as_deref().unwrap_or("none")
+                )],
+            });
+        }
+    }
+
+    let stored_reward_state = stored.get_reward_state();
+    if stored_reward_state != replayed.reward_state {
+        return Some(Divergence::State {
+            field: "reward state".to_string(),
+            diff: vec![
+                format!("stored {:?}", stored_reward_state),
+                format!("replayed {:?}", replayed.reward_state),
+            ],
+        });
+    }
+
+    let stored_ledger = stored.db_to_ledger();
+    let replayed_ledger = replayed.db_to_ledger();
+    let diffs = [
+        (
+            "credits",
+            diff_maps(&stored_ledger.credits, &replayed_ledger.credits),
+        ),
+        (
+            "debits",
+            diff_maps(&stored_ledger.debits, &replayed_ledger.debits),
+        ),
+        (
+            "txn nonces",
+            diff_maps(&stored_ledger.nonces, &replayed_ledger.nonces),
+        ),
+        (
+            "claims",
+            diff_maps(&stored_ledger.claims, &replayed_ledger.claims),
+        ),
+    ];
+    diffs
+        .iter()
+        .find(|(_, diff)| !diff.is_empty())
+        .map(|(field, diff)| Divergence::State {
+            field: field.to_string(),
+            diff: diff.clone(),
+        })
+}
+
+// One line for every key with another value in `stored` than in `replayed`.
+fn diff_maps<V: Serialize>(
+    stored: &LinkedHashMap<String, V>,
+    replayed: &LinkedHashMap<String, V>,
+) -> Vec<String> {
+    let to_string = |value: Option<&V>| match value {
+        Some(value) => serde_json::to_string(value).unwrap(),
+        None => "none".to_string(),
+    };
+
+    stored
+        .keys()
+        .chain(replayed.keys().filter(|key| !stored.contains_key(*key)))
+        .filter_map(|key| {
+            let stored = to_string(stored.get(key));
+            let replayed = to_string(replayed.get(key));
+            if stored == replayed {
+                None
+            } else {
+                Some(format!("{}: stored {}, replayed {}", key, stored, replayed))
+            }
+        })
+        .collect()
+}
+
+impl fmt::Display for BlockCheck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Missing => write!(f, "missing from the chain db"),
+            Self::Genesis => write!(f, "invalid genesis block"),
+            Self::ChainId => write!(f, "invalid chain id"),
+            Self::BlockHeight => write!(f, "invalid block height"),
+            Self::Signature => write!(f, "invalid signature"),
+            Self::LastHash => write!(f, "invalid last hash"),
+            Self::BlockNonce => write!(f, "invalid block nonce"),
+            Self::ClaimPointer => write!(f, "invalid claim pointer"),
+            Self::Claim => write!(f, "invalid claim"),
+            Self::BlockReward => write!(f, "invalid block reward"),
+            Self::Txns => write!(f, "invalid txns in block"),
+            Self::StateHash => write!(f, "invalid state hash"),
+        }
+    }
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Block {
+                height,
+                hash,
+                check,
+            } if hash.is_empty() => write!(f, "Block {}: {}", height, check),
+            Self::Block {
+                height,
+                hash,
+                check,
+            } => write!(f, "Block {} {}: {}", height, hash, check),
+            Self::State { field, diff } => {
+                write!(f, "The stored {} differs from the replayed one", field)?;
+                for line in diff {
+                    write!(f, "\n    {}", line)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain_spec::ChainSpec;
+    use crate::signer::MockSigner;
+    use crate::test_utils::{genesis_block, TempDb};
+
+    #[test]
+    fn test_verify_chain_reports_the_first_divergence() {
+        let (chain_db, state_db, replay_db) = (
+            TempDb::new("verify_chain"),
+            TempDb::new("verify_state"),
+            TempDb::new("verify_replay"),
+        );
+        let (chain_path, replay_path) = (chain_db.path(), replay_db.path());
+        let genesis = genesis_block(&MockSigner::new(1));
+
+        let mut blockchain = Blockchain::new(chain_path, 0, ChainSpec::testnet());
+        let mut stored = NetworkState::restore(state_db.path());
+        let reward_state = stored.reward_state;
+        blockchain
+            .process_block(&stored, &reward_state, &genesis)
+            .unwrap();
+        stored.dump(&genesis);
+        let blockchain = Blockchain::restore(chain_path, 0, ChainSpec::testnet());
+
+        let verification = verify_chain(&blockchain, None, &stored, replay_path);
+        assert_eq!(verification.blocks, 1);
+        assert_eq!(verification.divergence, None);
+
+        // A ledger changed behind the chain's back is caught.
+        let mut ledger = stored.db_to_ledger();
+        ledger.credits.insert("address".to_string(), 1);
+        stored.update_ledger(ledger, stored.reward_state);
+        let verification = verify_chain(&blockchain, None, &stored, replay_path);
+        match verification.divergence {
+            Some(Divergence::State { field, diff }) => {
+                assert_eq!(field, "credits");
+                assert_eq!(diff.len(), 1);
+                assert!(diff[0].starts_with("address: stored 1, replayed "));
+            }
+            divergence => panic!("expected the credits to diverge: {:?}", divergence),
+        }
+
+        // A block stored past a gap is reported missing at the gap, even
+        // though the restored chain ends before it.
+        let mut orphan = genesis.clone();
+        orphan.header.block_height = 2;
+        let mut chain_db = blockchain.get_chain_db();
+        chain_db.set("unknown parent", &orphan).unwrap();
+        chain_db.dump().unwrap();
+        let blockchain = Blockchain::restore(chain_path, 0, ChainSpec::testnet());
+        assert_eq!(blockchain.chain.len(), 1);
+        let verification = verify_chain(&blockchain, None, &stored, replay_path);
+        assert_eq!(verification.blocks, 1);
+        assert_eq!(
+            verification.divergence,
+            Some(Divergence::Block {
+                height: 1,
+                hash: String::new(),
+                check: BlockCheck::Missing,
+            })
+        );
+    }
+}
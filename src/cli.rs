@@ -0,0 +1,380 @@
+//! The command line of the node binary. Everything a node keeps lives in one
+//! data dir, under fixed file names so that a node started again from the
+//! same data dir finds its keystore, chain and state:
+//!
+//! ```text
+//! vrrb node run --data-dir ./data/vrrb --listen /ip4/0.0.0.0/tcp/9292 \
+//!     --bootstrap /ip4/10.0.0.2/tcp/9292 --node-type full --log-level debug
+//! vrrb node run --no-dashboard
+//! vrrb wallet balance
+//! vrrb wallet send 1 tvrrb1... 100
+//! vrrb state inspect
+//! vrrb chain export chain.vrrb --from 100 --to 200
+//! vrrb chain import chain.vrrb
+//! vrrb chain verify
+//! ```
+//!
+//! Options of `node run` left out on the command line are read from the node
+//! config file, `vrrb.toml` in the data dir, see `config::NodeConfig`. The
+//! consensus rules are read from `chain_spec.toml`, see
+//! `chain_spec::ChainSpec`, and the genesis block is built from
+//! `genesis.toml` if there is one, see `genesis::GenesisSpec`.
+use crate::chain_spec::CHAIN_SPEC_FILE;
+use crate::config::{DataPaths, NodeConfig, NodeConfigError, NODE_CONFIG_FILE};
+use crate::genesis::GENESIS_SPEC_FILE;
+use crate::network::node::NodeAuth;
+use libp2p::Multiaddr;
+use log::LevelFilter;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+use thiserror::Error;
+
+pub const DEFAULT_DATA_DIR: &str = "./data/vrrb";
+pub const DEFAULT_LISTEN_ADDRESS: &str = "/ip4/0.0.0.0/tcp/9292";
+pub const DEFAULT_LOG_LEVEL: LevelFilter = LevelFilter::Info;
+
+#[derive(Error, Debug)]
+pub enum CliError {
+    #[error("Invalid listen address {0}")]
+    InvalidListenAddress(String),
+    #[error("Invalid log level {0}, expected off, error, warn, info, debug or trace")]
+    InvalidLogLevel(String),
+    #[error(transparent)]
+    NodeConfig(#[from] NodeConfigError),
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "vrrb",
+    about = "Runs a VRRB node and manages its wallet, chain and state"
+)]
+pub struct Cli {
+    /// Directory holding the keystore, databases, config and logs of the node
+    #[structopt(long, global = true, default_value = DEFAULT_DATA_DIR, parse(from_os_str))]
+    pub data_dir: PathBuf,
+    #[structopt(subcommand)]
+    pub command: CliCommand,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum CliCommand {
+    /// Run a node
+    Node(NodeCommand),
+    /// Create, restore and use the wallet in the keystore
+    Wallet(WalletCommand),
+    /// Move a chain between nodes and check it
+    Chain(ChainCommand),
+    /// Look into the network state the node has stored
+    State(StateCommand),
+}
+
+#[derive(Debug, StructOpt)]
+pub enum NodeCommand {
+    /// Join the network and mine, validate and relay blocks and txns
+    Run(RunArgs),
+}
+
+#[derive(Debug, Clone, Default, StructOpt)]
+pub struct RunArgs {
+    /// Multiaddr to listen for peers on [default: /ip4/0.0.0.0/tcp/9292]
+    #[structopt(long)]
+    pub listen: Option<String>,
+    /// Multiaddr of a peer to dial on start, can be given several times
+    #[structopt(long = "bootstrap", number_of_values = 1)]
+    pub bootstrap: Vec<String>,
+    /// One of archive, full, light, ultra-light or bootstrap [default: full]
+    #[structopt(long)]
+    pub node_type: Option<NodeAuth>,
+    /// One of off, error, warn, info, debug or trace [default: info]
+    #[structopt(long)]
+    pub log_level: Option<String>,
+    /// Node config file [default: <data dir>/vrrb.toml]
+    #[structopt(long, parse(from_os_str))]
+    pub config: Option<PathBuf>,
+    /// Read commands from a plain command line instead of showing the dashboard
+    #[structopt(long)]
+    pub no_dashboard: bool,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum WalletCommand {
+    /// Create a new wallet and its mnemonic phrase
+    New,
+    /// Restore a wallet from its mnemonic phrase
+    Restore,
+    /// Show the balances of the wallet's addresses in the stored state
+    Balance,
+    /// Sign a txn and send it through the JSON-RPC server of a running node
+    Send {
+        /// Number of the address to send from
+        address_number: u32,
+        receiver: String,
+        amount: u128,
+        /// Address of the node's JSON-RPC server [default: from the network config]
+        #[structopt(long)]
+        rpc: Option<String>,
+    },
+    /// Sign a txn built with BUILDTXN, on a machine that never goes online
+    Sign {
+        /// The unsigned txn, or a file holding it
+        txn: String,
+    },
+    /// Create a multisig address, or show the pubkey to cosign with
+    Multisig {
+        threshold: Option<usize>,
+        pubkeys: Vec<String>,
+    },
+    /// Save the wallet's addresses as a watch-only wallet, without any keys
+    ExportWatchOnly {
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+    },
+    /// Show the balances and history of addresses without holding their keys
+    Watch {
+        /// A watch-only wallet file, or addresses and pubkeys
+        #[structopt(required = true)]
+        entries: Vec<String>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum ChainCommand {
+    /// Write a range of blocks to a file
+    Export {
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+        /// Height of the first block to export
+        #[structopt(long)]
+        from: Option<u128>,
+        /// Height of the last block to export
+        #[structopt(long)]
+        to: Option<u128>,
+    },
+    /// Validate and apply the blocks in a file exported with `chain export`
+    Import {
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+    },
+    /// Re-verify the stored chain and state from genesis
+    Verify,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum StateCommand {
+    /// Show the state hash, reward state and ledger
+    Inspect {
+        /// Only show these addresses of the ledger
+        addresses: Vec<String>,
+    },
+}
+
+/// The node settings `node run` ends up with once the command line, the node
+/// config file and the defaults are combined.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunOptions {
+    pub listen_address: Multiaddr,
+    pub bootstrap_peers: Vec<String>,
+    pub node_type: NodeAuth,
+    pub log_level: LevelFilter,
+    pub dashboard: bool,
+}
+
+/// Where the files of a node are in its data dir.
+#[derive(Debug, Clone)]
+pub struct DataDir {
+    path: PathBuf,
+    paths: DataPaths,
+}
+
+impl RunArgs {
+    /// Fills in the options missing from the command line from `config`, and
+    /// the ones missing from both with the defaults.
+    pub fn resolve(&self, config: &NodeConfig) -> Result<RunOptions, CliError> {
+        let listen_address = self
+            .listen
+            .clone()
+            .or_else(|| config.listen_address.clone())
+            .unwrap_or_else(|| DEFAULT_LISTEN_ADDRESS.to_string());
+        let log_level = match self.log_level.clone().or_else(|| config.log_level.clone()) {
+            Some(level) => level
+                .parse::<LevelFilter>()
+                .map_err(|_| CliError::InvalidLogLevel(level))?,
+            None => DEFAULT_LOG_LEVEL,
+        };
+        let bootstrap_peers = if self.bootstrap.is_empty() {
+            config.bootstrap_peers.clone()
+        } else {
+            self.bootstrap.clone()
+        };
+
+        Ok(RunOptions {
+            listen_address: listen_address
+                .parse()
+                .map_err(|_| CliError::InvalidListenAddress(listen_address))?,
+            bootstrap_peers,
+            node_type: self
+                .node_type
+                .clone()
+                .or_else(|| config.node_type.clone())
+                .unwrap_or(NodeAuth::Full),
+            log_level,
+            dashboard: !self.no_dashboard,
+        })
+    }
+}
+
+impl DataDir {
+    pub fn new(path: PathBuf) -> DataDir {
+        DataDir {
+            path,
+            paths: DataPaths::default(),
+        }
+    }
+
+    /// The same data dir, with the files the node config keeps elsewhere.
+    pub fn with_paths(&self, paths: &DataPaths) -> DataDir {
+        DataDir {
+            path: self.path.clone(),
+            paths: paths.clone(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn create(&self) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.path)
+    }
+
+    pub fn file(&self, name: &str) -> String {
+        self.path.join(name).to_string_lossy().into_owned()
+    }
+
+    // A path set in the node config replaces the file in the data dir.
+    fn configured_file(&self, path: &Option<PathBuf>, name: &str) -> String {
+        match path {
+            Some(path) => self.file(&path.to_string_lossy()),
+            None => self.file(name),
+        }
+    }
+
+    pub fn keystore(&self) -> String {
+        self.file("keystore.json")
+    }
+
+    pub fn network_config(&self) -> String {
+        self.file("network.json")
+    }
+
+    pub fn node_config(&self) -> String {
+        self.file(NODE_CONFIG_FILE)
+    }
+
+    pub fn chain_spec(&self) -> String {
+        self.file(CHAIN_SPEC_FILE)
+    }
+
+    pub fn genesis_spec(&self) -> String {
+        self.file(GENESIS_SPEC_FILE)
+    }
+
+    pub fn state_db(&self) -> String {
+        self.configured_file(&self.paths.state_db, "state.db")
+    }
+
+    pub fn chain_db(&self) -> String {
+        self.configured_file(&self.paths.chain_db, "chain.db")
+    }
+
+    pub fn address_book(&self) -> String {
+        self.configured_file(&self.paths.address_book, "address_book.db")
+    }
+
+    pub fn events_db(&self) -> String {
+        self.configured_file(&self.paths.events_db, "events.db")
+    }
+
+    pub fn wallet_history(&self) -> String {
+        self.configured_file(&self.paths.wallet_history, "wallet_history.db")
+    }
+
+    pub fn watch_history(&self) -> String {
+        self.file("watch_history.db")
+    }
+
+    /// Directory unsigned txns built with BUILDTXN are exported to.
+    pub fn txns_dir(&self) -> String {
+        self.file("txns")
+    }
+
+    pub fn log_file(&self) -> String {
+        self.configured_file(&self.paths.log_file, "vrrb.log")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_line_overrides_config_file_and_defaults() {
+        let config = NodeConfig {
+            listen_address: Some("/ip4/127.0.0.1/tcp/10000".to_string()),
+            bootstrap_peers: vec!["/ip4/10.0.0.2/tcp/9292".to_string()],
+            node_type: Some(NodeAuth::Light),
+            log_level: None,
+            paths: DataPaths {
+                chain_db: Some(PathBuf::from("/mnt/archive/chain.db")),
+                ..DataPaths::default()
+            },
+            ..NodeConfig::default()
+        };
+        let cli = Cli::from_iter_safe(&[
+            "vrrb",
+            "node",
+            "run",
+            "--data-dir",
+            "/tmp/vrrb",
+            "--bootstrap",
+            "/ip4/10.0.0.3/tcp/9292",
+            "--bootstrap",
+            "/ip4/10.0.0.4/tcp/9292",
+            "--log-level",
+            "debug",
+        ])
+        .unwrap();
+        let data_dir = DataDir::new(cli.data_dir);
+        assert_eq!(data_dir.chain_db(), "/tmp/vrrb/chain.db".to_string());
+        let data_dir = data_dir.with_paths(&config.paths);
+        assert_eq!(data_dir.chain_db(), "/mnt/archive/chain.db".to_string());
+        assert_eq!(data_dir.state_db(), "/tmp/vrrb/state.db".to_string());
+
+        let options = match cli.command {
+            CliCommand::Node(NodeCommand::Run(args)) => args.resolve(&config).unwrap(),
+            command => panic!("parsed the wrong command: {:?}", command),
+        };
+        assert_eq!(
+            options,
+            RunOptions {
+                listen_address: "/ip4/127.0.0.1/tcp/10000".parse().unwrap(),
+                bootstrap_peers: vec![
+                    "/ip4/10.0.0.3/tcp/9292".to_string(),
+                    "/ip4/10.0.0.4/tcp/9292".to_string()
+                ],
+                node_type: NodeAuth::Light,
+                log_level: LevelFilter::Debug,
+                dashboard: true,
+            }
+        );
+
+        let invalid = RunArgs {
+            log_level: Some("loud".to_string()),
+            ..RunArgs::default()
+        };
+        assert!(matches!(
+            invalid.resolve(&NodeConfig::default()),
+            Err(CliError::InvalidLogLevel(_))
+        ));
+    }
+}
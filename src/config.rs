@@ -0,0 +1,268 @@
+use crate::network::node::{NodeAuth, MAX_TRANSMIT_SIZE};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Name of the node config file in the data dir.
+pub const NODE_CONFIG_FILE: &str = "vrrb.toml";
+
+#[derive(Error, Debug)]
+pub enum NodeConfigError {
+    #[error("Error reading the node config file: {0}")]
+    ReadConfigError(#[from] std::io::Error),
+    #[error("Error parsing the node config file: {0}")]
+    ParseConfigError(#[from] toml::de::Error),
+    #[error("Invalid node config: {0}")]
+    InvalidConfig(String),
+}
+
+/// Settings of this node, as opposed to the `NetworkConfig` every node on the
+/// network shares and the `ChainSpec` every node validates blocks by. The
+/// options of `node run` can also be given on the command line, which takes
+/// precedence. Every field is left out of the file to use the default:
+///
+/// ```toml
+/// listen_address = "/ip4/0.0.0.0/tcp/9292"
+/// bootstrap_peers = ["/ip4/10.0.0.2/tcp/9292"]
+/// node_type = "Full"
+/// log_level = "info"
+/// max_transmit_size = 2000000
+///
+/// [gossipsub]
+/// heartbeat_interval_secs = 1
+/// mesh_n = 6
+///
+/// [ping]
+/// interval_secs = 20
+///
+/// [kademlia]
+/// max_records = 1024
+///
+/// [paths]
+/// chain_db = "/mnt/archive/chain.db"
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct NodeConfig {
+    // Multiaddr the node listens for peers on.
+    pub listen_address: Option<String>,
+    // Multiaddrs of peers to dial on start, on top of the network's
+    // bootstrap nodes.
+    pub bootstrap_peers: Vec<String>,
+    pub node_type: Option<NodeAuth>,
+    // One of off, error, warn, info, debug or trace.
+    pub log_level: Option<String>,
+    // The largest message published or accepted, at least MAX_TRANSMIT_SIZE
+    // so that the chunks other nodes send aren't dropped.
+    pub max_transmit_size: Option<usize>,
+    pub gossipsub: GossipsubSettings,
+    pub ping: PingSettings,
+    pub kademlia: KademliaSettings,
+    pub paths: DataPaths,
+}
+
+/// How the node gossips messages, see `GossipsubConfigBuilder`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct GossipsubSettings {
+    pub heartbeat_interval_secs: u64,
+    pub history_length: usize,
+    pub history_gossip: usize,
+    pub mesh_n: usize,
+    pub mesh_n_low: usize,
+    pub mesh_n_high: usize,
+    pub gossip_lazy: usize,
+    pub gossip_factor: f64,
+    pub fanout_ttl_secs: u64,
+    pub check_explicit_peers_ticks: u64,
+    pub published_message_ids_cache_time_secs: u64,
+    pub flood_publish: bool,
+}
+
+/// How often the node pings its peers and how long it waits for an answer.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PingSettings {
+    pub interval_secs: u64,
+    pub timeout_secs: u64,
+}
+
+/// Limits of the Kademlia record store, see `MemoryStoreConfig`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct KademliaSettings {
+    pub max_records: usize,
+    pub max_value_bytes: usize,
+    pub max_providers_per_key: usize,
+    pub max_provided_keys: usize,
+}
+
+/// Files of the node to keep somewhere else than in the data dir, e.g. the
+/// chain db on a larger disk. Relative paths are relative to the data dir.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DataPaths {
+    pub chain_db: Option<PathBuf>,
+    pub state_db: Option<PathBuf>,
+    pub events_db: Option<PathBuf>,
+    pub address_book: Option<PathBuf>,
+    pub wallet_history: Option<PathBuf>,
+    pub log_file: Option<PathBuf>,
+}
+
+impl NodeConfig {
+    pub fn load(path: &str) -> Result<NodeConfig, NodeConfigError> {
+        let content = fs::read_to_string(path)?;
+        let config = toml::from_str::<NodeConfig>(&content)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Loads the node config at `path`, falling back to the defaults if there
+    /// is no file at that path.
+    pub fn restore(path: &str) -> Result<NodeConfig, NodeConfigError> {
+        match NodeConfig::load(path) {
+            Err(NodeConfigError::ReadConfigError(e))
+                if e.kind() == std::io::ErrorKind::NotFound =>
+            {
+                Ok(NodeConfig::default())
+            }
+            result => result,
+        }
+    }
+
+    pub fn max_transmit_size(&self) -> usize {
+        self.max_transmit_size.unwrap_or(MAX_TRANSMIT_SIZE)
+    }
+
+    /// Checks the settings the swarm would otherwise panic on or that would
+    /// cut the node off from its peers.
+    pub fn validate(&self) -> Result<(), NodeConfigError> {
+        let invalid = |reason: String| Err(NodeConfigError::InvalidConfig(reason));
+        if self.max_transmit_size() < MAX_TRANSMIT_SIZE {
+            return invalid(format!(
+                "max_transmit_size has to be at least {}, the size of the chunks peers send",
+                MAX_TRANSMIT_SIZE
+            ));
+        }
+
+        let gossipsub = &self.gossipsub;
+        if gossipsub.heartbeat_interval_secs == 0 {
+            return invalid("gossipsub.heartbeat_interval_secs has to be at least 1".to_string());
+        }
+        if gossipsub.history_gossip > gossipsub.history_length {
+            return invalid(
+                "gossipsub.history_gossip can't be larger than gossipsub.history_length"
+                    .to_string(),
+            );
+        }
+        // Gossipsub keeps at least two outbound peers in the mesh.
+        if gossipsub.mesh_n_low < 2
+            || gossipsub.mesh_n < 4
+            || gossipsub.mesh_n_low > gossipsub.mesh_n
+            || gossipsub.mesh_n > gossipsub.mesh_n_high
+        {
+            return invalid(
+                "gossipsub needs 2 <= mesh_n_low <= mesh_n <= mesh_n_high and mesh_n >= 4"
+                    .to_string(),
+            );
+        }
+        if !(0.0..=1.0).contains(&gossipsub.gossip_factor) {
+            return invalid("gossipsub.gossip_factor has to be between 0 and 1".to_string());
+        }
+
+        if self.ping.interval_secs == 0 || self.ping.timeout_secs == 0 {
+            return invalid(
+                "ping.interval_secs and ping.timeout_secs have to be at least 1".to_string(),
+            );
+        }
+
+        if self.kademlia.max_records == 0 || self.kademlia.max_value_bytes == 0 {
+            return invalid(
+                "kademlia.max_records and kademlia.max_value_bytes have to be at least 1"
+                    .to_string(),
+            );
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for GossipsubSettings {
+    fn default() -> GossipsubSettings {
+        GossipsubSettings {
+            heartbeat_interval_secs: 1,
+            history_length: 5,
+            history_gossip: 3,
+            mesh_n: 6,
+            mesh_n_low: 4,
+            mesh_n_high: 12,
+            gossip_lazy: 6,
+            gossip_factor: 0.25,
+            fanout_ttl_secs: 60,
+            check_explicit_peers_ticks: 150,
+            published_message_ids_cache_time_secs: 5,
+            flood_publish: true,
+        }
+    }
+}
+
+impl Default for PingSettings {
+    fn default() -> PingSettings {
+        PingSettings {
+            interval_secs: 20,
+            timeout_secs: 20,
+        }
+    }
+}
+
+impl Default for KademliaSettings {
+    fn default() -> KademliaSettings {
+        // The defaults of libp2p's MemoryStoreConfig.
+        KademliaSettings {
+            max_records: 1024,
+            max_value_bytes: 65 * 1024,
+            max_providers_per_key: 20,
+            max_provided_keys: 1024,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_node_config_keeps_the_defaults_it_leaves_out_and_is_validated() {
+        let config = toml::from_str::<NodeConfig>(
+            "log_level = \"debug\"\n[gossipsub]\nmesh_n = 8\n[paths]\nchain_db = \"/mnt/chain.db\"",
+        )
+        .unwrap();
+        assert_eq!(config.gossipsub.mesh_n, 8);
+        assert_eq!(config.gossipsub.mesh_n_high, 12);
+        assert_eq!(config.ping, PingSettings::default());
+        assert_eq!(config.max_transmit_size(), MAX_TRANSMIT_SIZE);
+        assert!(config.validate().is_ok());
+
+        // Misspelled settings aren't silently ignored.
+        assert!(toml::from_str::<NodeConfig>("[gossipsub]\nmeshn = 8").is_err());
+
+        let invalid = NodeConfig {
+            gossipsub: GossipsubSettings {
+                mesh_n_low: 7,
+                ..GossipsubSettings::default()
+            },
+            ..NodeConfig::default()
+        };
+        assert!(matches!(
+            invalid.validate(),
+            Err(NodeConfigError::InvalidConfig(_))
+        ));
+        let invalid = NodeConfig {
+            max_transmit_size: Some(MAX_TRANSMIT_SIZE / 2),
+            ..NodeConfig::default()
+        };
+        assert!(invalid.validate().is_err());
+    }
+}
@@ -0,0 +1,528 @@
+//! The dashboard `node run` shows in the terminal. Its tabs show the wallet,
+//! the chain, the mempool, the claims, the peers and network events and the
+//! reward state, from the copies of the wallet, miner and blockchain the
+//! node's threads send it in `UpdateApp*` commands whenever they change. Below
+//! the tabs is the output of the commands typed into its command line.
+use crate::blockchain::Blockchain;
+use crate::helpers::{
+    render_claim_map, render_claim_pool, render_command_cache, render_header_chain, render_home,
+    render_invalid_blocks, render_network_data, render_network_state, render_reward_state,
+    render_txn_pool, render_wallet, render_wallet_history,
+};
+use crate::history::WalletHistory;
+use crate::miner::Miner;
+use crate::network::command_parser;
+use crate::network::command_utils::Command;
+use crate::wallet::WalletAccount;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use libp2p::Multiaddr;
+use ritelinked::LinkedHashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tui::backend::Backend;
+use tui::layout::{Alignment, Constraint, Direction, Layout, Rect};
+use tui::style::{Color, Modifier, Style};
+use tui::text::{Span, Spans};
+use tui::widgets::{Block, BorderType, Borders, List, ListItem, ListState, Paragraph, Tabs};
+use tui::Frame;
+
+/// How often the dashboard is redrawn when nothing is sent to it, to pick up
+/// the network events and wallet history other threads write.
+pub const REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+/// Lines of command output the dashboard keeps.
+pub const OUTPUT_LINES: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tab {
+    Home,
+    Wallet,
+    Chain,
+    Mempool,
+    Claims,
+    Network,
+    Rewards,
+}
+
+pub const TABS: [Tab; 7] = [
+    Tab::Home,
+    Tab::Wallet,
+    Tab::Chain,
+    Tab::Mempool,
+    Tab::Claims,
+    Tab::Network,
+    Tab::Rewards,
+];
+
+impl Tab {
+    pub fn title(&self) -> &'static str {
+        match self {
+            Tab::Home => "Home",
+            Tab::Wallet => "Wallet",
+            Tab::Chain => "Chain",
+            Tab::Mempool => "Mempool",
+            Tab::Claims => "Claims",
+            Tab::Network => "Network",
+            Tab::Rewards => "Rewards",
+        }
+    }
+
+    // The keys that move the selections of the tab, on top of the ones every
+    // tab has.
+    fn keys(&self) -> &'static str {
+        match self {
+            Tab::Wallet => "Up/Down address",
+            Tab::Chain => "Up/Down block, PgUp/PgDn invalid block",
+            Tab::Mempool => "Up/Down txn, Home/End pending or confirmed",
+            Tab::Claims => "Up/Down claim, PgUp/PgDn pool claim, Home/End pending or confirmed",
+            Tab::Home | Tab::Network | Tab::Rewards => "",
+        }
+    }
+
+    fn index(&self) -> usize {
+        TABS.iter().position(|tab| tab == self).unwrap_or(0)
+    }
+}
+
+/// What a key pressed in the dashboard asks the node to do.
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyAction {
+    None,
+    Submit(String),
+    Quit,
+}
+
+pub struct App {
+    pub tab: Tab,
+    listen_address: Multiaddr,
+    wallet: WalletAccount,
+    wallet_history: Arc<Mutex<WalletHistory>>,
+    credits: LinkedHashMap<String, u128>,
+    debits: LinkedHashMap<String, u128>,
+    miner: Option<Miner>,
+    blockchain: Option<Blockchain>,
+    peers: Vec<String>,
+    events_path: String,
+    output: Vec<String>,
+    input: String,
+    wallet_list: ListState,
+    chain_list: ListState,
+    invalid_list: ListState,
+    txn_status_list: ListState,
+    txn_list: ListState,
+    claim_map_list: ListState,
+    claim_status_list: ListState,
+    claim_list: ListState,
+}
+
+impl App {
+    pub fn new(
+        listen_address: Multiaddr,
+        wallet: WalletAccount,
+        wallet_history: Arc<Mutex<WalletHistory>>,
+        events_path: String,
+    ) -> App {
+        let selected = || {
+            let mut state = ListState::default();
+            state.select(Some(0));
+            state
+        };
+        let mut app = App {
+            tab: Tab::Home,
+            listen_address,
+            wallet,
+            wallet_history,
+            credits: LinkedHashMap::new(),
+            debits: LinkedHashMap::new(),
+            miner: None,
+            blockchain: None,
+            peers: vec![],
+            events_path,
+            output: vec![],
+            input: String::new(),
+            wallet_list: selected(),
+            chain_list: selected(),
+            invalid_list: selected(),
+            txn_status_list: selected(),
+            txn_list: selected(),
+            claim_map_list: selected(),
+            claim_status_list: selected(),
+            claim_list: selected(),
+        };
+        // The home tab shows the first address.
+        app.wallet.get_address(1);
+        app.push_output(&command_parser::help(None));
+        app
+    }
+
+    /// Replaces the copy of the wallet, miner or blockchain the dashboard
+    /// shows with the one in `command`.
+    pub fn update(&mut self, command: Command) {
+        match command {
+            Command::UpdateAppMiner(bytes) => {
+                let miner = Miner::from_bytes(&bytes);
+                // The balances are read from the ledger db, only when the
+                // state changed.
+                let state_changed = self.miner.as_ref().map_or(true, |current| {
+                    current.network_state.state_hash != miner.network_state.state_hash
+                });
+                if state_changed {
+                    self.credits = miner.network_state.get_credits();
+                    self.debits = miner.network_state.get_debits();
+                }
+                self.miner = Some(miner);
+            }
+            Command::UpdateAppBlockchain(bytes) => {
+                self.blockchain = Some(Blockchain::from_bytes(&bytes));
+            }
+            Command::UpdateAppWallet(bytes) => {
+                self.wallet = WalletAccount::from_bytes(&bytes);
+                self.wallet.get_address(1);
+            }
+            Command::UpdateAppMessageCache(bytes) => {
+                self.push_output(&String::from_utf8_lossy(&bytes));
+            }
+            Command::UpdateAppPeers(peers) => {
+                self.peers = peers.into_iter().collect();
+                self.peers.sort();
+            }
+            _ => {}
+        }
+    }
+
+    /// Adds `output` to the command output, one line at a time.
+    pub fn push_output(&mut self, output: &str) {
+        self.output
+            .extend(output.lines().map(|line| line.to_string()));
+        if self.output.len() > OUTPUT_LINES {
+            let excess = self.output.len() - OUTPUT_LINES;
+            self.output.drain(..excess);
+        }
+    }
+
+    pub fn on_key(&mut self, key: KeyEvent) -> KeyAction {
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                return KeyAction::Quit
+            }
+            KeyCode::Char(c) => self.input.push(c),
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Enter => {
+                let line = std::mem::take(&mut self.input);
+                if !line.trim().is_empty() {
+                    self.push_output(&format!("> {}", line));
+                    return KeyAction::Submit(line);
+                }
+            }
+            KeyCode::Tab => self.complete(),
+            KeyCode::Right => self.tab = TABS[(self.tab.index() + 1) % TABS.len()],
+            KeyCode::Left => self.tab = TABS[(self.tab.index() + TABS.len() - 1) % TABS.len()],
+            KeyCode::Up | KeyCode::Down => {
+                let forward = key.code == KeyCode::Down;
+                match self.tab {
+                    Tab::Wallet => step(&mut self.wallet_list, forward),
+                    Tab::Chain => step(&mut self.chain_list, forward),
+                    Tab::Mempool => step(&mut self.txn_list, forward),
+                    Tab::Claims => step(&mut self.claim_map_list, forward),
+                    _ => {}
+                }
+            }
+            KeyCode::PageUp | KeyCode::PageDown => {
+                let forward = key.code == KeyCode::PageDown;
+                match self.tab {
+                    Tab::Chain => step(&mut self.invalid_list, forward),
+                    Tab::Claims => step(&mut self.claim_list, forward),
+                    _ => {}
+                }
+            }
+            KeyCode::Home | KeyCode::End => {
+                let status = if key.code == KeyCode::Home { 0 } else { 1 };
+                match self.tab {
+                    Tab::Mempool => self.txn_status_list.select(Some(status)),
+                    Tab::Claims => self.claim_status_list.select(Some(status)),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+        KeyAction::None
+    }
+
+    // Completes the command name typed so far, or lists the commands it could
+    // be.
+    fn complete(&mut self) {
+        let candidates = command_parser::complete(&self.input);
+        match candidates.as_slice() {
+            [] => {}
+            [name] => self.input = format!("{} ", name),
+            names => {
+                let listed = names.join(" ");
+                self.push_output(&listed);
+            }
+        }
+    }
+
+    pub fn draw<B: Backend>(&mut self, f: &mut Frame<B>) {
+        self.clamp_selections();
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(
+                [
+                    Constraint::Length(3),
+                    Constraint::Min(10),
+                    Constraint::Length(10),
+                    Constraint::Length(3),
+                ]
+                .as_ref(),
+            )
+            .split(f.size());
+
+        let titles = TABS
+            .iter()
+            .map(|tab| Spans::from(Span::raw(tab.title())))
+            .collect::<Vec<_>>();
+        let keys = match self.tab.keys() {
+            "" => "Left/Right tab, Tab complete, Ctrl-C quit".to_string(),
+            keys => format!("Left/Right tab, {}, Tab complete, Ctrl-C quit", keys),
+        };
+        let tabs = Tabs::new(titles)
+            .select(self.tab.index())
+            .block(bordered(keys))
+            .highlight_style(
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD),
+            );
+        f.render_widget(tabs, chunks[0]);
+
+        match self.tab {
+            Tab::Home => self.draw_home(f, chunks[1]),
+            Tab::Wallet => self.draw_wallet(f, chunks[1]),
+            Tab::Chain => self.draw_chain(f, chunks[1]),
+            Tab::Mempool => self.draw_mempool(f, chunks[1]),
+            Tab::Claims => self.draw_claims(f, chunks[1]),
+            Tab::Network => self.draw_network(f, chunks[1]),
+            Tab::Rewards => self.draw_rewards(f, chunks[1]),
+        }
+
+        // Only the last lines of output fit, inside the borders.
+        let visible = (chunks[2].height as usize).saturating_sub(2);
+        let first = self.output.len().saturating_sub(visible);
+        f.render_widget(
+            render_command_cache(&self.output[first..].to_vec()),
+            chunks[2],
+        );
+
+        let input = Paragraph::new(format!("> {}", self.input)).block(bordered("Command"));
+        f.render_widget(input, chunks[3]);
+        f.set_cursor(
+            chunks[3].x + 3 + self.input.chars().count() as u16,
+            chunks[3].y + 1,
+        );
+    }
+
+    fn draw_home<B: Backend>(&self, f: &mut Frame<B>, area: Rect) {
+        f.render_widget(render_home(&self.listen_address, &self.wallet), area);
+    }
+
+    fn draw_wallet<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
+        let columns = split(Direction::Horizontal, area, &[35, 65]);
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(4), Constraint::Min(3)].as_ref())
+            .split(columns[1]);
+        let (list, detail) = render_wallet(
+            &self.wallet_list,
+            self.wallet.addresses.clone(),
+            self.credits.clone(),
+            self.debits.clone(),
+        );
+        let history = render_wallet_history(
+            &self.wallet_list,
+            &self.wallet.addresses,
+            &self.wallet_history.lock().unwrap(),
+        );
+        f.render_stateful_widget(list, columns[0], &mut self.wallet_list);
+        f.render_widget(detail, rows[0]);
+        f.render_widget(history, rows[1]);
+    }
+
+    fn draw_chain<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
+        let blockchain = match &self.blockchain {
+            Some(blockchain) => blockchain,
+            None => return f.render_widget(waiting("Chain", "the first block"), area),
+        };
+        let rows = split(Direction::Vertical, area, &[60, 40]);
+        let chain = split(Direction::Horizontal, rows[0], &[40, 60]);
+        let invalid = split(Direction::Horizontal, rows[1], &[40, 60]);
+        let (chain_list, block) = render_header_chain(&self.chain_list, &blockchain.chain);
+        let (invalid_list, invalid_block) =
+            render_invalid_blocks(&self.invalid_list, &blockchain.invalid);
+        f.render_stateful_widget(chain_list, chain[0], &mut self.chain_list);
+        f.render_widget(block, chain[1]);
+        f.render_stateful_widget(invalid_list, invalid[0], &mut self.invalid_list);
+        f.render_widget(invalid_block, invalid[1]);
+    }
+
+    fn draw_mempool<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
+        let miner = match &self.miner {
+            Some(miner) => miner,
+            None => return f.render_widget(waiting("Mempool", "the miner"), area),
+        };
+        let columns = split(Direction::Horizontal, area, &[15, 35, 50]);
+        let (status_list, txn_list, txn) =
+            render_txn_pool(&self.txn_status_list, &self.txn_list, &miner.txn_pool);
+        f.render_stateful_widget(status_list, columns[0], &mut self.txn_status_list);
+        f.render_stateful_widget(txn_list, columns[1], &mut self.txn_list);
+        f.render_widget(txn, columns[2]);
+    }
+
+    fn draw_claims<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
+        let miner = match &self.miner {
+            Some(miner) => miner,
+            None => return f.render_widget(waiting("Claims", "the miner"), area),
+        };
+        let rows = split(Direction::Vertical, area, &[50, 50]);
+        let claim_map = split(Direction::Horizontal, rows[0], &[50, 50]);
+        let claim_pool = split(Direction::Horizontal, rows[1], &[15, 35, 50]);
+        let (claim_map_list, claim) = render_claim_map(&self.claim_map_list, &miner.claim_map);
+        let (status_list, claim_list, pool_claim) =
+            render_claim_pool(&self.claim_status_list, &self.claim_list, &miner.claim_pool);
+        f.render_stateful_widget(claim_map_list, claim_map[0], &mut self.claim_map_list);
+        f.render_widget(claim, claim_map[1]);
+        f.render_stateful_widget(status_list, claim_pool[0], &mut self.claim_status_list);
+        f.render_stateful_widget(claim_list, claim_pool[1], &mut self.claim_list);
+        f.render_widget(pool_claim, claim_pool[2]);
+    }
+
+    fn draw_network<B: Backend>(&self, f: &mut Frame<B>, area: Rect) {
+        let columns = split(Direction::Horizontal, area, &[30, 70]);
+        let peers = List::new(
+            self.peers
+                .iter()
+                .map(|peer| ListItem::new(Spans::from(Span::raw(peer.clone()))))
+                .collect::<Vec<_>>(),
+        )
+        .block(bordered(format!("Connected Peers ({})", self.peers.len())));
+        f.render_widget(peers, columns[0]);
+
+        let rows = split(Direction::Vertical, columns[1], &[30, 70]);
+        match &self.miner {
+            Some(miner) => f.render_widget(render_network_state(&miner.network_state), rows[0]),
+            None => f.render_widget(waiting("Network State", "the miner"), rows[0]),
+        }
+        f.render_widget(render_network_data(&self.events_path), rows[1]);
+    }
+
+    fn draw_rewards<B: Backend>(&self, f: &mut Frame<B>, area: Rect) {
+        match &self.miner {
+            Some(miner) => f.render_widget(render_reward_state(&miner.reward_state), area),
+            None => f.render_widget(waiting("Rewards", "the miner"), area),
+        }
+    }
+
+    // The renderers index the lists with the selections, which have to be
+    // moved back when a list shrinks.
+    fn clamp_selections(&mut self) {
+        clamp(&mut self.wallet_list, self.wallet.addresses.len());
+        if let Some(blockchain) = &self.blockchain {
+            clamp(&mut self.chain_list, blockchain.chain.len());
+            clamp(&mut self.invalid_list, blockchain.invalid.len());
+        }
+        if let Some(miner) = &self.miner {
+            let txns = match self.txn_status_list.selected() {
+                Some(0) => miner.txn_pool.pending.len(),
+                _ => miner.txn_pool.confirmed.len(),
+            };
+            let claims = match self.claim_status_list.selected() {
+                Some(0) => miner.claim_pool.pending.len(),
+                _ => miner.claim_pool.confirmed.len(),
+            };
+            clamp(&mut self.txn_list, txns);
+            clamp(&mut self.claim_map_list, miner.claim_map.len());
+            clamp(&mut self.claim_list, claims);
+        }
+    }
+}
+
+fn step(state: &mut ListState, forward: bool) {
+    let selected = state.selected().unwrap_or(0);
+    let selected = if forward {
+        selected + 1
+    } else {
+        selected.saturating_sub(1)
+    };
+    state.select(Some(selected));
+}
+
+fn clamp(state: &mut ListState, len: usize) {
+    let selected = state.selected().unwrap_or(0);
+    state.select(Some(selected.min(len.saturating_sub(1))));
+}
+
+fn split(direction: Direction, area: Rect, percentages: &[u16]) -> Vec<Rect> {
+    let constraints = percentages
+        .iter()
+        .map(|percentage| Constraint::Percentage(*percentage))
+        .collect::<Vec<_>>();
+    Layout::default()
+        .direction(direction)
+        .constraints(constraints)
+        .split(area)
+}
+
+fn bordered<'a, T: Into<String>>(title: T) -> Block<'a> {
+    Block::default()
+        .borders(Borders::ALL)
+        .style(Style::default().fg(Color::White))
+        .title(title.into())
+        .border_type(BorderType::Plain)
+}
+
+fn waiting<'a>(title: &'a str, what: &str) -> Paragraph<'a> {
+    Paragraph::new(format!("Waiting for {}", what))
+        .alignment(Alignment::Center)
+        .block(bordered(title))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::network_config::NetworkConfig;
+    use crate::test_utils::TempDb;
+
+    #[test]
+    fn test_keys_edit_the_command_line_and_switch_tabs() {
+        let (history_db, events_db) = (TempDb::new("history"), TempDb::new("events"));
+        let mut app = App::new(
+            "/ip4/127.0.0.1/tcp/9292".parse().unwrap(),
+            WalletAccount::new(&NetworkConfig::default()),
+            Arc::new(Mutex::new(WalletHistory::restore(history_db.path()))),
+            events_db.path().to_string(),
+        );
+        let press =
+            |app: &mut App, code: KeyCode| app.on_key(KeyEvent::new(code, KeyModifiers::NONE));
+
+        for c in "getb".chars() {
+            press(&mut app, KeyCode::Char(c));
+        }
+        press(&mut app, KeyCode::Tab);
+        press(&mut app, KeyCode::Char('1'));
+        assert_eq!(
+            press(&mut app, KeyCode::Enter),
+            KeyAction::Submit("GETBAL 1".to_string())
+        );
+        assert_eq!(press(&mut app, KeyCode::Enter), KeyAction::None);
+
+        press(&mut app, KeyCode::Left);
+        assert_eq!(app.tab, Tab::Rewards);
+        press(&mut app, KeyCode::Right);
+        press(&mut app, KeyCode::Right);
+        assert_eq!(app.tab, Tab::Wallet);
+        assert_eq!(
+            app.on_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL)),
+            KeyAction::Quit
+        );
+    }
+}
@@ -0,0 +1,332 @@
+use crate::address::Address;
+use crate::block::Block;
+use crate::claim::Claim;
+use crate::header::{BlockHeader, SECOND};
+use crate::reward::{Category, Reward, RewardState, FLAKE_REWARD_RANGE};
+use crate::state::{Ledger, NetworkState};
+use ritelinked::LinkedHashMap;
+use serde::{Deserialize, Serialize};
+use sha256::digest_bytes;
+use std::collections::HashSet;
+use std::fs;
+use thiserror::Error;
+
+/// Name of the genesis spec file in the data dir.
+pub const GENESIS_SPEC_FILE: &str = "genesis.toml";
+
+#[derive(Error, Debug)]
+pub enum GenesisSpecError {
+    #[error("Error reading the genesis spec file: {0}")]
+    ReadSpecError(#[from] std::io::Error),
+    #[error("Error parsing the genesis spec file: {0}")]
+    ParseSpecError(#[from] toml::de::Error),
+    #[error("Invalid genesis spec: {0}")]
+    InvalidSpec(String),
+}
+
+/// Everything the first block of a chain and the state it starts from are
+/// built from. Every node given the same spec builds the same genesis block,
+/// so a network started from a spec can be started again, and nodes reject a
+/// genesis block with another hash:
+///
+/// ```toml
+/// chain_id = 402
+/// timestamp = 1650000000
+/// next_block_nonce = 4294967296
+///
+/// [[allocations]]
+/// address = "tvrrb1..."
+/// amount = 200000000
+///
+/// [[claims]]
+/// pubkey = "02..."
+/// address = "tvrrb1..."
+/// ```
+///
+/// The reward schedule starts from `RewardState::start` unless the spec has a
+/// `[reward_state]` table. toml can't hold u128s, so amounts and counters are
+/// u64s in the spec.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct GenesisSpec {
+    pub chain_id: u64,
+    // Seconds since the unix epoch.
+    pub timestamp: u64,
+    // Nonce of the first block mined on top of the genesis block, it decides
+    // which of the initial claims mines it.
+    pub next_block_nonce: u64,
+    #[serde(default)]
+    pub allocations: Vec<Allocation>,
+    // The claims allowed to mine from the start. The first one is the claim
+    // of the genesis block.
+    pub claims: Vec<GenesisClaim>,
+    #[serde(default)]
+    pub reward_state: Option<GenesisRewardState>,
+}
+
+/// Funds an address holds from the start.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Allocation {
+    pub address: String,
+    pub amount: u64,
+}
+
+/// The counters of a `RewardState` to start the reward schedule from.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct GenesisRewardState {
+    pub epoch: u64,
+    pub next_epoch_block: u64,
+    pub current_block: u64,
+    pub n_nuggets_remaining: u64,
+    pub n_veins_remaining: u64,
+    pub n_motherlodes_remaining: u64,
+    pub n_nuggets_current_epoch: u64,
+    pub n_veins_current_epoch: u64,
+    pub n_motherlodes_current_epoch: u64,
+    pub n_flakes_current_epoch: u64,
+    pub n_grains_current_epoch: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct GenesisClaim {
+    pub pubkey: String,
+    pub address: String,
+}
+
+impl GenesisSpec {
+    pub fn load(path: &str) -> Result<GenesisSpec, GenesisSpecError> {
+        let content = fs::read_to_string(path)?;
+        let spec = toml::from_str::<GenesisSpec>(&content)?;
+        spec.validate()?;
+        Ok(spec)
+    }
+
+    /// Loads the genesis spec at `path`, or None if there is no file at that
+    /// path and the first miner mines the genesis block instead.
+    pub fn restore(path: &str) -> Result<Option<GenesisSpec>, GenesisSpecError> {
+        match GenesisSpec::load(path) {
+            Ok(spec) => Ok(Some(spec)),
+            Err(GenesisSpecError::ReadSpecError(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), GenesisSpecError> {
+        let invalid = |reason: String| Err(GenesisSpecError::InvalidSpec(reason));
+        if self.claims.is_empty() {
+            return invalid("it needs at least one claim to mine the next block".to_string());
+        }
+
+        let mut pubkeys = HashSet::new();
+        for claim in self.claims.iter() {
+            if !pubkeys.insert(&claim.pubkey) {
+                return invalid(format!("the claim of {} is listed twice", claim.pubkey));
+            }
+            if let Err(e) = Address::validate(&claim.address) {
+                return invalid(format!("claim address {}: {}", claim.address, e));
+            }
+        }
+
+        let mut supply: u128 = 0;
+        for allocation in self.allocations.iter() {
+            if let Err(e) = Address::validate(&allocation.address) {
+                return invalid(format!("allocation address {}: {}", allocation.address, e));
+            }
+            supply = match supply.checked_add(u128::from(allocation.amount)) {
+                Some(supply) => supply,
+                None => return invalid("the allocations add up to more than a u128".to_string()),
+            };
+        }
+
+        let first_reward = first_block_reward().category;
+        if self.reward_state().valid_reward(first_reward) != Some(true) {
+            return invalid("the reward state has no flakes left for the next block".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// The reward state the chain starts from, before the genesis block.
+    pub fn reward_state(&self) -> RewardState {
+        self.reward_state
+            .map(RewardState::from)
+            .unwrap_or_else(RewardState::start)
+    }
+
+    /// The initial claims, keyed by pubkey like the claims of the ledger.
+    pub fn claims(&self) -> LinkedHashMap<String, Claim> {
+        self.claims
+            .iter()
+            .map(|claim| {
+                // Miners start their own claim at nonce 1, see `Miner::start`.
+                let claim = Claim::new(claim.pubkey.clone(), claim.address.clone(), 1);
+                (claim.pubkey.clone(), claim)
+            })
+            .collect()
+    }
+
+    pub fn credits(&self) -> LinkedHashMap<String, u128> {
+        let mut credits = LinkedHashMap::new();
+        self.allocations.iter().for_each(|allocation| {
+            *credits.entry(allocation.address.clone()).or_insert(0) +=
+                u128::from(allocation.amount);
+        });
+
+        credits
+    }
+
+    /// The genesis block. It isn't signed, its hash commits to the header,
+    /// which commits to the allocations and the initial claims.
+    pub fn block(&self) -> Block {
+        let claims = self.claims();
+        let (_, claim) = claims.front().unwrap();
+        let header = BlockHeader {
+            last_hash: digest_bytes("Genesis_Last_Hash".as_bytes()),
+            block_nonce: 0,
+            next_block_nonce: self.next_block_nonce,
+            block_height: 0,
+            timestamp: self.timestamp as u128 * SECOND,
+            txn_hash: digest_bytes(serde_json::to_string(&self.allocations).unwrap().as_bytes()),
+            claim: claim.clone(),
+            claim_map_hash: Some(Block::claims_hash(&claims)),
+            // The allocations are the genesis reward, the claim of the genesis
+            // block isn't paid on top of them.
+            block_reward: Reward {
+                miner: Some(claim.address.clone()),
+                category: Category::Genesis(Some(0)),
+                amount: 0,
+            },
+            next_block_reward: first_block_reward(),
+            neighbor_hash: None,
+            chain_id: self.chain_id,
+            signature: String::new(),
+        };
+
+        Block {
+            hash: Block::genesis_hash(&header),
+            header,
+            neighbors: None,
+            height: 0,
+            txns: LinkedHashMap::new(),
+            claims,
+            received_at: None,
+            received_from: None,
+            abandoned_claim: None,
+        }
+    }
+
+    /// Stores the state the chain starts from in the state db at `path`, as
+    /// it is once `genesis`, the block of this spec, is applied.
+    pub fn network_state(&self, path: &str, genesis: &Block) -> NetworkState {
+        let credits = self.credits();
+        let debits = LinkedHashMap::new();
+        let mut reward_state = self.reward_state();
+        reward_state.update(genesis.header.block_reward.category);
+
+        let mut network_state = NetworkState::restore(path);
+        network_state.credits = Some(digest_bytes(format!("{:?}", &credits).as_bytes()));
+        network_state.debits = Some(digest_bytes(format!("{:?}", &debits).as_bytes()));
+        network_state.reward_state = reward_state;
+        network_state.state_hash = Some(genesis.hash.clone());
+        network_state.update_ledger(
+            Ledger {
+                credits,
+                debits,
+                claims: genesis.claims.clone(),
+                nonces: LinkedHashMap::new(),
+            },
+            reward_state,
+        );
+
+        network_state
+    }
+}
+
+impl From<GenesisRewardState> for RewardState {
+    fn from(state: GenesisRewardState) -> RewardState {
+        RewardState {
+            epoch: state.epoch.into(),
+            next_epoch_block: state.next_epoch_block.into(),
+            current_block: state.current_block.into(),
+            n_nuggets_remaining: state.n_nuggets_remaining.into(),
+            n_veins_remaining: state.n_veins_remaining.into(),
+            n_motherlodes_remaining: state.n_motherlodes_remaining.into(),
+            n_nuggets_current_epoch: state.n_nuggets_current_epoch.into(),
+            n_veins_current_epoch: state.n_veins_current_epoch.into(),
+            n_motherlodes_current_epoch: state.n_motherlodes_current_epoch.into(),
+            n_flakes_current_epoch: state.n_flakes_current_epoch.into(),
+            n_grains_current_epoch: state.n_grains_current_epoch.into(),
+        }
+    }
+}
+
+// The reward of the first block is random when a miner mines the genesis
+// block, it has to be the same for every node building it from a spec.
+fn first_block_reward() -> Reward {
+    Reward {
+        miner: None,
+        category: Category::Flake(Some(FLAKE_REWARD_RANGE.0)),
+        amount: FLAKE_REWARD_RANGE.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::network_config::TESTNET_CHAIN_ID;
+    use crate::signer::{MockSigner, Signer};
+    use crate::test_utils::{testnet_address, TempDb};
+    use crate::verifiable::Verifiable;
+
+    #[test]
+    fn test_genesis_spec_builds_the_same_genesis_block_and_state() {
+        let signer = MockSigner::new(1);
+        let (pubkey, address) = (signer.pubkey(), testnet_address(&signer));
+        let spec = toml::from_str::<GenesisSpec>(&format!(
+            "chain_id = {}\ntimestamp = 1650000000\nnext_block_nonce = 4294967296\n\
+             [[allocations]]\naddress = \"{}\"\namount = 200000000\n\
+             [[claims]]\npubkey = \"{}\"\naddress = \"{}\"",
+            TESTNET_CHAIN_ID, address, pubkey, address
+        ))
+        .unwrap();
+        assert!(spec.validate().is_ok());
+
+        let genesis = spec.block();
+        assert_eq!(genesis.hash, spec.block().hash);
+        let db = TempDb::new("genesis_state");
+        let network_state = NetworkState::restore(db.path());
+        let reward_state = RewardState::start();
+        assert_eq!(spec.reward_state(), reward_state);
+        assert!(genesis.valid_genesis(&network_state, &reward_state, Some(genesis.hash.as_str())));
+        assert!(!genesis.valid_genesis(&network_state, &reward_state, Some("another hash")));
+
+        // Changing any allocation changes the genesis block.
+        let mut richer = spec.clone();
+        richer.allocations[0].amount += 1;
+        assert_ne!(richer.block().hash, genesis.hash);
+        let mut forged = genesis.clone();
+        forged.header.txn_hash = richer.block().header.txn_hash;
+        assert!(!forged.valid_genesis(&network_state, &reward_state, None));
+
+        let started = spec.network_state(db.path(), &genesis);
+        let restored = NetworkState::restore(db.path());
+        assert_eq!(restored.get_balance(&address), 200000000);
+        assert_eq!(restored.state_hash, Some(genesis.hash.clone()));
+        assert_eq!(restored.credits, started.credits);
+        assert_eq!(restored.get_claims().len(), 1);
+
+        let unclaimed = GenesisSpec {
+            claims: vec![],
+            ..spec
+        };
+        assert!(matches!(
+            unclaimed.validate(),
+            Err(GenesisSpecError::InvalidSpec(_))
+        ));
+    }
+}
@@ -50,8 +50,13 @@ impl CommandHandler {
                     println!("Error sending to mining sender: {:?}", e);
                 }
             }
-            Command::GetState => {
-                //TODO: request the state from the most recent confirmed block miner's node.
+            Command::GetState(peer_id) => {
+                if let Err(e) = self.to_blockchain_sender.send(Command::GetState(peer_id)) {
+                    println!(
+                        "Error sending GetState command to blockchain thread: {:?}",
+                        e
+                    );
+                }
             }
             Command::ProcessTxn(txn) => {
                 if let Err(e) = self.to_mining_sender.send(Command::ProcessTxn(txn)) {
@@ -95,7 +100,17 @@ impl CommandHandler {
             Command::ProcessBacklog => {}
             Command::CheckStateUpdateStatus((_block_height, _block, _last_block)) => {}
             Command::Quit => {
-                // TODO: Inform all the threads that you're shutting down.
+                let senders = [
+                    &self.to_mining_sender,
+                    &self.to_blockchain_sender,
+                    &self.to_state_sender,
+                    &self.to_swarm_sender,
+                ];
+                for sender in senders.iter() {
+                    if let Err(e) = sender.send(Command::Quit) {
+                        println!("Error sending quit command: {:?}", e);
+                    }
+                }
             }
             Command::SendMessage(message) => {
                 if let Err(e) = self.to_swarm_sender.send(Command::SendMessage(message)) {
@@ -141,6 +156,14 @@ impl CommandHandler {
                     println!("Error sending GetBalance command to mining thread: {:?}", e);
                 }
             }
+            Command::BuildTxn(sender, receiver, amount) => {
+                if let Err(e) = self
+                    .to_mining_sender
+                    .send(Command::BuildTxn(sender, receiver, amount))
+                {
+                    println!("Error sending BuildTxn command to mining thread: {:?}", e);
+                }
+            }
             Command::SendGenesis(sender_id) => {
                 if let Err(e) = self
                     .to_blockchain_sender
@@ -1,17 +1,11 @@
 use crate::block::Block;
 use crate::claim::Claim;
 use crate::reward::{Reward, RewardState};
-use bytebuffer::ByteBuffer;
+use crate::signer::{self, Signer, SignerError};
 use rand::Rng;
 use secp256k1::Error;
-use secp256k1::{
-    key::{PublicKey, SecretKey},
-    Signature,
-};
-use secp256k1::{Message, Secp256k1};
 use serde::{Deserialize, Serialize};
 use sha256::digest_bytes;
-use std::str::FromStr;
 use std::time::{SystemTime, UNIX_EPOCH};
 use std::u32::MAX as u32MAX;
 use std::u64::MAX as u64MAX;
@@ -34,16 +28,18 @@ pub struct BlockHeader {
     pub block_reward: Reward,
     pub next_block_reward: Reward,
     pub neighbor_hash: Option<String>,
+    pub chain_id: u64,
     pub signature: String,
 }
 
 impl BlockHeader {
     pub fn genesis(
         nonce: u64,
+        chain_id: u64,
         reward_state: &RewardState,
         claim: Claim,
-        secret_key: String,
-    ) -> BlockHeader {
+        signer: &dyn Signer,
+    ) -> Result<BlockHeader, SignerError> {
         let mut rng = rand::thread_rng();
         let last_hash = digest_bytes("Genesis_Last_Hash".as_bytes());
         let block_nonce = nonce;
@@ -55,26 +51,8 @@ impl BlockHeader {
         let txn_hash = digest_bytes("Genesis_Txn_Hash".as_bytes());
         let block_reward = Reward::genesis(Some(claim.address.clone()));
         let next_block_reward = Reward::new(None, reward_state);
-        let claim_map_hash: Option<String> = None;
-        let neighbor_hash: Option<String> = None;
-        let payload = format!(
-            "{},{},{},{},{},{},{:?},{:?},{:?},{:?},{:?}",
-            last_hash,
-            block_nonce,
-            next_block_nonce,
-            0,
-            timestamp,
-            txn_hash,
-            claim,
-            claim_map_hash,
-            block_reward,
-            next_block_reward,
-            neighbor_hash,
-        );
 
-        let signature = BlockHeader::sign(&payload, secret_key).unwrap().to_string();
-
-        BlockHeader {
+        let mut header = BlockHeader {
             last_hash,
             block_nonce,
             next_block_nonce,
@@ -86,8 +64,13 @@ impl BlockHeader {
             block_reward,
             next_block_reward,
             neighbor_hash: None,
-            signature,
-        }
+            chain_id,
+            signature: String::new(),
+        };
+
+        header.signature = signer.sign(&header.get_payload())?.to_string();
+
+        Ok(header)
     }
 
     pub fn new(
@@ -97,8 +80,8 @@ impl BlockHeader {
         txn_hash: String,
         claim_map_hash: Option<String>,
         neighbor_hash: Option<String>,
-        secret_key: String,
-    ) -> BlockHeader {
+        signer: &dyn Signer,
+    ) -> Result<BlockHeader, SignerError> {
         let mut rng = rand::thread_rng();
         let last_hash = last_block.hash;
         let block_nonce = last_block.header.next_block_nonce.clone();
@@ -111,8 +94,9 @@ impl BlockHeader {
         block_reward.miner = Some(claim.clone().address);
         let next_block_reward = Reward::new(None, reward_state);
         let block_height = last_block.header.block_height + 1;
-        let payload = format!(
-            "{},{},{},{},{},{},{:?},{:?},{:?},{:?},{:?}",
+
+        // A block always belongs to the same chain as its parent.
+        let mut header = BlockHeader {
             last_hash,
             block_nonce,
             next_block_nonce,
@@ -124,81 +108,27 @@ impl BlockHeader {
             block_reward,
             next_block_reward,
             neighbor_hash,
-        );
-
-        let signature = BlockHeader::sign(&payload, secret_key).unwrap().to_string();
-
-        BlockHeader {
-            last_hash,
-            block_nonce,
-            next_block_nonce,
-            block_height: last_block.header.block_height + 1,
-            timestamp,
-            txn_hash,
-            claim,
-            claim_map_hash,
-            block_reward,
-            next_block_reward,
-            neighbor_hash: None,
-            signature,
-        }
-    }
+            chain_id: last_block.header.chain_id,
+            signature: String::new(),
+        };
 
-    pub fn sign(message: &str, secret_key: String) -> Result<Signature, Error> {
-        let message_bytes = message.as_bytes().to_owned();
-        let mut buffer = ByteBuffer::new();
-        buffer.write_bytes(&message_bytes);
-        while buffer.len() < 32 {
-            buffer.write_u8(0);
-        }
+        header.signature = signer.sign(&header.get_payload())?.to_string();
 
-        let new_message = buffer.to_bytes();
-        let message_hash = blake3::hash(&new_message);
-        let message_hash = Message::from_slice(message_hash.as_bytes())?;
-        let secp = Secp256k1::new();
-        let sk = SecretKey::from_str(&secret_key).unwrap();
-        let sig = secp.sign(&message_hash, &sk);
-        Ok(sig)
+        Ok(header)
     }
 
     pub fn verify(&self) -> Result<bool, Error> {
-        let message_bytes = self.get_payload().as_bytes().to_vec();
-        let signature = {
-            if let Ok(signature) = Signature::from_str(&self.signature) {
-                signature
-            } else {
-                return Err(Error::InvalidSignature);
-            }
-        };
-
-        let pubkey = {
-            if let Ok(pubkey) = PublicKey::from_str(&self.claim.pubkey) {
-                pubkey
-            } else {
-                return Err(Error::InvalidPublicKey);
-            }
-        };
-
-        let mut buffer = ByteBuffer::new();
-        buffer.write_bytes(&message_bytes);
-        while buffer.len() < 32 {
-            buffer.write_u8(0);
-        }
-        let new_message = buffer.to_bytes();
-        let message_hash = blake3::hash(&new_message);
-        let message_hash = Message::from_slice(message_hash.as_bytes())?;
-        let secp = Secp256k1::new();
-        let valid = secp.verify(&message_hash, &signature, &pubkey);
-
-        match valid {
-            Ok(()) => Ok(true),
-            _ => Err(Error::IncorrectSignature),
+        if signer::verify(&self.get_payload(), &self.signature, &self.claim.pubkey) {
+            Ok(true)
+        } else {
+            Err(Error::IncorrectSignature)
         }
     }
 
     pub fn get_payload(&self) -> String {
         format!(
-            "{},{},{},{},{},{},{:?},{:?},{:?},{:?},{:?}",
+            "{},{},{},{},{},{},{},{:?},{:?},{:?},{:?},{:?}",
+            self.chain_id,
             self.last_hash,
             self.block_nonce,
             self.next_block_nonce,
@@ -1,6 +1,7 @@
 use crate::block;
 use crate::claim::Claim;
 use crate::header::BlockHeader;
+use crate::history::WalletHistory;
 use crate::network::protocol::VrrbNetworkEvent;
 use crate::pool::Pool;
 use crate::reward::RewardState;
@@ -42,7 +43,7 @@ pub fn render_home<'a>(addr: &Multiaddr, wallet: &WalletAccount) -> Paragraph<'a
         )]),
         Spans::from(vec![Span::raw("")]),
         Spans::from(vec![Span::raw(
-            "Press 'w' to access wallet, 'm' to access mining and 'n' to access network.",
+            "Use the left and right arrow keys to move between the tabs.",
         )]),
         Spans::from(vec![Span::raw("Address to dial: ")]),
         Spans::from(vec![Span::styled(
@@ -65,24 +66,18 @@ pub fn render_home<'a>(addr: &Multiaddr, wallet: &WalletAccount) -> Paragraph<'a
         Spans::from(vec![Span::raw("")]),
         Spans::from(vec![Span::raw("")]),
         Spans::from(vec![Span::styled(
-            "***** WRITE DOWN YOUR SECRET KEY AND PLACE IT SOMEWHERE YOU WILL NOT LOSE IT *****",
+            "***** YOUR WALLET CAN ONLY BE RESTORED FROM YOUR MNEMONIC PHRASE *****",
             Style::default()
                 .fg(Color::Yellow)
                 .add_modifier(Modifier::BOLD),
         )]),
         Spans::from(vec![Span::raw("")]),
         Spans::from(vec![Span::styled(
-            "***** NEVER SHARE YOUR SECRET KEY *****",
+            "***** NEVER SHARE YOUR MNEMONIC PHRASE OR KEYSTORE PASSWORD *****",
             Style::default()
                 .fg(Color::Yellow)
                 .add_modifier(Modifier::BOLD),
         )]),
-        Spans::from(vec![Span::raw("")]),
-        Spans::from(vec![Span::raw("Wallet Secret Key:")]),
-        Spans::from(vec![Span::styled(
-            format!("{}", wallet.get_secretkey()),
-            Style::default().fg(Color::Yellow),
-        )]),
     ])
     .alignment(Alignment::Center)
     .block(
@@ -188,6 +183,65 @@ pub fn render_wallet<'a>(
     (list, wallet_detail)
 }
 
+/// The txns sent from and to the address selected in the wallet view.
+pub fn render_wallet_history<'a>(
+    wallet_list_state: &ListState,
+    wallet_addresses: &LinkedHashMap<u32, String>,
+    history: &WalletHistory,
+) -> Table<'a> {
+    let selected_address = wallet_list_state
+        .selected()
+        .and_then(|selected| wallet_addresses.get(&(selected as u32 + 1)));
+
+    let records = if let Some(address) = selected_address {
+        history.for_address(address)
+    } else {
+        vec![]
+    };
+
+    let rows = records
+        .iter()
+        .map(|record| {
+            Row::new(vec![
+                Cell::from(Span::raw(record.txn_id.clone())),
+                Cell::from(Span::raw(format!("{}", record.direction))),
+                Cell::from(Span::raw(record.counterparty.clone())),
+                Cell::from(Span::raw(format!("{}", record.amount))),
+                Cell::from(Span::raw(format!("{}", record.status))),
+                Cell::from(Span::raw(format!("{}", history.confirmations(record)))),
+            ])
+        })
+        .collect::<Vec<_>>();
+
+    let header = ["Txn ID", "In/Out", "Counterparty", "Amount", "Status", "Confirmations"]
+        .iter()
+        .map(|title| {
+            Cell::from(Span::styled(
+                *title,
+                Style::default().add_modifier(Modifier::BOLD),
+            ))
+        })
+        .collect::<Vec<_>>();
+
+    Table::new(rows)
+        .header(Row::new(header))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::White))
+                .title("History")
+                .border_type(BorderType::Plain),
+        )
+        .widths(&[
+            Constraint::Percentage(25),
+            Constraint::Percentage(8),
+            Constraint::Percentage(35),
+            Constraint::Percentage(12),
+            Constraint::Percentage(10),
+            Constraint::Percentage(10),
+        ])
+}
+
 pub fn render_mining_data<'a>() -> List<'a> {
     let data = Block::default()
         .borders(Borders::ALL)
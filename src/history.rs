@@ -0,0 +1,324 @@
+use crate::block::Block;
+use crate::txn::Txn;
+use log::info;
+use pickledb::{PickleDb, PickleDbDumpPolicy, SerializationMethod};
+use ritelinked::LinkedHashMap;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+pub const RECORDS_KEY: &str = "records";
+pub const TIP_HEIGHT_KEY: &str = "tipheight";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TxnDirection {
+    Incoming,
+    Outgoing,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TxnStatus {
+    // Seen by the node, waiting for validators.
+    Pending,
+    // Confirmed by validators, `block_height` is set once it is in a block.
+    Confirmed,
+    // Rejected by validators, it will never be in a block.
+    Rejected,
+}
+
+/// One txn as seen from one of the wallet's addresses. A txn between two
+/// addresses of the same wallet has a record for each of them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TxnRecord {
+    pub txn_id: String,
+    pub address: String,
+    pub counterparty: String,
+    pub direction: TxnDirection,
+    pub amount: u128,
+    pub nonce: u128,
+    pub timestamp: u128,
+    pub status: TxnStatus,
+    pub block_height: Option<u128>,
+}
+
+/// The history of the txns sent from and to the wallet's addresses, persisted
+/// to a PickleDb at `path` so it survives restarts. It is updated as txns are
+/// sent or received, as validators confirm or reject them and as blocks
+/// including them are confirmed.
+#[derive(Debug, Clone)]
+pub struct WalletHistory {
+    pub path: String,
+    pub records: LinkedHashMap<String, TxnRecord>,
+    pub tip_height: u128,
+}
+
+impl TxnRecord {
+    fn new(txn: &Txn, address: &str, direction: TxnDirection) -> TxnRecord {
+        let counterparty = match direction {
+            TxnDirection::Incoming => txn.sender_address.clone(),
+            TxnDirection::Outgoing => txn.receiver_address.clone(),
+        };
+
+        TxnRecord {
+            txn_id: txn.txn_id.clone(),
+            address: address.to_string(),
+            counterparty,
+            direction,
+            amount: txn.txn_amount,
+            nonce: txn.nonce,
+            timestamp: txn.txn_timestamp,
+            status: TxnStatus::Pending,
+            block_height: None,
+        }
+    }
+}
+
+impl WalletHistory {
+    pub fn restore(path: &str) -> WalletHistory {
+        let db = WalletHistory::get_db(path);
+        let records: LinkedHashMap<String, TxnRecord> = db.get(RECORDS_KEY).unwrap_or_default();
+        let tip_height: u128 = db.get(TIP_HEIGHT_KEY).unwrap_or_default();
+
+        WalletHistory {
+            path: path.to_string(),
+            records,
+            tip_height,
+        }
+    }
+
+    fn get_db(path: &str) -> PickleDb {
+        match PickleDb::load_bin(path, PickleDbDumpPolicy::DumpUponRequest) {
+            Ok(db) => db,
+            Err(_) => PickleDb::new(
+                path,
+                PickleDbDumpPolicy::DumpUponRequest,
+                SerializationMethod::Bin,
+            ),
+        }
+    }
+
+    pub fn dump(&mut self) {
+        let mut db = WalletHistory::get_db(&self.path);
+        if let Err(e) = db.set(RECORDS_KEY, &self.records) {
+            info!("Error setting records to wallet history: {:?}", e);
+        }
+        if let Err(e) = db.set(TIP_HEIGHT_KEY, &self.tip_height) {
+            info!("Error setting tip height to wallet history: {:?}", e);
+        }
+        if let Err(e) = db.dump() {
+            info!("Error dumping wallet history to file: {:?}", e);
+        }
+    }
+
+    /// Records `txn` as pending for each of `addresses` it touches, unless it
+    /// is already known. Returns whether the txn touches any of them.
+    pub fn record_txn(&mut self, txn: &Txn, addresses: &[String]) -> bool {
+        let mut touched = false;
+        let mut changed = false;
+        for (address, direction) in directions(txn, addresses) {
+            touched = true;
+            let key = record_key(&txn.txn_id, &address, direction);
+            if !self.records.contains_key(&key) {
+                self.records
+                    .insert(key, TxnRecord::new(txn, &address, direction));
+                changed = true;
+            }
+        }
+
+        if changed {
+            self.dump();
+        }
+        touched
+    }
+
+    /// Sets the status validators agreed on for a txn the wallet knows about.
+    pub fn set_status(&mut self, txn_id: &str, status: TxnStatus) {
+        let mut changed = false;
+        self.records
+            .iter_mut()
+            .filter(|(_, record)| record.txn_id == txn_id && record.block_height.is_none())
+            .for_each(|(_, record)| {
+                if record.status != status {
+                    record.status = status;
+                    changed = true;
+                }
+            });
+
+        if changed {
+            self.dump();
+        }
+    }
+
+    /// Marks the wallet's txns in a confirmed block as confirmed at its height
+    /// and moves the tip forward, which adds a confirmation to every txn
+    /// already in a block.
+    pub fn record_block(&mut self, block: &Block, addresses: &[String]) {
+        let height = block.header.block_height;
+        block.txns.iter().for_each(|(_, txn)| {
+            for (address, direction) in directions(txn, addresses) {
+                let record = self
+                    .records
+                    .entry(record_key(&txn.txn_id, &address, direction))
+                    .or_insert_with(|| TxnRecord::new(txn, &address, direction));
+                record.status = TxnStatus::Confirmed;
+                record.block_height = Some(height);
+            }
+        });
+
+        if height > self.tip_height {
+            self.tip_height = height;
+        }
+        self.dump();
+    }
+
+    /// The number of blocks on top of and including the block the txn is in,
+    /// 0 while it isn't in a block.
+    pub fn confirmations(&self, record: &TxnRecord) -> u128 {
+        match record.block_height {
+            Some(height) if height <= self.tip_height => self.tip_height - height + 1,
+            _ => 0,
+        }
+    }
+
+    /// The records of `address`, the most recent first.
+    pub fn for_address(&self, address: &str) -> Vec<TxnRecord> {
+        let mut records = self
+            .records
+            .iter()
+            .filter(|(_, record)| record.address == address)
+            .map(|(_, record)| record.clone())
+            .collect::<Vec<_>>();
+        records.sort_by_key(|record| std::cmp::Reverse(record.timestamp));
+        records
+    }
+
+    pub fn get(&self, txn_id: &str) -> Vec<TxnRecord> {
+        self.records
+            .iter()
+            .filter(|(_, record)| record.txn_id == txn_id)
+            .map(|(_, record)| record.clone())
+            .collect()
+    }
+}
+
+impl fmt::Display for TxnStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TxnStatus::Pending => write!(f, "pending"),
+            TxnStatus::Confirmed => write!(f, "confirmed"),
+            TxnStatus::Rejected => write!(f, "rejected"),
+        }
+    }
+}
+
+impl fmt::Display for TxnDirection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TxnDirection::Incoming => write!(f, "in"),
+            TxnDirection::Outgoing => write!(f, "out"),
+        }
+    }
+}
+
+fn record_key(txn_id: &str, address: &str, direction: TxnDirection) -> String {
+    format!("{}:{}:{}", txn_id, address, direction)
+}
+
+/// The wallet addresses `txn` touches and in which direction.
+fn directions(txn: &Txn, addresses: &[String]) -> Vec<(String, TxnDirection)> {
+    let mut directions = vec![];
+    if addresses.contains(&txn.sender_address) {
+        directions.push((txn.sender_address.clone(), TxnDirection::Outgoing));
+    }
+    if addresses.contains(&txn.receiver_address) {
+        directions.push((txn.receiver_address.clone(), TxnDirection::Incoming));
+    }
+    directions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signer::MockSigner;
+    use crate::test_utils::{genesis_block, TempDb};
+    use crate::txn::UnsignedTxn;
+
+    fn txn(sender: &str, receiver: &str, amount: u128) -> Txn {
+        UnsignedTxn::new(0, sender.to_string(), receiver.to_string(), amount, 0)
+            .sign(&MockSigner::new(1))
+            .unwrap()
+    }
+
+    fn block(height: u128, txns: &[&Txn]) -> Block {
+        let mut block = genesis_block(&MockSigner::new(1));
+        block.header.block_height = height;
+        block.txns = txns
+            .iter()
+            .map(|txn| (txn.txn_id.clone(), (*txn).clone()))
+            .collect();
+        block
+    }
+
+    #[test]
+    fn test_txn_status_moves_from_pending_to_confirmed_or_rejected() {
+        let db = TempDb::new("history");
+        let addresses = vec!["alice".to_string()];
+        let mut history = WalletHistory::restore(db.path());
+        let confirmed = txn("alice", "bob", 10);
+        let rejected = txn("carol", "alice", 5);
+
+        assert!(history.record_txn(&confirmed, &addresses));
+        assert!(history.record_txn(&rejected, &addresses));
+        assert!(!history.record_txn(&txn("bob", "carol", 1), &addresses));
+        assert_eq!(history.get(&confirmed.txn_id)[0].status, TxnStatus::Pending);
+        assert_eq!(
+            history.get(&rejected.txn_id)[0].direction,
+            TxnDirection::Incoming
+        );
+
+        history.set_status(&confirmed.txn_id, TxnStatus::Confirmed);
+        history.set_status(&rejected.txn_id, TxnStatus::Rejected);
+        assert_eq!(
+            history.get(&confirmed.txn_id)[0].status,
+            TxnStatus::Confirmed
+        );
+        assert_eq!(history.get(&confirmed.txn_id)[0].block_height, None);
+        assert_eq!(history.get(&rejected.txn_id)[0].status, TxnStatus::Rejected);
+
+        // Once the txn is in a block validators can't change its status.
+        history.record_block(&block(1, &[&confirmed]), &addresses);
+        history.set_status(&confirmed.txn_id, TxnStatus::Rejected);
+        let restored = WalletHistory::restore(db.path());
+        assert_eq!(
+            restored.get(&confirmed.txn_id)[0].status,
+            TxnStatus::Confirmed
+        );
+        assert_eq!(restored.get(&confirmed.txn_id)[0].block_height, Some(1));
+        assert_eq!(
+            restored.get(&rejected.txn_id)[0].status,
+            TxnStatus::Rejected
+        );
+    }
+
+    #[test]
+    fn test_confirmations_count_the_blocks_up_to_the_chain_height() {
+        let db = TempDb::new("history");
+        let addresses = vec!["alice".to_string()];
+        let mut history = WalletHistory::restore(db.path());
+        let sent = txn("alice", "bob", 10);
+
+        history.record_txn(&sent, &addresses);
+        assert_eq!(history.confirmations(&history.get(&sent.txn_id)[0]), 0);
+
+        history.record_block(&block(3, &[&sent]), &addresses);
+        assert_eq!(history.confirmations(&history.get(&sent.txn_id)[0]), 1);
+
+        history.record_block(&block(5, &[]), &addresses);
+        assert_eq!(history.tip_height, 5);
+        assert_eq!(history.confirmations(&history.get(&sent.txn_id)[0]), 3);
+
+        // A block behind the tip doesn't move it back.
+        history.record_block(&block(4, &[]), &addresses);
+        assert_eq!(history.tip_height, 5);
+        assert_eq!(history.confirmations(&history.get(&sent.txn_id)[0]), 3);
+    }
+}
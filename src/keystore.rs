@@ -0,0 +1,196 @@
+use crate::claim::Claim;
+use crate::multisig::MultisigPolicy;
+use crate::network::network_config::TESTNET_ADDRESS_HRP;
+use chacha20poly1305::aead::{Aead, NewAead};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hmac::Hmac;
+use rand::RngCore;
+use ritelinked::LinkedHashMap;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::fs;
+use std::io;
+use std::path::Path;
+use thiserror::Error;
+
+pub const KEYSTORE_VERSION: u8 = 1;
+pub const KDF_NAME: &str = "pbkdf2-hmac-sha256";
+pub const CIPHER_NAME: &str = "chacha20poly1305";
+/// PBKDF2 rounds used for new keystores. Older keystores keep the rounds they
+/// were written with, they are stored alongside the salt.
+pub const KDF_ROUNDS: u32 = 100_000;
+pub const SALT_LEN: usize = 32;
+pub const NONCE_LEN: usize = 12;
+pub const KEY_LEN: usize = 32;
+
+#[derive(Error, Debug)]
+pub enum KeystoreError {
+    #[error("Error reading or writing the keystore file: {0}")]
+    Io(#[from] io::Error),
+    #[error("Error parsing the keystore file: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("Error decoding the keystore file: {0}")]
+    Decode(#[from] hex::FromHexError),
+    #[error("Unsupported keystore version {0}, expected {}", KEYSTORE_VERSION)]
+    UnsupportedVersion(u8),
+    #[error("Unsupported keystore {0}")]
+    UnsupportedAlgorithm(String),
+    #[error("Incorrect password or corrupted keystore")]
+    IncorrectPassword,
+    #[error("Error encrypting the keystore")]
+    Encryption,
+    #[error("The keystore does not hold a valid wallet secret")]
+    InvalidSecret,
+}
+
+/// How the encryption key is derived from the user's password.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub kdf: String,
+    pub rounds: u32,
+    pub salt: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CipherParams {
+    pub cipher: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+/// The on-disk form of a wallet. Only the wallet's secret is encrypted, the
+/// public parts of the wallet are stored in the clear so that they can be
+/// updated, e.g. after sending a txn, without asking for the password again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Keystore {
+    pub version: u8,
+    pub kdf: KdfParams,
+    pub cipher: CipherParams,
+    pub pubkey: String,
+    pub addresses: LinkedHashMap<u32, String>,
+    pub claims: LinkedHashMap<u128, Claim>,
+    #[serde(default)]
+    pub txn_nonces: LinkedHashMap<String, u128>,
+    pub chain_id: u64,
+    pub address_prefix: String,
+    #[serde(default = "default_address_hrp")]
+    pub address_hrp: String,
+    #[serde(default)]
+    pub multisig_policies: LinkedHashMap<String, MultisigPolicy>,
+}
+
+impl Keystore {
+    pub fn exists(path: &str) -> bool {
+        Path::new(path).exists()
+    }
+
+    pub fn load(path: &str) -> Result<Keystore, KeystoreError> {
+        let content = fs::read_to_string(path)?;
+        let keystore = serde_json::from_str::<Keystore>(&content)?;
+        if keystore.version != KEYSTORE_VERSION {
+            return Err(KeystoreError::UnsupportedVersion(keystore.version));
+        }
+
+        Ok(keystore)
+    }
+
+    /// Writes the keystore to a temporary file first and then moves it over
+    /// `path`, so that a crash mid-write never leaves a truncated keystore.
+    pub fn save(&self, path: &str) -> Result<(), KeystoreError> {
+        let tmp_path = format!("{}.tmp", path);
+        fs::write(&tmp_path, serde_json::to_vec_pretty(self)?)?;
+        restrict_permissions(&tmp_path)?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Encrypts `secret` with a key derived from `password` under a fresh salt
+    /// and nonce.
+    pub fn seal(secret: &[u8], password: &str) -> Result<(KdfParams, CipherParams), KeystoreError> {
+        let mut rng = rand::thread_rng();
+        let mut salt = [0u8; SALT_LEN];
+        let mut nonce = [0u8; NONCE_LEN];
+        rng.fill_bytes(&mut salt);
+        rng.fill_bytes(&mut nonce);
+
+        let key = derive_key(password, &salt, KDF_ROUNDS);
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), secret)
+            .map_err(|_| KeystoreError::Encryption)?;
+
+        Ok((
+            KdfParams {
+                kdf: KDF_NAME.to_string(),
+                rounds: KDF_ROUNDS,
+                salt: hex::encode(salt),
+            },
+            CipherParams {
+                cipher: CIPHER_NAME.to_string(),
+                nonce: hex::encode(nonce),
+                ciphertext: hex::encode(ciphertext),
+            },
+        ))
+    }
+
+    /// Decrypts the wallet secret. A wrong password fails the authentication
+    /// tag check and returns `IncorrectPassword`.
+    pub fn open(&self, password: &str) -> Result<Vec<u8>, KeystoreError> {
+        if self.kdf.kdf != KDF_NAME {
+            return Err(KeystoreError::UnsupportedAlgorithm(self.kdf.kdf.clone()));
+        }
+        if self.cipher.cipher != CIPHER_NAME {
+            return Err(KeystoreError::UnsupportedAlgorithm(
+                self.cipher.cipher.clone(),
+            ));
+        }
+
+        let salt = hex::decode(&self.kdf.salt)?;
+        let nonce = hex::decode(&self.cipher.nonce)?;
+        let ciphertext = hex::decode(&self.cipher.ciphertext)?;
+        if nonce.len() != NONCE_LEN {
+            return Err(KeystoreError::InvalidSecret);
+        }
+
+        let key = derive_key(password, &salt, self.kdf.rounds);
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+        cipher
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+            .map_err(|_| KeystoreError::IncorrectPassword)
+    }
+
+    /// Re-encrypts the secret under `new_password`, failing if `old_password`
+    /// does not open the keystore.
+    pub fn change_password(
+        &mut self,
+        old_password: &str,
+        new_password: &str,
+    ) -> Result<(), KeystoreError> {
+        let secret = self.open(old_password)?;
+        let (kdf, cipher) = Keystore::seal(&secret, new_password)?;
+        self.kdf = kdf;
+        self.cipher = cipher;
+        Ok(())
+    }
+}
+
+fn default_address_hrp() -> String {
+    TESTNET_ADDRESS_HRP.to_string()
+}
+
+fn derive_key(password: &str, salt: &[u8], rounds: u32) -> [u8; KEY_LEN] {
+    let mut key = [0u8; KEY_LEN];
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(password.as_bytes(), salt, rounds, &mut key);
+    key
+}
+
+#[cfg(unix)]
+fn restrict_permissions(path: &str) -> Result<(), io::Error> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+}
+
+#[cfg(not(unix))]
+fn restrict_permissions(_path: &str) -> Result<(), io::Error> {
+    Ok(())
+}
@@ -1,16 +1,33 @@
 pub mod account;
+pub mod address;
+pub mod balance;
 pub mod block;
 pub mod blockchain;
+pub mod chain_file;
+pub mod chain_spec;
+pub mod chain_tools;
+pub mod chain_verifier;
 pub mod claim;
+pub mod cli;
+pub mod config;
+pub mod dashboard;
 pub mod fields;
+pub mod genesis;
 pub mod handler;
 pub mod header;
 pub mod helpers;
+pub mod history;
+pub mod keystore;
 pub mod miner;
+pub mod multisig;
 pub mod network;
 pub mod pool;
 pub mod reward;
+pub mod rpc;
+pub mod signer;
 pub mod state;
+#[cfg(test)]
+pub mod test_utils;
 pub mod txn;
 pub mod utils;
 pub mod validator;
@@ -1,10 +1,13 @@
 use crate::block::Block;
+use crate::chain_spec::ChainSpec;
 use crate::claim::Claim;
 use crate::header::BlockHeader;
+use crate::network::network_config::NetworkConfig;
 use crate::pool::{Pool, PoolKind};
 use crate::reward::RewardState;
+use crate::signer::Signer;
 use crate::state::NetworkState;
-use crate::txn::Txn;
+use crate::txn::{Txn, UnsignedTxn};
 use crate::validator::TxnValidator;
 use crate::verifiable::Verifiable;
 use ritelinked::LinkedHashMap;
@@ -13,9 +16,9 @@ use sha256::digest_bytes;
 use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
+use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-pub const VALIDATOR_THRESHOLD: f64 = 0.60;
 pub const NANO: u128 = 1;
 pub const MICRO: u128 = NANO * 1000;
 pub const MILLI: u128 = MICRO * 1000;
@@ -47,20 +50,29 @@ pub struct Miner {
     pub init: bool,
     pub abandoned_claim_counter: LinkedHashMap<String, Claim>,
     pub abandoned_claim: Option<Claim>,
-    secret_key: String,
+    pub chain_id: u64,
+    // The network the addresses of the txns this miner validates belong to.
+    pub address_hrp: String,
+    pub address_prefix: String,
+    pub chain_spec: ChainSpec,
+    // Signs the blocks this miner mines. It is never serialized, a miner
+    // restored from bytes has no signer and can't mine.
+    #[serde(skip)]
+    signer: Option<Arc<dyn Signer>>,
 }
 
 impl Miner {
     pub fn start(
-        secret_key: String,
-        pubkey: String,
+        signer: Arc<dyn Signer>,
         address: String,
         reward_state: RewardState,
         network_state: NetworkState,
         n_miners: u128,
+        network_config: &NetworkConfig,
+        chain_spec: ChainSpec,
     ) -> Miner {
         let miner = Miner {
-            claim: Claim::new(pubkey.clone(), address, 1),
+            claim: Claim::new(signer.pubkey(), address, 1),
             mining: false,
             claim_map: LinkedHashMap::new(),
             txn_pool: Pool::new(PoolKind::Txn),
@@ -74,7 +86,11 @@ impl Miner {
             init: false,
             abandoned_claim_counter: LinkedHashMap::new(),
             abandoned_claim: None,
-            secret_key,
+            chain_id: network_config.chain_id,
+            address_hrp: network_config.address_hrp.clone(),
+            address_prefix: network_config.address_prefix.clone(),
+            chain_spec,
+            signer: Some(signer),
         };
 
         miner
@@ -117,18 +133,21 @@ impl Miner {
     }
 
     pub fn genesis(&mut self) -> Option<Block> {
+        let signer = self.signer.clone()?;
         self.claim_map
             .insert(self.claim.pubkey.clone(), self.claim.clone());
         Block::genesis(
             &self.reward_state.clone(),
             self.claim.clone(),
-            self.secret_key.clone(),
+            signer.as_ref(),
+            self.chain_id,
         )
     }
 
     pub fn mine(&mut self) -> Option<Block> {
         let claim_map_hash =
             digest_bytes(serde_json::to_string(&self.claim_map).unwrap().as_bytes());
+        let signer = self.signer.clone()?;
         if let Some(last_block) = self.last_block.clone() {
             return Block::mine(
                 self.clone().claim,
@@ -140,7 +159,8 @@ impl Miner {
                 &self.clone().network_state.clone(),
                 self.clone().neighbors.clone(),
                 self.abandoned_claim.clone(),
-                self.secret_key.clone(),
+                signer.as_ref(),
+                &self.chain_spec,
             );
         }
 
@@ -158,6 +178,12 @@ impl Miner {
         self.claim_map = new_claim_map;
     }
 
+    fn validate_txn(&self, txn: &Txn) -> bool {
+        txn.valid_chain_id(self.chain_id)
+            && txn.valid_addresses(&self.address_hrp, &self.address_prefix)
+            && txn.valid_txn(&self.network_state, &self.txn_pool)
+    }
+
     pub fn process_txn(&mut self, mut txn: Txn) -> TxnValidator {
         if let Some(_txn) = self.txn_pool.confirmed.get(&txn.txn_id) {
             // Nothing really to do here
@@ -165,20 +191,16 @@ impl Miner {
             // add validator if you have not validated already
             if let None = txn.validators.clone().get(&self.claim.pubkey) {
                 let mut txn = txn.clone();
-                txn.validators.insert(
-                    self.claim.pubkey.clone(),
-                    txn.valid_txn(&self.network_state, &self.txn_pool),
-                );
+                let valid = self.validate_txn(&txn);
+                txn.validators.insert(self.claim.pubkey.clone(), valid);
                 self.txn_pool
                     .pending
                     .insert(txn.txn_id.clone(), txn.clone());
             }
         } else {
             // add validator
-            txn.validators.insert(
-                self.claim.pubkey.clone(),
-                txn.valid_txn(&self.network_state, &self.txn_pool),
-            );
+            let valid = self.validate_txn(&txn);
+            txn.validators.insert(self.claim.pubkey.clone(), valid);
             self.txn_pool
                 .pending
                 .insert(txn.txn_id.clone(), txn.clone());
@@ -189,9 +211,19 @@ impl Miner {
             txn.clone(),
             &self.network_state,
             &self.txn_pool,
+            self.chain_id,
+            &self.address_hrp,
+            &self.address_prefix,
         );
     }
 
+    /// Builds an unsigned txn from `sender` using the next nonce of the sender
+    /// that isn't used by a confirmed or pending txn.
+    pub fn build_txn(&self, sender: String, receiver: String, amount: u128) -> UnsignedTxn {
+        let nonce = self.network_state.next_txn_nonce(&sender, &self.txn_pool);
+        UnsignedTxn::new(self.chain_id, sender, receiver, amount, nonce)
+    }
+
     pub fn process_txn_validator(&mut self, txn_validator: TxnValidator) {
         if let Some(_txn) = self.txn_pool.confirmed.get(&txn_validator.txn.txn_id) {
         } else if let Some(txn) = self.txn_pool.pending.get_mut(&txn_validator.txn.txn_id) {
@@ -216,7 +248,9 @@ impl Miner {
         };
 
         validators.retain(|_, v| *v);
-        if validators.len() as f64 / (self.claim_map.len() - 1) as f64 > VALIDATOR_THRESHOLD {
+        if validators.len() as f64 / (self.claim_map.len() - 1) as f64
+            > self.chain_spec.validator_threshold
+        {
             if let Some((k, v)) = self.txn_pool.pending.remove_entry(&txn_id) {
                 self.txn_pool.confirmed.insert(k, v);
             }
@@ -236,7 +270,9 @@ impl Miner {
         rejected.retain(|_, v| !*v);
         validators.retain(|_, v| *v);
 
-        if rejected.len() as f64 / self.claim_map.len() as f64 > 1.0 - VALIDATOR_THRESHOLD {
+        if rejected.len() as f64 / self.claim_map.len() as f64
+            > 1.0 - self.chain_spec.validator_threshold
+        {
             let slash_claims = validators
                 .iter()
                 .map(|(k, _)| return k.to_string())
@@ -247,6 +283,15 @@ impl Miner {
         }
     }
 
+    /// Drops the claim of a miner that left the network, so that it is no
+    /// longer a candidate to mine the next block. The miner's own claim is
+    /// never pruned.
+    pub fn prune_miner(&mut self, pubkey: &str) {
+        if pubkey != self.claim.pubkey {
+            self.claim_map.remove(pubkey);
+        }
+    }
+
     pub fn slash_claim(&mut self, pubkey: String) {
         if let Some(claim) = self.claim_map.get_mut(&pubkey) {
             claim.eligible = false;
@@ -310,7 +355,10 @@ impl Miner {
             "init".to_string(),
             "abandoned_claim_counter".to_string(),
             "abandoned_claim".to_string(),
-            "secret_key".to_string(),
+            "chain_id".to_string(),
+            "address_hrp".to_string(),
+            "address_prefix".to_string(),
+            "chain_spec".to_string(),
         ]
     }
 }
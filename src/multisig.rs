@@ -0,0 +1,121 @@
+use secp256k1::PublicKey;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Most cosigners a multisig address can have.
+pub const MAX_COSIGNERS: usize = 16;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum MultisigError {
+    #[error("threshold {threshold} must be between 1 and the {cosigners} cosigners")]
+    InvalidThreshold { threshold: usize, cosigners: usize },
+    #[error(
+        "a multisig address can have at most {} cosigners, got {0}",
+        MAX_COSIGNERS
+    )]
+    TooManyCosigners(usize),
+    #[error("{0} is not a valid public key")]
+    InvalidPubkey(String),
+    #[error("{0} appears more than once")]
+    DuplicatePubkey(String),
+    #[error("{0} is not a cosigner of this address")]
+    NotACosigner(String),
+    #[error("txn has {have} valid signatures, {need} are required")]
+    NotEnoughSignatures { have: usize, need: usize },
+}
+
+/// The M-of-N policy controlling a multisig address: any `threshold` of the
+/// `pubkeys` can spend from it. The pubkeys are kept sorted so the same set of
+/// keys always gives the same address, no matter the order they were given in.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MultisigPolicy {
+    pub threshold: usize,
+    pub pubkeys: Vec<String>,
+}
+
+impl MultisigPolicy {
+    pub fn new(
+        threshold: usize,
+        mut pubkeys: Vec<String>,
+    ) -> Result<MultisigPolicy, MultisigError> {
+        pubkeys.sort();
+        let policy = MultisigPolicy { threshold, pubkeys };
+        policy.validate()?;
+        Ok(policy)
+    }
+
+    /// Checks a policy that may have been built elsewhere, e.g. one carried in
+    /// a txn received from the network.
+    pub fn validate(&self) -> Result<(), MultisigError> {
+        if self.pubkeys.len() > MAX_COSIGNERS {
+            return Err(MultisigError::TooManyCosigners(self.pubkeys.len()));
+        }
+        if self.threshold == 0 || self.threshold > self.pubkeys.len() {
+            return Err(MultisigError::InvalidThreshold {
+                threshold: self.threshold,
+                cosigners: self.pubkeys.len(),
+            });
+        }
+
+        for (idx, pubkey) in self.pubkeys.iter().enumerate() {
+            if PublicKey::from_str(pubkey).is_err() {
+                return Err(MultisigError::InvalidPubkey(pubkey.clone()));
+            }
+            if idx > 0 && self.pubkeys[idx - 1] >= *pubkey {
+                return Err(MultisigError::DuplicatePubkey(pubkey.clone()));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn is_cosigner(&self, pubkey: &str) -> bool {
+        self.pubkeys.binary_search(&pubkey.to_string()).is_ok()
+    }
+}
+
+/// The canonical encoding of the policy, committed to by its address.
+impl fmt::Display for MultisigPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.threshold, self.pubkeys.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUBKEYS: [&str; 3] = [
+        "02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9",
+        "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5",
+    ];
+
+    #[test]
+    fn test_policy_is_independent_of_key_order() {
+        let keys = PUBKEYS.iter().map(|pk| pk.to_string()).collect::<Vec<_>>();
+        let mut reversed = keys.clone();
+        reversed.reverse();
+
+        let policy = MultisigPolicy::new(2, keys).unwrap();
+        assert_eq!(policy, MultisigPolicy::new(2, reversed).unwrap());
+        assert!(policy.is_cosigner(PUBKEYS[1]));
+    }
+
+    #[test]
+    fn test_invalid_policies_rejected() {
+        let keys = PUBKEYS.iter().map(|pk| pk.to_string()).collect::<Vec<_>>();
+        assert!(matches!(
+            MultisigPolicy::new(4, keys.clone()),
+            Err(MultisigError::InvalidThreshold { .. })
+        ));
+
+        let duplicated = vec![keys[0].clone(), keys[0].clone()];
+        assert!(matches!(
+            MultisigPolicy::new(1, duplicated),
+            Err(MultisigError::DuplicatePubkey(_))
+        ));
+    }
+}
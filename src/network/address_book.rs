@@ -0,0 +1,355 @@
+use libp2p::{Multiaddr, PeerId};
+use log::info;
+use pickledb::{PickleDb, PickleDbDumpPolicy, SerializationMethod};
+use ritelinked::LinkedHashMap;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const PEERS_KEY: &str = "peers";
+pub const MISMATCHES_KEY: &str = "mismatches";
+// Successful pings are frequent, only write them out this often.
+pub const SUCCESS_DUMP_INTERVAL: u128 = 60 * 1_000_000_000;
+
+/// Everything the node remembers about a peer between restarts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerRecord {
+    pub peer_id: String,
+    pub addresses: Vec<String>,
+    // Timestamp (nanos) of the last successful contact with the peer.
+    pub last_seen: u128,
+    pub successes: u64,
+    pub failures: u64,
+    // Pubkey of the claim this peer announced, if any.
+    pub claim_pubkey: Option<String>,
+}
+
+/// A peer that announced another network than this node's. It isn't dialed
+/// again before `retry_after`, after that it can rejoin if it was reconfigured.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NetworkMismatch {
+    // The protocol id the peer announced.
+    pub protocol_id: String,
+    // How many times in a row the peer announced another network.
+    pub count: u32,
+    // Timestamp (nanos) after which the peer is dialed again.
+    pub retry_after: u128,
+}
+
+/// The set of peers this node has successfully connected to. The address book is
+/// persisted to a PickleDb at `path` so that a restarted node can redial the
+/// peers it knew about instead of relying on a bootstrap node.
+#[derive(Debug, Clone)]
+pub struct AddressBook {
+    pub path: String,
+    pub peers: LinkedHashMap<String, PeerRecord>,
+    pub mismatches: LinkedHashMap<String, NetworkMismatch>,
+    last_dump: u128,
+}
+
+impl PeerRecord {
+    pub fn new(peer_id: String) -> PeerRecord {
+        PeerRecord {
+            peer_id,
+            addresses: vec![],
+            last_seen: 0,
+            successes: 0,
+            failures: 0,
+            claim_pubkey: None,
+        }
+    }
+
+    /// A peer is known good if it has answered more often than it has failed.
+    pub fn is_known_good(&self) -> bool {
+        self.successes > self.failures && !self.addresses.is_empty()
+    }
+
+    pub fn get_multiaddrs(&self) -> Vec<Multiaddr> {
+        self.addresses
+            .iter()
+            .filter_map(|addr| addr.parse::<Multiaddr>().ok())
+            .collect()
+    }
+}
+
+impl AddressBook {
+    pub fn restore(path: &str) -> AddressBook {
+        let db = AddressBook::get_db(path);
+        let peers: LinkedHashMap<String, PeerRecord> = if let Some(peers) = db.get(PEERS_KEY) {
+            peers
+        } else {
+            LinkedHashMap::new()
+        };
+
+        let mismatches = db.get(MISMATCHES_KEY).unwrap_or_default();
+
+        AddressBook {
+            path: path.to_string(),
+            peers,
+            mismatches,
+            last_dump: 0,
+        }
+    }
+
+    fn get_db(path: &str) -> PickleDb {
+        match PickleDb::load_bin(path, PickleDbDumpPolicy::DumpUponRequest) {
+            Ok(db) => db,
+            Err(_) => PickleDb::new(
+                path,
+                PickleDbDumpPolicy::DumpUponRequest,
+                SerializationMethod::Bin,
+            ),
+        }
+    }
+
+    pub fn dump(&mut self) {
+        let mut db = AddressBook::get_db(&self.path);
+        if let Err(e) = db.set(PEERS_KEY, &self.peers) {
+            info!("Error setting peers to address book: {:?}", e);
+        }
+        if let Err(e) = db.set(MISMATCHES_KEY, &self.mismatches) {
+            info!("Error setting network mismatches to address book: {:?}", e);
+        }
+        if let Err(e) = db.dump() {
+            info!("Error dumping address book to file: {:?}", e);
+        }
+        self.last_dump = get_timestamp();
+    }
+
+    fn entry(&mut self, peer_id: &PeerId) -> &mut PeerRecord {
+        let key = peer_id.to_string();
+        if !self.peers.contains_key(&key) {
+            self.peers.insert(key.clone(), PeerRecord::new(key.clone()));
+        }
+        self.peers.get_mut(&key).unwrap()
+    }
+
+    pub fn add_addresses(&mut self, peer_id: &PeerId, addresses: &[Multiaddr]) {
+        let record = self.entry(peer_id);
+        let mut changed = false;
+        addresses.iter().for_each(|addr| {
+            let addr = addr.to_string();
+            if !record.addresses.contains(&addr) {
+                record.addresses.push(addr);
+                changed = true;
+            }
+        });
+
+        if changed {
+            self.dump();
+        }
+    }
+
+    pub fn record_success(&mut self, peer_id: &PeerId) {
+        let record = self.entry(peer_id);
+        record.successes += 1;
+        record.last_seen = get_timestamp();
+
+        if get_timestamp().saturating_sub(self.last_dump) > SUCCESS_DUMP_INTERVAL {
+            self.dump();
+        }
+    }
+
+    pub fn record_failure(&mut self, peer_id: &PeerId) {
+        self.entry(peer_id).failures += 1;
+        self.dump();
+    }
+
+    pub fn set_claim_pubkey(&mut self, peer_id: &PeerId, pubkey: String) {
+        let record = self.entry(peer_id);
+        if record.claim_pubkey.as_ref() != Some(&pubkey) {
+            record.claim_pubkey = Some(pubkey);
+            self.dump();
+        }
+    }
+
+    /// Whether `peer_id` announced the claim of `pubkey`.
+    pub fn announced_claim(&self, peer_id: &PeerId, pubkey: &str) -> bool {
+        match self.peers.get(&peer_id.to_string()) {
+            Some(record) => record.claim_pubkey.as_deref() == Some(pubkey),
+            None => false,
+        }
+    }
+
+    /// How many times in a row `peer_id` announced another network.
+    pub fn mismatch_count(&self, peer_id: &PeerId) -> u32 {
+        self.mismatches
+            .get(&peer_id.to_string())
+            .map(|mismatch| mismatch.count)
+            .unwrap_or(0)
+    }
+
+    /// Records that `peer_id` announced `protocol_id`, another network, and
+    /// holds off dialing it for `backoff`.
+    pub fn record_mismatch(&mut self, peer_id: &PeerId, protocol_id: &str, backoff: Duration) {
+        let count = self.mismatch_count(peer_id) + 1;
+        self.mismatches.insert(
+            peer_id.to_string(),
+            NetworkMismatch {
+                protocol_id: protocol_id.to_string(),
+                count,
+                retry_after: get_timestamp() + backoff.as_nanos(),
+            },
+        );
+        self.dump();
+    }
+
+    /// Forgets the mismatches of `peer_id` once it announced this node's
+    /// network.
+    pub fn clear_mismatch(&mut self, peer_id: &PeerId) {
+        if self.mismatches.remove(&peer_id.to_string()).is_some() {
+            self.dump();
+        }
+    }
+
+    /// Whether the backoff after the last mismatch of `peer_id` hasn't elapsed
+    /// at `now`.
+    pub fn is_backing_off(&self, peer_id: &str, now: u128) -> bool {
+        match self.mismatches.get(peer_id) {
+            Some(mismatch) => now < mismatch.retry_after,
+            None => false,
+        }
+    }
+
+    pub fn remove(&mut self, peer_id: &PeerId) {
+        if self.peers.remove(&peer_id.to_string()).is_some() {
+            self.dump();
+        }
+    }
+
+    /// Returns up to `limit` known good peers with their addresses, the most
+    /// recently seen first. Peers backing off after a network mismatch are left
+    /// out.
+    pub fn known_good(&self, limit: usize) -> Vec<(PeerId, Vec<Multiaddr>)> {
+        let now = get_timestamp();
+        let mut records = self
+            .peers
+            .iter()
+            .map(|(_, record)| record.clone())
+            .filter(|record| record.is_known_good())
+            .filter(|record| !self.is_backing_off(&record.peer_id, now))
+            .collect::<Vec<_>>();
+
+        records.sort_by_key(|record| std::cmp::Reverse(record.last_seen));
+
+        records
+            .iter()
+            .filter_map(|record| {
+                if let Ok(peer_id) = PeerId::from_str(&record.peer_id) {
+                    Some((peer_id, record.get_multiaddrs()))
+                } else {
+                    None
+                }
+            })
+            .take(limit)
+            .collect()
+    }
+}
+
+fn get_timestamp() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempDb;
+
+    #[test]
+    fn test_address_book_persists_and_scores_peers() {
+        let db = TempDb::new("address_book");
+        let path = db.path();
+        let good = PeerId::random();
+        let flaky = PeerId::random();
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/19292".parse().unwrap();
+
+        let mut address_book = AddressBook::restore(path);
+        address_book.add_addresses(&good, &[addr.clone()]);
+        address_book.record_success(&good);
+        address_book.add_addresses(&flaky, &[addr.clone()]);
+        address_book.record_success(&flaky);
+        address_book.record_failure(&flaky);
+        address_book.set_claim_pubkey(&good, "pubkey".to_string());
+
+        // A restarted node sees the same peers, scores and claims.
+        let restored = AddressBook::restore(path);
+        let record = restored.peers.get(&good.to_string()).unwrap();
+        assert_eq!(record.addresses, vec![addr.to_string()]);
+        assert_eq!((record.successes, record.failures), (1, 0));
+        assert_eq!(record.claim_pubkey, Some("pubkey".to_string()));
+
+        // A peer that failed as often as it answered isn't redialed.
+        let known_good = restored.known_good(10);
+        assert_eq!(known_good.len(), 1);
+        assert_eq!(known_good[0], (good, vec![addr]));
+    }
+
+    #[test]
+    fn test_known_good_lists_the_most_recently_seen_first() {
+        let db = TempDb::new("address_book");
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/19293".parse().unwrap();
+        let mut address_book = AddressBook::restore(db.path());
+        let peers = (0..3).map(|_| PeerId::random()).collect::<Vec<_>>();
+        for (last_seen, peer) in peers.iter().enumerate() {
+            address_book.add_addresses(peer, &[addr.clone()]);
+            address_book.record_success(peer);
+            address_book.entry(peer).last_seen = last_seen as u128;
+        }
+
+        let known_good = address_book
+            .known_good(2)
+            .into_iter()
+            .map(|(peer_id, _)| peer_id)
+            .collect::<Vec<_>>();
+        assert_eq!(known_good, vec![peers[2], peers[1]]);
+    }
+
+    #[test]
+    fn test_claim_pubkey_is_replaced_and_removed_with_the_peer() {
+        let db = TempDb::new("address_book");
+        let peer = PeerId::random();
+        let mut address_book = AddressBook::restore(db.path());
+        address_book.set_claim_pubkey(&peer, "old".to_string());
+        address_book.set_claim_pubkey(&peer, "new".to_string());
+        assert_eq!(
+            AddressBook::restore(db.path())
+                .peers
+                .get(&peer.to_string())
+                .unwrap()
+                .claim_pubkey,
+            Some("new".to_string())
+        );
+
+        address_book.remove(&peer);
+        assert!(AddressBook::restore(db.path()).peers.is_empty());
+    }
+
+    #[test]
+    fn test_peer_on_another_network_is_skipped_until_its_backoff_elapsed() {
+        let db = TempDb::new("address_book");
+        let peer = PeerId::random();
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/19294".parse().unwrap();
+        let mut address_book = AddressBook::restore(db.path());
+        address_book.add_addresses(&peer, &[addr]);
+        address_book.record_success(&peer);
+
+        address_book.record_mismatch(&peer, "/vrrb/other/1", Duration::from_secs(60));
+        address_book.record_mismatch(&peer, "/vrrb/other/1", Duration::from_secs(60));
+        let restored = AddressBook::restore(db.path());
+        assert_eq!(restored.mismatch_count(&peer), 2);
+        assert!(restored.known_good(10).is_empty());
+        // The peer is kept, only not dialed, and is dialed again later.
+        assert!(restored.peers.contains_key(&peer.to_string()));
+        let later = get_timestamp() + Duration::from_secs(61).as_nanos();
+        assert!(!restored.is_backing_off(&peer.to_string(), later));
+
+        // Once the peer runs this node's network it is a known good peer again.
+        address_book.clear_mismatch(&peer);
+        let restored = AddressBook::restore(db.path());
+        assert_eq!(restored.mismatch_count(&peer), 0);
+        assert_eq!(restored.known_good(10).len(), 1);
+    }
+}
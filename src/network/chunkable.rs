@@ -1,3 +1,16 @@
 pub trait Chunkable {
     fn chunk(&self) -> Option<Vec<Vec<u8>>>;
-}
\ No newline at end of file
+}
+
+/// Splits a byte buffer into pieces of at most `chunk_size` bytes, every piece
+/// but the last is exactly `chunk_size` long.
+pub fn chunk_bytes(bytes: &[u8], chunk_size: usize) -> Vec<Vec<u8>> {
+    if bytes.is_empty() {
+        return vec![vec![]];
+    }
+
+    bytes
+        .chunks(chunk_size)
+        .map(|chunk| chunk.to_vec())
+        .collect()
+}
@@ -0,0 +1,354 @@
+use crate::address::Address;
+use crate::blockchain::StateComponent;
+use crate::network::command_utils::{
+    Command, BUILDTXN, GETBAL, GETHEIGHT, GETSTATE, HISTORY, MINEBLOCK, NEWTXN, QUIT, SENDADDRESS,
+    SENDRAWTXN, SENDSTATE, SENDTXN, STOPMINE, TEST,
+};
+use crate::network::network_config::NetworkConfig;
+use crate::txn::Txn;
+use std::str::FromStr;
+use thiserror::Error;
+
+pub const HELP: &str = "HELP";
+
+/// One command of the interactive interface, its arguments and what it does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub args: &'static str,
+    pub min_args: usize,
+    pub max_args: usize,
+    pub about: &'static str,
+}
+
+/// Every command that can be typed into the node, in the order HELP lists them.
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: SENDTXN,
+        args: "<address number> <receiver address> <amount>",
+        min_args: 3,
+        max_args: 3,
+        about: "Sign a txn from one of your addresses and broadcast it",
+    },
+    CommandSpec {
+        name: NEWTXN,
+        args: "<address number> <receiver address> <amount>",
+        min_args: 3,
+        max_args: 3,
+        about: "Same as SENDTXN",
+    },
+    CommandSpec {
+        name: BUILDTXN,
+        args: "<sender address> <receiver address> <amount>",
+        min_args: 3,
+        max_args: 3,
+        about: "Build an unsigned txn to sign offline",
+    },
+    CommandSpec {
+        name: SENDRAWTXN,
+        args: "<signed txn or file>",
+        min_args: 1,
+        max_args: 1,
+        about: "Broadcast a txn signed offline",
+    },
+    CommandSpec {
+        name: GETBAL,
+        args: "<address number>",
+        min_args: 1,
+        max_args: 1,
+        about: "Show the balance of one of your addresses",
+    },
+    CommandSpec {
+        name: HISTORY,
+        args: "<address number>",
+        min_args: 1,
+        max_args: 1,
+        about: "Show the txns sent from and to one of your addresses",
+    },
+    CommandSpec {
+        name: GETHEIGHT,
+        args: "",
+        min_args: 0,
+        max_args: 0,
+        about: "Show the height of the chain",
+    },
+    CommandSpec {
+        name: MINEBLOCK,
+        args: "",
+        min_args: 0,
+        max_args: 0,
+        about: "Start mining",
+    },
+    CommandSpec {
+        name: STOPMINE,
+        args: "",
+        min_args: 0,
+        max_args: 0,
+        about: "Stop mining",
+    },
+    CommandSpec {
+        name: SENDADDRESS,
+        args: "",
+        min_args: 0,
+        max_args: 0,
+        about: "Share your claim with the network",
+    },
+    CommandSpec {
+        name: GETSTATE,
+        args: "[peer id]",
+        min_args: 0,
+        max_args: 1,
+        about: "Request the network state from a peer, by default the one that sent the last block",
+    },
+    CommandSpec {
+        name: SENDSTATE,
+        args: "<peer id>",
+        min_args: 1,
+        max_args: 1,
+        about: "Send your network state to a peer",
+    },
+    CommandSpec {
+        name: TEST,
+        args: "",
+        min_args: 0,
+        max_args: 0,
+        about: "Check that the node responds to commands",
+    },
+    CommandSpec {
+        name: QUIT,
+        args: "",
+        min_args: 0,
+        max_args: 0,
+        about: "Stop the node",
+    },
+    CommandSpec {
+        name: HELP,
+        args: "[command]",
+        min_args: 0,
+        max_args: 1,
+        about: "List the commands, or show how to use one",
+    },
+];
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum CommandError {
+    #[error("Type a command, or HELP to list them")]
+    Empty,
+    #[error("Unknown command {0}, type HELP to list the commands")]
+    Unknown(String),
+    #[error("Usage: {0}")]
+    Usage(String),
+    #[error("Invalid {name}: {reason}")]
+    InvalidArgument { name: &'static str, reason: String },
+}
+
+/// A line typed into the node: either a command for the node's threads, or a
+/// request for help the interface answers itself.
+#[derive(Debug, Clone)]
+pub enum Input {
+    Command(Command),
+    Test,
+    Help(Option<&'static CommandSpec>),
+}
+
+impl CommandSpec {
+    pub fn find(name: &str) -> Option<&'static CommandSpec> {
+        let name = name.to_uppercase();
+        COMMANDS.iter().find(|spec| spec.name == name)
+    }
+
+    pub fn usage(&self) -> String {
+        if self.args.is_empty() {
+            self.name.to_string()
+        } else {
+            format!("{} {}", self.name, self.args)
+        }
+    }
+}
+
+/// Parses a line typed into the node. Command names are case insensitive and
+/// arguments are separated by any whitespace. Addresses have to belong to the
+/// network of `network_config`.
+pub fn parse(line: &str, network_config: &NetworkConfig) -> Result<Input, CommandError> {
+    let mut words = line.split_whitespace();
+    let name = words.next().ok_or(CommandError::Empty)?;
+    let args = words.collect::<Vec<_>>();
+    let spec = CommandSpec::find(name).ok_or_else(|| CommandError::Unknown(name.to_string()))?;
+    if args.len() < spec.min_args || args.len() > spec.max_args {
+        return Err(CommandError::Usage(spec.usage()));
+    }
+
+    let command = match spec.name {
+        HELP => {
+            return match args.first() {
+                Some(name) => CommandSpec::find(name)
+                    .map(|spec| Input::Help(Some(spec)))
+                    .ok_or_else(|| CommandError::Unknown(name.to_string())),
+                None => Ok(Input::Help(None)),
+            }
+        }
+        TEST => return Ok(Input::Test),
+        SENDTXN | NEWTXN => Command::SendTxn(
+            number(args[0], "address number")?,
+            address(args[1], "receiver address", network_config)?,
+            number(args[2], "amount")?,
+        ),
+        BUILDTXN => Command::BuildTxn(
+            address(args[0], "sender address", network_config)?,
+            address(args[1], "receiver address", network_config)?,
+            number(args[2], "amount")?,
+        ),
+        SENDRAWTXN => Command::SendRawTxn(signed_txn(args[0])?),
+        GETBAL => Command::GetBalance(number(args[0], "address number")?),
+        HISTORY => Command::GetHistory(number(args[0], "address number")?),
+        GETHEIGHT => Command::GetHeight,
+        MINEBLOCK => Command::MineBlock,
+        STOPMINE => Command::StopMine,
+        SENDADDRESS => Command::SendAddress,
+        GETSTATE => Command::GetState(args.first().map(|peer_id| peer_id.to_string())),
+        SENDSTATE => Command::SendStateComponents(args[0].to_string(), StateComponent::All),
+        QUIT => Command::Quit,
+        _ => return Err(CommandError::Unknown(name.to_string())),
+    };
+
+    Ok(Input::Command(command))
+}
+
+/// The list of commands, or how to use `command`.
+pub fn help(command: Option<&CommandSpec>) -> String {
+    match command {
+        Some(spec) => format!("{}\n    {}", spec.usage(), spec.about),
+        None => COMMANDS
+            .iter()
+            .map(|spec| format!("{:<60}{}", spec.usage(), spec.about))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
+
+/// The command names starting with the partly typed `input`, to complete it
+/// with.
+pub fn complete(input: &str) -> Vec<&'static str> {
+    let input = input.trim_start().to_uppercase();
+    if input.contains(char::is_whitespace) {
+        return vec![];
+    }
+
+    COMMANDS
+        .iter()
+        .map(|spec| spec.name)
+        .filter(|name| name.starts_with(&input))
+        .collect()
+}
+
+fn number<T: FromStr>(arg: &str, name: &'static str) -> Result<T, CommandError> {
+    arg.parse::<T>().map_err(|_| CommandError::InvalidArgument {
+        name,
+        reason: format!("{} is not a whole number", arg),
+    })
+}
+
+fn address(
+    arg: &str,
+    name: &'static str,
+    network_config: &NetworkConfig,
+) -> Result<String, CommandError> {
+    Address::parse_for_network(
+        arg,
+        &network_config.address_hrp,
+        &network_config.address_prefix,
+    )
+    .map_err(|e| CommandError::InvalidArgument {
+        name,
+        reason: e.to_string(),
+    })?;
+    Ok(arg.to_string())
+}
+
+// The signed txn is either pasted directly or read from the file it was
+// exported to.
+fn signed_txn(arg: &str) -> Result<Txn, CommandError> {
+    let exported = std::fs::read_to_string(arg).unwrap_or_else(|_| arg.to_string());
+    let txn = Txn::import(&exported).map_err(|e| CommandError::InvalidArgument {
+        name: "signed txn",
+        reason: e.to_string(),
+    })?;
+    txn.finalize().map_err(|e| CommandError::InvalidArgument {
+        name: "signed txn",
+        reason: format!("it can't be sent yet, {}", e),
+    })?;
+    Ok(txn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::network_config::TESTNET_ADDRESS_HRP;
+    use crate::signer::{MockSigner, Signer};
+
+    #[test]
+    fn test_commands_are_parsed_or_rejected_without_panicking() {
+        let network_config = NetworkConfig::testnet();
+        let receiver =
+            Address::from_pubkey(TESTNET_ADDRESS_HRP, &MockSigner::new(1).pubkey()).to_string();
+        match parse(&format!("sendtxn  1 {} 100", receiver), &network_config) {
+            Ok(Input::Command(Command::SendTxn(1, address, 100))) => {
+                assert_eq!(address, receiver)
+            }
+            parsed => panic!("parsed {:?}", parsed),
+        }
+
+        assert!(matches!(
+            parse("SENDTXN x y z", &network_config),
+            Err(CommandError::InvalidArgument {
+                name: "address number",
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse(&format!("SENDTXN 1 {} lots", receiver), &network_config),
+            Err(CommandError::InvalidArgument { name: "amount", .. })
+        ));
+        let mainnet_receiver =
+            Address::from_pubkey("vrrb", &MockSigner::new(1).pubkey()).to_string();
+        assert!(matches!(
+            parse(
+                &format!("SENDTXN 1 {} 100", mainnet_receiver),
+                &network_config
+            ),
+            Err(CommandError::InvalidArgument {
+                name: "receiver address",
+                ..
+            })
+        ));
+        assert_eq!(
+            parse("GETBAL", &network_config).unwrap_err(),
+            CommandError::Usage("GETBAL <address number>".to_string())
+        );
+        assert_eq!(
+            parse("MINE", &network_config).unwrap_err(),
+            CommandError::Unknown("MINE".to_string())
+        );
+        assert_eq!(
+            parse("  ", &network_config).unwrap_err(),
+            CommandError::Empty
+        );
+        assert_eq!(
+            parse("ACQRCLM abc", &network_config).unwrap_err(),
+            CommandError::Unknown("ACQRCLM".to_string())
+        );
+        assert!(matches!(
+            parse("GET_STE", &network_config),
+            Ok(Input::Command(Command::GetState(None)))
+        ));
+        assert!(
+            matches!(parse("help getbal", &network_config), Ok(Input::Help(Some(spec))) if spec.name == GETBAL)
+        );
+
+        // Every command is listed by HELP.
+        let listed = help(None);
+        assert!(COMMANDS.iter().all(|spec| listed.contains(spec.name)));
+        assert_eq!(complete("get"), vec![GETBAL, GETHEIGHT, GETSTATE]);
+        assert!(complete("GETBAL 1").is_empty());
+    }
+}
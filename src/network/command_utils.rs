@@ -1,14 +1,20 @@
+use crate::balance::Balance;
 use crate::block::Block;
 use crate::blockchain::StateComponent;
 use crate::claim::Claim;
+use crate::history::TxnRecord;
 use crate::network::message_types::StateBlock;
 use crate::state::{Components, NetworkState};
 use crate::txn::Txn;
 use crate::validator::TxnValidator;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
 
 pub const NEWTXN: &str = "NEW_TXN";
 pub const SENDTXN: &str = "SENDTXN";
+pub const BUILDTXN: &str = "BUILDTXN";
+pub const SENDRAWTXN: &str = "SENDRAWTXN";
 pub const GETSTATE: &str = "GET_STE";
 pub const SENDSTATE: &str = "SENDSTE";
 pub const MINEBLOCK: &str = "MINEBLK";
@@ -20,12 +26,15 @@ pub const TXNTOPIC: &str = "txn";
 pub const QUIT: &str = "QUIT";
 pub const TEST: &str = "TEST";
 pub const GETBAL: &str = "GETBAL";
+pub const HISTORY: &str = "HISTORY";
 pub const GETHEIGHT: &str = "GETHEIGHT";
 
 #[allow(dead_code)]
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub enum Command {
     SendTxn(u32, String, u128), // address number, receiver address, amount
+    BuildTxn(String, String, u128), // sender address, receiver address, amount
+    SendRawTxn(Txn),
     ProcessTxn(Txn),
     ProcessTxnValidator(TxnValidator),
     ConfirmedBlock(Block),
@@ -38,6 +47,7 @@ pub enum Command {
     SendState(String, u128),
     SendMessage(Vec<u8>),
     GetBalance(u32),
+    GetHistory(u32), // address number
     SendGenesis(String),
     SendStateComponents(String, StateComponent),
     GetStateComponents(String, StateComponent),
@@ -47,75 +57,78 @@ pub enum Command {
     UpdateLastBlock(Block),
     ClaimAbandoned(String, Claim),
     SlashClaims(Vec<String>),
+    DisconnectPeer(String),
+    PeerClaim(String, String), // peer id, pubkey of the claim the peer announced
+    PeerDisconnected(String, HashSet<String>), // peer id, ids of the peers still connected
+    PruneMiner(String),        // pubkey of a miner that left the network
     UpdateAppMiner(Vec<u8>),
     UpdateAppBlockchain(Vec<u8>),
     UpdateAppMessageCache(Vec<u8>),
     UpdateAppWallet(Vec<u8>),
+    UpdateAppPeers(HashSet<String>), // connected peer ids
     CheckAbandoned,
     StartMiner,
     GetHeight,
     MineBlock,
     MineGenesis,
     StopMine,
-    GetState,
+    GetState(Option<String>), // peer id to request the state from
     ProcessBacklog,
     SendAddress,
     NonceUp,
     Quit,
 }
 
-impl Command {
-    pub fn from_str(command_string: &str) -> Option<Command> {
-        let args: Vec<&str> = command_string.split(' ').collect();
-        if args.len() == 4 {
-            match args[0] {
-                SENDTXN => {
-                    return Some(Command::SendTxn(
-                        args[1].parse::<u32>().unwrap(),
-                        args[2].to_string(),
-                        args[3].parse::<u128>().unwrap(),
-                    ))
-                }
-                _ => {
-                    println!("Invalid command string!");
-                    return None;
-                }
-            }
-        } else if args.len() == 3 {
-            match args[0] {
-                _ => {
-                    println!("Invalid command string!");
-                    return None;
-                }
+/// What the node answers to a command typed into it. The node's threads send
+/// these back to the interface instead of printing them, so it can show them
+/// however it likes.
+#[derive(Debug, Clone)]
+pub enum CommandResult {
+    Balance(String, Balance),                // address, balance
+    History(String, Vec<(TxnRecord, u128)>), // address, records and their confirmations
+    Height(Option<u128>),
+    TxnSent(String),                  // txn id
+    TxnBuilt(Option<String>, String), // file it was saved to, exported txn
+    Mining(bool),
+    Message(String),
+    Error(String),
+}
+
+impl fmt::Display for CommandResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandResult::Balance(address, balance) => {
+                write!(f, "Balance of {}: {}", address, balance)
             }
-        } else if args.len() == 2 {
-            match args[0] {
-                GETBAL => {
-                    if let Ok(num) = args[1].parse::<u32>() {
-                        return Some(Command::GetBalance(num));
-                    } else {
-                        println!("Invalid command string");
-                        None
-                    }
-                }
-                _ => {
-                    println!("Invalid command string");
-                    None
+            CommandResult::History(address, records) => {
+                write!(f, "History of {}:", address)?;
+                for (record, confirmations) in records {
+                    write!(
+                        f,
+                        "\n{} {} {} {} {} ({} confirmations)",
+                        record.txn_id,
+                        record.direction,
+                        record.counterparty,
+                        record.amount,
+                        record.status,
+                        confirmations
+                    )?;
                 }
+                Ok(())
             }
-        } else {
-            match command_string.clone() {
-                GETSTATE => return Some(Command::GetState),
-                MINEBLOCK => return Some(Command::MineBlock),
-                STOPMINE => return Some(Command::StopMine),
-                SENDADDRESS => return Some(Command::SendAddress),
-                GETHEIGHT => return Some(Command::GetHeight),
-                QUIT => return Some(Command::Quit),
-                _ => {
-                    println!("Invalid command string");
-                    None
+            CommandResult::Height(Some(height)) => write!(f, "Blockchain height: {}", height),
+            CommandResult::Height(None) => write!(f, "There are no blocks yet"),
+            CommandResult::TxnSent(txn_id) => write!(f, "Sent txn {}", txn_id),
+            CommandResult::TxnBuilt(path, exported) => {
+                if let Some(path) = path {
+                    writeln!(f, "Unsigned txn saved to {}", path)?;
                 }
+                write!(f, "{}", exported)
             }
+            CommandResult::Mining(true) => write!(f, "Mining started"),
+            CommandResult::Mining(false) => write!(f, "Mining stopped"),
+            CommandResult::Message(message) => write!(f, "{}", message),
+            CommandResult::Error(error) => write!(f, "Error: {}", error),
         }
     }
 }
@@ -1,6 +1,11 @@
 #[allow(unused_imports)]
 use crate::account::AccountState;
+use crate::config::NodeConfig;
+use crate::network::address_book::AddressBook;
 use crate::network::command_utils::Command;
+use crate::network::connection_manager::{ConnectionManager, MAX_PING_FAILURES};
+use crate::network::message_types::MessageType;
+use crate::network::network_config::NetworkConfig;
 use crate::network::protocol::{build_transport, VrrbNetworkBehavior};
 use core::num::NonZeroU32;
 use libp2p::gossipsub::MessageId;
@@ -9,25 +14,30 @@ use libp2p::gossipsub::{
     ValidationMode,
 };
 use libp2p::identify::{Identify, IdentifyConfig};
-use libp2p::kad::{record::store::MemoryStore, Kademlia};
+use libp2p::kad::{
+    record::store::{MemoryStore, MemoryStoreConfig},
+    Kademlia,
+};
+use libp2p::mdns::{Mdns, MdnsConfig};
 use libp2p::ping::{Ping, PingConfig};
-use libp2p::swarm::Swarm;
+use libp2p::swarm::{toggle::Toggle, Swarm};
 use libp2p::{identity::Keypair, PeerId};
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
 use std::time::Duration;
 use tokio::sync::mpsc;
 
-pub const MAX_TRANSMIT_SIZE: usize = 2000000;
-
 pub async fn configure_swarm(
-    message_sender: mpsc::UnboundedSender<GossipsubMessage>,
+    message_sender: mpsc::UnboundedSender<MessageType>,
     command_sender: mpsc::UnboundedSender<Command>,
     local_peer_id: PeerId,
     local_key: Keypair,
     pubkey: String,
     address: String,
     event_path: String,
+    network_config: NetworkConfig,
+    address_book_path: String,
+    node_config: &NodeConfig,
 ) -> Swarm<VrrbNetworkBehavior> {
     let message_id_fn = |message: &GossipsubMessage| {
         let mut s = DefaultHasher::new();
@@ -35,23 +45,26 @@ pub async fn configure_swarm(
         MessageId::from(s.finish().to_string())
     };
 
+    let settings = &node_config.gossipsub;
     let gossipsub_config = GossipsubConfigBuilder::default()
-        .heartbeat_interval(Duration::from_secs(1))
-        .history_length(5)
-        .history_gossip(3)
-        .mesh_n(6)
-        .mesh_n_low(4)
-        .mesh_n_high(12)
-        .gossip_lazy(6)
-        .gossip_factor(0.25)
-        .fanout_ttl(Duration::from_secs(60))
-        .check_explicit_peers_ticks(150)
+        .heartbeat_interval(Duration::from_secs(settings.heartbeat_interval_secs))
+        .history_length(settings.history_length)
+        .history_gossip(settings.history_gossip)
+        .mesh_n(settings.mesh_n)
+        .mesh_n_low(settings.mesh_n_low)
+        .mesh_n_high(settings.mesh_n_high)
+        .gossip_lazy(settings.gossip_lazy)
+        .gossip_factor(settings.gossip_factor)
+        .fanout_ttl(Duration::from_secs(settings.fanout_ttl_secs))
+        .check_explicit_peers_ticks(settings.check_explicit_peers_ticks)
         .do_px()
-        .published_message_ids_cache_time(Duration::from_secs(5))
+        .published_message_ids_cache_time(Duration::from_secs(
+            settings.published_message_ids_cache_time_secs,
+        ))
         .validation_mode(ValidationMode::Strict)
         .message_id_fn(message_id_fn)
-        .flood_publish(true)
-        .max_transmit_size(MAX_TRANSMIT_SIZE)
+        .flood_publish(settings.flood_publish)
+        .max_transmit_size(node_config.max_transmit_size())
         .build()
         .expect("Valid config");
 
@@ -61,34 +74,58 @@ pub async fn configure_swarm(
     )
     .expect("Correct configuration");
 
-    let testnet_topic = Topic::new("test-net");
-    gossipsub.subscribe(&testnet_topic).unwrap();
+    let network_topic = Topic::new(network_config.topic());
+    gossipsub.subscribe(&network_topic).unwrap();
 
-    let store = MemoryStore::new(local_peer_id);
+    let settings = &node_config.kademlia;
+    let store = MemoryStore::with_config(
+        local_peer_id,
+        MemoryStoreConfig {
+            max_records: settings.max_records,
+            max_value_bytes: settings.max_value_bytes,
+            max_providers_per_key: settings.max_providers_per_key,
+            max_provided_keys: settings.max_provided_keys,
+        },
+    );
     let kademlia = Kademlia::new(local_peer_id, store);
 
-    let identify_config =
-        IdentifyConfig::new("vrrb/test-net/0.1.0".to_string(), local_key.public());
+    let identify_config = IdentifyConfig::new(network_config.protocol_id(), local_key.public());
     let identify = Identify::new(identify_config);
 
-    let ping_config = PingConfig::new();
-    ping_config
-        .with_interval(Duration::from_secs(20))
-        .with_max_failures(NonZeroU32::new(3).unwrap())
-        .with_timeout(Duration::from_secs(20));
+    let ping_config = PingConfig::new()
+        .with_interval(Duration::from_secs(node_config.ping.interval_secs))
+        .with_max_failures(NonZeroU32::new(MAX_PING_FAILURES).unwrap())
+        .with_timeout(Duration::from_secs(node_config.ping.timeout_secs))
+        .with_keep_alive(true);
 
-    let ping = Ping::new(PingConfig::new());
+    let ping = Ping::new(ping_config);
+
+    let mdns = if network_config.enable_mdns {
+        match Mdns::new(MdnsConfig::default()).await {
+            Ok(mdns) => Some(mdns),
+            Err(e) => {
+                println!("Unable to start mDNS peer discovery: {:?}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
 
     let behaviour = VrrbNetworkBehavior {
         gossipsub,
         identify,
         kademlia,
         ping,
+        mdns: Toggle::from(mdns),
         command_sender: command_sender.clone(),
         message_sender: message_sender.clone(),
         pubkey,
         address,
         path: event_path.clone(),
+        network_config,
+        address_book: AddressBook::restore(&address_book_path),
+        connection_manager: ConnectionManager::default(),
     };
 
     let transport = build_transport(local_key).await.unwrap();
@@ -0,0 +1,290 @@
+use crate::network::protocol::VrrbNetworkBehavior;
+use libp2p::swarm::Swarm;
+use libp2p::PeerId;
+use log::info;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+pub const MAX_PING_FAILURES: u32 = 3;
+pub const BASE_REDIAL_BACKOFF: Duration = Duration::from_secs(2);
+pub const MAX_REDIAL_BACKOFF: Duration = Duration::from_secs(300);
+pub const TARGET_PEER_COUNT: usize = 8;
+pub const TOP_UP_INTERVAL: Duration = Duration::from_secs(60);
+/// How often the swarm loop runs `maintain_connections`.
+pub const CONNECTION_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// What the node should do about a peer after a ping failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeerStatus {
+    // The peer failed fewer than `max_failures` times in a row, redial it after
+    // the backoff.
+    Redial(Duration),
+    // The peer failed `max_failures` times in a row and should be dropped.
+    Disconnected,
+}
+
+#[derive(Debug, Clone)]
+struct PeerConnectionState {
+    consecutive_failures: u32,
+    next_redial: Option<Instant>,
+}
+
+/// Tracks the health of the node's connections. A peer is only considered gone
+/// after `max_failures` consecutive ping failures, until then it is redialed
+/// with an exponential backoff. The manager also keeps track of how far the node
+/// is from its target number of peers.
+#[derive(Debug, Clone)]
+pub struct ConnectionManager {
+    pub max_failures: u32,
+    pub base_backoff: Duration,
+    pub max_backoff: Duration,
+    pub target_peers: usize,
+    connected: HashSet<PeerId>,
+    peers: HashMap<PeerId, PeerConnectionState>,
+    last_top_up: Option<Instant>,
+}
+
+impl ConnectionManager {
+    pub fn new(
+        max_failures: u32,
+        base_backoff: Duration,
+        max_backoff: Duration,
+        target_peers: usize,
+    ) -> ConnectionManager {
+        ConnectionManager {
+            max_failures,
+            base_backoff,
+            max_backoff,
+            target_peers,
+            connected: HashSet::new(),
+            peers: HashMap::new(),
+            last_top_up: None,
+        }
+    }
+
+    pub fn record_success(&mut self, peer_id: &PeerId) {
+        self.connected.insert(*peer_id);
+        self.peers.remove(peer_id);
+    }
+
+    pub fn record_failure(&mut self, peer_id: &PeerId) -> PeerStatus {
+        let state = self.peers.entry(*peer_id).or_insert(PeerConnectionState {
+            consecutive_failures: 0,
+            next_redial: None,
+        });
+        state.consecutive_failures += 1;
+
+        if state.consecutive_failures >= self.max_failures {
+            self.peers.remove(peer_id);
+            self.connected.remove(peer_id);
+            return PeerStatus::Disconnected;
+        }
+
+        let failures = state.consecutive_failures;
+        let backoff = self.backoff(failures);
+        if let Some(state) = self.peers.get_mut(peer_id) {
+            state.next_redial = Some(Instant::now() + backoff);
+        }
+
+        PeerStatus::Redial(backoff)
+    }
+
+    /// The delay before the redial following the `attempt`th consecutive
+    /// failure, doubling with every attempt up to `max_backoff`.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(16);
+        let backoff = self.base_backoff * 2u32.pow(exponent);
+        if backoff > self.max_backoff {
+            self.max_backoff
+        } else {
+            backoff
+        }
+    }
+
+    /// Returns the peers whose backoff has elapsed. Each scheduled redial is
+    /// only returned once.
+    pub fn due_redials(&mut self, now: Instant) -> Vec<PeerId> {
+        self.peers
+            .iter_mut()
+            .filter_map(|(peer_id, state)| match state.next_redial {
+                Some(at) if at <= now => {
+                    state.next_redial = None;
+                    Some(*peer_id)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Forgets `peer_id`, which the node disconnected from on purpose. It is
+    /// no longer counted as connected nor redialed.
+    pub fn disconnect(&mut self, peer_id: &PeerId) {
+        self.connected.remove(peer_id);
+        self.peers.remove(peer_id);
+    }
+
+    pub fn is_connected(&self, peer_id: &PeerId) -> bool {
+        self.connected.contains(peer_id)
+    }
+
+    pub fn connected_peers(&self) -> HashSet<String> {
+        self.connected
+            .iter()
+            .map(|peer_id| peer_id.to_string())
+            .collect()
+    }
+
+    /// How many more peers the node needs to reach its target.
+    pub fn peer_deficit(&self) -> usize {
+        self.target_peers.saturating_sub(self.connected.len())
+    }
+
+    fn should_top_up(&mut self, now: Instant) -> bool {
+        if self.peer_deficit() == 0 {
+            return false;
+        }
+
+        if let Some(last) = self.last_top_up {
+            if now.duration_since(last) < TOP_UP_INTERVAL {
+                return false;
+            }
+        }
+
+        self.last_top_up = Some(now);
+        true
+    }
+}
+
+impl Default for ConnectionManager {
+    fn default() -> ConnectionManager {
+        ConnectionManager::new(
+            MAX_PING_FAILURES,
+            BASE_REDIAL_BACKOFF,
+            MAX_REDIAL_BACKOFF,
+            TARGET_PEER_COUNT,
+        )
+    }
+}
+
+/// Redials peers whose backoff has elapsed and, if the node is below its target
+/// peer count, dials known good peers from the address book and looks for more
+/// through Kademlia. Called periodically from the swarm event loop.
+pub fn maintain_connections(swarm: &mut Swarm<VrrbNetworkBehavior>) {
+    let now = Instant::now();
+    let due = swarm.behaviour_mut().connection_manager.due_redials(now);
+
+    for peer_id in due {
+        if let Err(e) = swarm.dial(&peer_id) {
+            info!("Error redialing peer {:?}: {:?}", peer_id, e);
+        }
+    }
+
+    if !swarm.behaviour_mut().connection_manager.should_top_up(now) {
+        return;
+    }
+
+    let deficit = swarm.behaviour().connection_manager.peer_deficit();
+    let candidates = swarm
+        .behaviour()
+        .address_book
+        .known_good(usize::MAX)
+        .into_iter()
+        .filter(|(peer_id, _)| {
+            peer_id != swarm.local_peer_id()
+                && !swarm.behaviour().connection_manager.is_connected(peer_id)
+        })
+        .take(deficit)
+        .collect::<Vec<_>>();
+
+    for (peer_id, addrs) in candidates {
+        for addr in addrs {
+            swarm.behaviour_mut().kademlia.add_address(&peer_id, addr);
+        }
+        if let Err(e) = swarm.dial(&peer_id) {
+            info!(
+                "Error dialing peer {:?} to reach target peer count: {:?}",
+                peer_id, e
+            );
+        }
+    }
+
+    if let Err(e) = swarm.behaviour_mut().kademlia.bootstrap() {
+        info!("Unable to bootstrap kademlia to find more peers: {:?}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peer_is_dropped_after_max_consecutive_ping_failures() {
+        let mut manager = ConnectionManager::default();
+        let peer = PeerId::random();
+        manager.record_success(&peer);
+
+        for attempt in 1..MAX_PING_FAILURES {
+            assert_eq!(
+                manager.record_failure(&peer),
+                PeerStatus::Redial(manager.backoff(attempt))
+            );
+            assert!(manager.is_connected(&peer));
+        }
+        assert_eq!(manager.record_failure(&peer), PeerStatus::Disconnected);
+        assert!(!manager.is_connected(&peer));
+
+        // A successful ping resets the count of consecutive failures.
+        manager.record_success(&peer);
+        manager.record_failure(&peer);
+        manager.record_success(&peer);
+        for _ in 1..MAX_PING_FAILURES {
+            assert!(matches!(
+                manager.record_failure(&peer),
+                PeerStatus::Redial(_)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_max_and_redials_are_due_once() {
+        let manager = ConnectionManager::new(
+            10,
+            Duration::from_secs(2),
+            Duration::from_secs(10),
+            TARGET_PEER_COUNT,
+        );
+        let backoffs = (1..=5).map(|attempt| manager.backoff(attempt).as_secs());
+        assert_eq!(backoffs.collect::<Vec<_>>(), vec![2, 4, 8, 10, 10]);
+
+        let mut manager = manager;
+        let peer = PeerId::random();
+        let now = Instant::now();
+        manager.record_failure(&peer);
+        assert!(manager.due_redials(now).is_empty());
+        let later = now + Duration::from_secs(3);
+        assert_eq!(manager.due_redials(later), vec![peer]);
+        assert!(manager.due_redials(later).is_empty());
+    }
+
+    #[test]
+    fn test_top_up_until_the_target_peer_count_is_reached() {
+        let mut manager = ConnectionManager::new(
+            MAX_PING_FAILURES,
+            BASE_REDIAL_BACKOFF,
+            MAX_REDIAL_BACKOFF,
+            2,
+        );
+        let now = Instant::now();
+        assert_eq!(manager.peer_deficit(), 2);
+        assert!(manager.should_top_up(now));
+        // Not again until the top up interval has elapsed.
+        assert!(!manager.should_top_up(now + Duration::from_secs(1)));
+        assert!(manager.should_top_up(now + TOP_UP_INTERVAL));
+
+        manager.record_success(&PeerId::random());
+        manager.record_success(&PeerId::random());
+        assert_eq!(manager.peer_deficit(), 0);
+        assert!(!manager.should_top_up(now + TOP_UP_INTERVAL * 2));
+        assert_eq!(manager.connected_peers().len(), 2);
+    }
+}
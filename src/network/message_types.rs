@@ -2,6 +2,7 @@ use crate::block::Block;
 use crate::blockchain::StateComponent;
 use crate::claim::Claim;
 use crate::network::node::NodeAuth;
+use crate::network::wire::{self, WireError};
 use crate::txn::Txn;
 use crate::validator::TxnValidator;
 use crate::blockchain::InvalidBlockErrorReason;
@@ -100,15 +101,21 @@ pub enum MessageType {
 }
 
 impl MessageType {
+    /// Encodes the message into a versioned, optionally compressed wire frame.
+    /// See `network::wire` for the layout.
     pub fn as_bytes(self) -> Vec<u8> {
-        serde_json::to_string(&self).unwrap().as_bytes().to_vec()
+        wire::encode(&self).unwrap()
     }
 
     pub fn from_bytes(data: &[u8]) -> Option<MessageType> {
-        if let Ok(message) = serde_json::from_slice::<MessageType>(data) {
+        if let Ok(message) = MessageType::decode(data) {
             Some(message)
         } else {
             None
         }
     }
+
+    pub fn decode(data: &[u8]) -> Result<MessageType, WireError> {
+        wire::decode::<MessageType>(data)
+    }
 }
@@ -1,9 +1,14 @@
+pub mod address_book;
 pub mod chunkable;
+pub mod command_parser;
 pub mod command_utils;
 pub mod config_utils;
+pub mod connection_manager;
 pub mod message;
 pub mod message_types;
+pub mod network_config;
 pub mod node;
 pub mod protocol;
 pub mod sendable;
 pub mod voting;
+pub mod wire;
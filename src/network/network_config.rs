@@ -0,0 +1,154 @@
+use crate::chain_spec::ChainSpec;
+use crate::rpc::DEFAULT_RPC_ADDRESS;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use thiserror::Error;
+
+pub const PROTOCOL_NAME: &str = "vrrb";
+pub const PROTOCOL_VERSION: &str = "0.1.0";
+pub const TESTNET_NAME: &str = "test-net";
+pub const TESTNET_CHAIN_ID: u64 = 0x192;
+pub const TESTNET_ADDRESS_PREFIX: &str = "0x192";
+pub const TESTNET_ADDRESS_HRP: &str = "tvrrb";
+
+#[derive(Error, Debug)]
+pub enum NetworkConfigError {
+    #[error("Error reading the network config file: {0}")]
+    ReadConfigError(#[from] std::io::Error),
+    #[error("Error parsing the network config file: {0}")]
+    ParseConfigError(#[from] serde_json::Error),
+    #[error("Peer is on a different network: expected {expected}, received {received}")]
+    NetworkMismatch { expected: String, received: String },
+}
+
+/// Identifies the network (chain) a node belongs to. Every peer on the same
+/// network must agree on all of these fields, they are exchanged in the identify
+/// handshake and the chain id is committed to in every txn and block signature
+/// so that neither can be replayed on another network.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct NetworkConfig {
+    // Human readable name of the network, also used to name the gossipsub topic.
+    pub network_name: String,
+    pub chain_id: u64,
+    // Hash of the genesis block, None until the network's genesis is known.
+    pub genesis_hash: Option<String>,
+    // Prefix of the legacy hex addresses on this network.
+    pub address_prefix: String,
+    // Human readable part of the bech32m addresses on this network.
+    #[serde(default = "default_address_hrp")]
+    pub address_hrp: String,
+    pub protocol_version: String,
+    // Multiaddrs of the seed nodes new nodes on this network dial first.
+    #[serde(default)]
+    pub bootstrap_nodes: Vec<String>,
+    // Discover peers on the local network through mDNS. Meant for development
+    // clusters running on a single host or LAN, off by default.
+    #[serde(default)]
+    pub enable_mdns: bool,
+    // Address the node's JSON-RPC server listens on, localhost only by
+    // default. None turns the server off.
+    #[serde(default = "default_rpc_address")]
+    pub rpc_address: Option<String>,
+    // Hash of the chain spec the node runs, None for the testnet's. It comes
+    // from the chain spec file, not from this config.
+    #[serde(skip)]
+    pub chain_spec_hash: Option<String>,
+}
+
+impl NetworkConfig {
+    pub fn testnet() -> NetworkConfig {
+        NetworkConfig {
+            network_name: TESTNET_NAME.to_string(),
+            chain_id: TESTNET_CHAIN_ID,
+            genesis_hash: None,
+            address_prefix: TESTNET_ADDRESS_PREFIX.to_string(),
+            address_hrp: TESTNET_ADDRESS_HRP.to_string(),
+            protocol_version: PROTOCOL_VERSION.to_string(),
+            bootstrap_nodes: vec![],
+            enable_mdns: false,
+            rpc_address: default_rpc_address(),
+            chain_spec_hash: None,
+        }
+    }
+
+    pub fn load(path: &str) -> Result<NetworkConfig, NetworkConfigError> {
+        let content = fs::read_to_string(path)?;
+        Ok(serde_json::from_str::<NetworkConfig>(&content)?)
+    }
+
+    /// Loads the network config at `path`, falling back to the default testnet
+    /// config if there is no file at that path.
+    pub fn restore(path: &str) -> Result<NetworkConfig, NetworkConfigError> {
+        match NetworkConfig::load(path) {
+            Err(NetworkConfigError::ReadConfigError(e))
+                if e.kind() == std::io::ErrorKind::NotFound =>
+            {
+                Ok(NetworkConfig::testnet())
+            }
+            result => result,
+        }
+    }
+
+    /// The protocol string sent to peers in the identify handshake. Two nodes
+    /// only stay connected if their protocol strings are equal.
+    pub fn protocol_id(&self) -> String {
+        let mut protocol_id = format!(
+            "/{}/{}/{}/{}",
+            PROTOCOL_NAME, self.network_name, self.chain_id, self.protocol_version
+        );
+
+        if let Some(genesis_hash) = &self.genesis_hash {
+            protocol_id.push('/');
+            protocol_id.push_str(genesis_hash);
+        }
+
+        if let Some(chain_spec_hash) = &self.chain_spec_hash {
+            protocol_id.push('/');
+            protocol_id.push_str(chain_spec_hash);
+        }
+
+        protocol_id
+    }
+
+    pub fn check_protocol_id(&self, received: &str) -> Result<(), NetworkConfigError> {
+        let expected = self.protocol_id();
+        if expected != received {
+            return Err(NetworkConfigError::NetworkMismatch {
+                expected,
+                received: received.to_string(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Commits to `chain_spec` in the protocol id, so that the node only
+    /// connects to peers running the same chain spec. The testnet's chain spec
+    /// leaves the protocol id as it was before chain specs existed.
+    pub fn set_chain_spec(&mut self, chain_spec: &ChainSpec) {
+        self.chain_spec_hash = if *chain_spec == ChainSpec::testnet() {
+            None
+        } else {
+            Some(chain_spec.hash())
+        };
+    }
+
+    /// The gossipsub topic all messages on this network are published to.
+    pub fn topic(&self) -> String {
+        format!("{}-{}", self.network_name, self.chain_id)
+    }
+}
+
+fn default_address_hrp() -> String {
+    TESTNET_ADDRESS_HRP.to_string()
+}
+
+fn default_rpc_address() -> Option<String> {
+    Some(DEFAULT_RPC_ADDRESS.to_string())
+}
+
+impl Default for NetworkConfig {
+    fn default() -> NetworkConfig {
+        NetworkConfig::testnet()
+    }
+}
@@ -1,18 +1,36 @@
-#[allow(unused_imports)]
 use crate::account::AccountState;
 use crate::handler::{CommandHandler, MessageHandler};
 use crate::network::command_utils::Command;
 use crate::network::message;
 use crate::network::message_types::MessageType;
-use libp2p::gossipsub::GossipsubMessage;
-use libp2p::{identity, PeerId};
+use crate::network::protocol::VrrbNetworkBehavior;
+use crate::pool::{Pool, PoolKind};
+use libp2p::swarm::Swarm;
+use libp2p::{identity, Multiaddr, PeerId};
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::error::Error;
+use std::str::FromStr;
+use std::time::Duration;
 
-pub const MAX_TRANSMIT_SIZE: usize = 65000;
+/// The largest message gossipsub will publish or accept by default. Chunking
+/// is derived from it below, so the node config can raise the transmit limit
+/// `configure_swarm` hands to gossipsub but not lower it below this.
+pub const MAX_TRANSMIT_SIZE: usize = 2_000_000;
+/// Bytes reserved in every published message for the wire frame header, the
+/// fields of the chunk message wrapping the data and the gossipsub envelope
+/// (source, sequence number, topic, signature and key).
+pub const CHUNK_HEADROOM: usize = 4096;
+/// The largest slice of a serialized object carried by a single chunk message.
+pub const MAX_CHUNK_SIZE: usize = MAX_TRANSMIT_SIZE - CHUNK_HEADROOM;
+/// The most peers from the address book redialed when the node starts.
+pub const MAX_REDIAL_PEERS: usize = 25;
+/// How long the swarm keeps running after QUIT, to deliver the disconnect
+/// message to the peers.
+pub const DISCONNECT_GRACE_PERIOD: Duration = Duration::from_secs(1);
 
 #[allow(dead_code)]
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub enum NodeAuth {
     // Builds a full block archive all blocks and all claims
     Archive,
@@ -33,7 +51,27 @@ pub struct Node {
     pub id: PeerId,
     pub node_type: NodeAuth,
     pub command_handler: CommandHandler,
-    pub message_handler: MessageHandler<MessageType, GossipsubMessage>,
+    pub message_handler: MessageHandler<MessageType, MessageType>,
+    // The wallet pubkey of this node, announced to the peers when it leaves.
+    pub pubkey: String,
+    // Tracks which peers announced which claims so that the claims of peers
+    // this node loses can be pruned.
+    pub account_state: AccountState,
+}
+
+impl FromStr for NodeAuth {
+    type Err = String;
+
+    fn from_str(node_type: &str) -> Result<NodeAuth, String> {
+        match node_type.to_lowercase().as_str() {
+            "archive" => Ok(NodeAuth::Archive),
+            "full" => Ok(NodeAuth::Full),
+            "light" => Ok(NodeAuth::Light),
+            "ultra-light" | "ultralight" => Ok(NodeAuth::UltraLight),
+            "bootstrap" => Ok(NodeAuth::Bootstrap),
+            _ => Err(format!("unknown node type {}", node_type)),
+        }
+    }
 }
 
 impl Node {
@@ -48,7 +86,8 @@ impl Node {
     pub fn new(
         node_type: NodeAuth,
         command_handler: CommandHandler,
-        message_handler: MessageHandler<MessageType, GossipsubMessage>,
+        message_handler: MessageHandler<MessageType, MessageType>,
+        pubkey: String,
     ) -> Node {
         let local_key = identity::Keypair::generate_ed25519();
         let local_peer_id = PeerId::from(local_key.public());
@@ -59,6 +98,109 @@ impl Node {
             node_type,
             command_handler,
             message_handler,
+            pubkey,
+            account_state: AccountState::start(
+                Pool::new(PoolKind::Txn),
+                Pool::new(PoolKind::Claim),
+            ),
+        }
+    }
+
+    /// Associates the peer that published a claim with the claim's pubkey.
+    fn track_claim(&mut self, peer_id: String, pubkey: String) {
+        self.account_state
+            .add_miner_to_peer_tracker(peer_id, pubkey.clone());
+        self.account_state.add_miner_to_claim_counter(pubkey);
+    }
+
+    /// Prunes the claims of the miners this node is no longer connected to.
+    /// Only this node saw the peer time out, so nothing is gossiped, the other
+    /// nodes prune on their own timeouts or on the miner's disconnect message.
+    fn handle_peer_disconnected(&mut self, peer_id: String, connected_peers: HashSet<String>) {
+        println!("Lost peer {}", peer_id);
+        let tracked = self
+            .account_state
+            .claim_counter
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        self.account_state
+            .prune_miners(connected_peers, &self.pubkey);
+
+        let pruned = tracked
+            .into_iter()
+            .filter(|pubkey| !self.account_state.claim_counter.contains_key(pubkey))
+            .collect::<Vec<_>>();
+        pruned
+            .into_iter()
+            .for_each(|pubkey| self.prune_miner(pubkey));
+    }
+
+    fn prune_miner(&mut self, pubkey: String) {
+        self.account_state.claim_counter.remove(&pubkey);
+        self.account_state
+            .peer_tracker
+            .retain(|_, tracked| *tracked != pubkey);
+
+        if let Err(e) = self
+            .command_handler
+            .to_mining_sender
+            .send(Command::PruneMiner(pubkey))
+        {
+            println!("Error sending PruneMiner command to miner: {:?}", e);
+        }
+    }
+
+    fn inform_peers_of_shutdown(&self) {
+        let message = MessageType::DisconnectMessage {
+            sender_id: self.id.to_string(),
+            pubkey: self.pubkey.clone(),
+        };
+
+        if let Err(e) = self
+            .command_handler
+            .to_swarm_sender
+            .send(Command::SendMessage(message.as_bytes()))
+        {
+            println!("Error publishing disconnect message: {:?}", e);
+        }
+    }
+
+    /// Dials the known good peers in the address book and the network's bootstrap
+    /// nodes, so that a restarted node rejoins the network on its own.
+    pub fn redial_known_peers(
+        &self,
+        swarm: &mut Swarm<VrrbNetworkBehavior>,
+        bootstrap_nodes: &[String],
+    ) {
+        let known_good = swarm
+            .behaviour()
+            .address_book
+            .known_good(MAX_REDIAL_PEERS);
+
+        for (peer_id, addrs) in known_good {
+            if peer_id == self.id {
+                continue;
+            }
+
+            for addr in addrs {
+                swarm.behaviour_mut().kademlia.add_address(&peer_id, addr);
+            }
+
+            match swarm.dial(&peer_id) {
+                Ok(_) => println!("Redialed known peer {:?}", peer_id),
+                Err(e) => println!("Redial of known peer {:?} failed: {:?}", peer_id, e),
+            }
+        }
+
+        for bootstrap_node in bootstrap_nodes {
+            match bootstrap_node.parse::<Multiaddr>() {
+                Ok(addr) => match swarm.dial_addr(addr) {
+                    Ok(_) => println!("Dialed bootstrap node {:?}", bootstrap_node),
+                    Err(e) => println!("Dial bootstrap node {:?} failed: {:?}", bootstrap_node, e),
+                },
+                Err(e) => println!("Failed to parse bootstrap node address {:?}", e),
+            }
         }
     }
 
@@ -96,15 +238,10 @@ impl Node {
                         }
                     }
                     Command::Quit => {
-                        //TODO:
-                        // 1. Inform peers. DONE
-                        // 2. Before Ok(()) at the end of this method
-                        //    be sure to join all the threads in this method by setting them to variables
-                        //    and winding them down at the end after exiting this event loop.
-                        // 3. Print out the node's wallet secret key, the state db filepath and the
-                        //    block archive filepath so users can restore their wallet and state
-                        //    when rejoining.
-
+                        // Tell the peers this node is leaving, then stop the
+                        // other threads, which store what they hold first.
+                        self.inform_peers_of_shutdown();
+                        self.command_handler.handle_command(Command::Quit);
                         break;
                     }
                     Command::SendAddress => {
@@ -134,6 +271,24 @@ impl Node {
                             println!("Error sending state request to blockchain thread: {:?}", e);
                         }
                     }
+                    Command::DisconnectPeer(peer_id) => {
+                        if let Err(e) = self
+                            .command_handler
+                            .to_swarm_sender
+                            .send(Command::DisconnectPeer(peer_id))
+                        {
+                            println!("Error sending DisconnectPeer command to swarm: {:?}", e);
+                        }
+                    }
+                    Command::PeerClaim(peer_id, pubkey) => {
+                        self.track_claim(peer_id, pubkey);
+                    }
+                    Command::PeerDisconnected(peer_id, connected_peers) => {
+                        self.handle_peer_disconnected(peer_id, connected_peers);
+                    }
+                    Command::PruneMiner(pubkey) => {
+                        self.prune_miner(pubkey);
+                    }
                     Command::StoreStateDbChunk(object, data, chunk_number, total_chunks) => {
                         if let Err(e) = self.command_handler.to_blockchain_sender.send(
                             Command::StoreStateDbChunk(object, data, chunk_number, total_chunks),
@@ -1,20 +1,24 @@
+use crate::network::address_book::AddressBook;
 use crate::network::command_utils::Command;
+use crate::network::connection_manager::{ConnectionManager, PeerStatus};
+use crate::network::message_types::MessageType;
+use crate::network::network_config::NetworkConfig;
 use libp2p::{
     core::{
         muxing::StreamMuxerBox, transport::upgrade::Version, transport::Boxed,
         upgrade::SelectUpgrade,
     },
     dns::DnsConfig,
-    gossipsub::{Gossipsub, GossipsubEvent, GossipsubMessage},
+    gossipsub::{Gossipsub, GossipsubEvent},
     identify::{Identify, IdentifyEvent},
     identity,
     kad::record::store::MemoryStore,
     kad::{Kademlia, KademliaEvent, QueryResult},
+    mdns::{Mdns, MdnsEvent},
     mplex::MplexConfig,
     noise,
-    ping::handler::PingFailure,
     ping::{Ping, PingEvent},
-    swarm::NetworkBehaviourEventProcess,
+    swarm::{toggle::Toggle, NetworkBehaviourEventProcess},
     tcp::TcpConfig,
     websocket::WsConfig,
     yamux::YamuxConfig,
@@ -42,16 +46,23 @@ pub struct VrrbNetworkBehavior {
     pub identify: Identify,
     pub kademlia: Kademlia<MemoryStore>,
     pub ping: Ping,
+    pub mdns: Toggle<Mdns>,
     #[behaviour(ignore)]
     pub command_sender: mpsc::UnboundedSender<Command>,
     #[behaviour(ignore)]
-    pub message_sender: mpsc::UnboundedSender<GossipsubMessage>,
+    pub message_sender: mpsc::UnboundedSender<MessageType>,
     #[behaviour(ignore)]
     pub pubkey: String,
     #[behaviour(ignore)]
     pub address: String,
     #[behaviour(ignore)]
     pub path: String,
+    #[behaviour(ignore)]
+    pub network_config: NetworkConfig,
+    #[behaviour(ignore)]
+    pub address_book: AddressBook,
+    #[behaviour(ignore)]
+    pub connection_manager: ConnectionManager,
 }
 
 impl NetworkBehaviourEventProcess<IdentifyEvent> for VrrbNetworkBehavior {
@@ -62,9 +73,36 @@ impl NetworkBehaviourEventProcess<IdentifyEvent> for VrrbNetworkBehavior {
         };
         match event {
             IdentifyEvent::Received { peer_id, info } => {
+                if let Err(e) = self.network_config.check_protocol_id(&info.protocol_version) {
+                    // The peer may be reconfigured to this node's network, so it
+                    // isn't banned, only not dialed again before a backoff that
+                    // grows with every mismatch.
+                    let attempt = self.address_book.mismatch_count(&peer_id) + 1;
+                    let backoff = self.connection_manager.backoff(attempt);
+                    info!(
+                        "Disconnecting from peer {:?} for {:?}: {}",
+                        peer_id, backoff, e
+                    );
+                    self.kademlia.remove_peer(&peer_id);
+                    self.connection_manager.disconnect(&peer_id);
+                    self.address_book
+                        .record_mismatch(&peer_id, &info.protocol_version, backoff);
+                    if let Err(e) = self
+                        .command_sender
+                        .send(Command::DisconnectPeer(peer_id.to_string()))
+                    {
+                        println!("Error sending DisconnectPeer command to node: {:?}", e);
+                    }
+                    return;
+                }
+
                 for addr in &info.listen_addrs {
                     self.kademlia.add_address(&peer_id, addr.clone());
                 }
+                self.address_book.clear_mismatch(&peer_id);
+                self.address_book.add_addresses(&peer_id, &info.listen_addrs);
+                self.address_book.record_success(&peer_id);
+                self.connection_manager.record_success(&peer_id);
                 self.kademlia.bootstrap().unwrap();
             }
             _ => {}
@@ -83,7 +121,41 @@ impl NetworkBehaviourEventProcess<GossipsubEvent> for VrrbNetworkBehavior {
                 message_id: _id,
                 message,
             } => {
-                if let Err(_) = self.message_sender.send(message) {
+                // Decoded once here, the message handling thread gets the
+                // decoded message.
+                let decoded = match MessageType::from_bytes(&message.data) {
+                    Some(decoded) => decoded,
+                    None => {
+                        info!("Dropping gossip message that failed to decode");
+                        return;
+                    }
+                };
+
+                if !accepts_message(&self.address_book, message.source.as_ref(), &decoded) {
+                    info!(
+                        "Dropping disconnect message from {:?}, which never announced the claim",
+                        message.source
+                    );
+                    return;
+                }
+
+                // Remember which claim the publishing peer announced so that it
+                // can be associated with the peer across restarts, and so that
+                // the node can prune the claim if it loses the peer.
+                if let (Some(source), MessageType::ClaimMessage { claim, .. }) =
+                    (message.source, &decoded)
+                {
+                    self.address_book
+                        .set_claim_pubkey(&source, claim.pubkey.clone());
+                    if let Err(e) = self
+                        .command_sender
+                        .send(Command::PeerClaim(source.to_string(), claim.pubkey.clone()))
+                    {
+                        println!("Error sending PeerClaim command to node: {:?}", e);
+                    }
+                }
+
+                if let Err(_) = self.message_sender.send(decoded) {
                     println!("Error sending message to message handling thread");
                 };
             }
@@ -100,22 +172,32 @@ impl NetworkBehaviourEventProcess<PingEvent> for VrrbNetworkBehavior {
         match event {
             PingEvent { result, peer } => {
                 match result {
-                    Ok(_) => {}
+                    Ok(_) => {
+                        self.address_book.record_success(&peer);
+                        self.connection_manager.record_success(&peer);
+                    }
                     Err(failure) => {
-                        match failure {
-                            PingFailure::Timeout => {
-                                //TODO: Dial again and try again, keep track of failures and
-                                // if it fails three times then drop peer.
+                        self.address_book.record_failure(&peer);
+                        match self.connection_manager.record_failure(&peer) {
+                            PeerStatus::Redial(backoff) => {
+                                info!(
+                                    "Ping to peer {:?} failed ({:?}), redialing in {:?}",
+                                    peer, failure, backoff
+                                );
+                            }
+                            PeerStatus::Disconnected => {
+                                info!("Peer {:?} stopped responding to pings, dropping it", peer);
                                 self.kademlia.remove_peer(&peer);
-                                {
+                                if let Err(e) = self.command_sender.send(Command::PeerDisconnected(
+                                    peer.to_string(),
+                                    self.connection_manager.connected_peers(),
+                                )) {
                                     println!(
-                                        "Error sending remove peer command to command receiver"
+                                        "Error sending PeerDisconnected command to node: {:?}",
+                                        e
                                     );
                                 }
                             }
-                            PingFailure::Other { .. } => {
-                                self.kademlia.remove_peer(&peer);
-                            }
                         }
                     }
                 }
@@ -124,6 +206,38 @@ impl NetworkBehaviourEventProcess<PingEvent> for VrrbNetworkBehavior {
     }
 }
 
+impl NetworkBehaviourEventProcess<MdnsEvent> for VrrbNetworkBehavior {
+    // Only emitted when mDNS is enabled in the network config.
+    fn inject_event(&mut self, event: MdnsEvent) {
+        if let Err(_) = write_to_json(self.path.clone(), &event) {
+            info!("Error writing to json in MdnsEvent");
+        }
+        match event {
+            MdnsEvent::Discovered(discovered) => {
+                let mut found_new = false;
+                for (peer_id, addr) in discovered {
+                    info!("Discovered local peer {:?} at {:?}", peer_id, addr);
+                    self.kademlia.add_address(&peer_id, addr.clone());
+                    self.address_book.add_addresses(&peer_id, &[addr]);
+                    found_new = true;
+                }
+                // Bootstrapping connects to the newly added peers.
+                if found_new {
+                    if let Err(e) = self.kademlia.bootstrap() {
+                        info!("Unable to bootstrap kademlia after mDNS discovery: {:?}", e);
+                    }
+                }
+            }
+            MdnsEvent::Expired(expired) => {
+                for (peer_id, addr) in expired {
+                    info!("Local peer {:?} at {:?} expired", peer_id, addr);
+                    self.kademlia.remove_address(&peer_id, &addr);
+                }
+            }
+        }
+    }
+}
+
 impl NetworkBehaviourEventProcess<KademliaEvent> for VrrbNetworkBehavior {
     fn inject_event(&mut self, event: KademliaEvent) {
         if let Err(_) = write_to_json(self.path.clone(), &event) {
@@ -205,6 +319,24 @@ impl NetworkBehaviourEventProcess<KademliaEvent> for VrrbNetworkBehavior {
     }
 }
 
+/// Whether a gossip message published by `source` is handled. A disconnect
+/// message prunes the claim it names, so it is only taken from the peer that
+/// announced the claim, anyone else could drop miners from every node's claim
+/// map with it. Gossipsub signs the messages, so the source can't be forged.
+pub fn accepts_message(
+    address_book: &AddressBook,
+    source: Option<&PeerId>,
+    message: &MessageType,
+) -> bool {
+    match (message, source) {
+        (MessageType::DisconnectMessage { pubkey, .. }, Some(source)) => {
+            address_book.announced_claim(source, pubkey)
+        }
+        (MessageType::DisconnectMessage { .. }, None) => false,
+        _ => true,
+    }
+}
+
 pub async fn build_transport(
     key_pair: identity::Keypair,
 ) -> Result<Boxed<(PeerId, StreamMuxerBox)>, Error> {
@@ -235,32 +367,16 @@ pub async fn build_transport(
 }
 
 pub fn write_to_json<T: Debug>(path: String, event: &T) -> Result<(), serde_json::Error> {
-    let content = fs::read_to_string(path.clone());
-    if let Ok(string) = content {
-        let result: Result<Vec<VrrbNetworkEvent>, serde_json::Error> =
-            serde_json::from_str(&string);
-        if let Ok(mut events) = result {
-            let new_event = get_event(event);
-            events.push(new_event);
-            if events.len() > 100 {
-                events.remove(0);
-            }
-            let json_vec = serde_json::to_vec(&events);
-            if let Ok(json) = json_vec {
-                if let Err(e) = fs::write(path.clone(), json) {
-                    info!("Error writing event to events.json: {:?}", e);
-                }
-            }
-        } else {
-            let new_event = get_event(event);
-            let events = vec![new_event];
-            let json_vec = serde_json::to_vec(&events);
-            if let Ok(json) = json_vec {
-                if let Err(e) = fs::write(path.clone(), json) {
-                    info!("Error writing event to events.json: {:?}", e);
-                }
-            }
-        }
+    // The events file is started over when it is missing or unreadable.
+    let string = fs::read_to_string(path.clone()).unwrap_or_default();
+    let mut events: Vec<VrrbNetworkEvent> = serde_json::from_str(&string).unwrap_or_default();
+    events.push(get_event(event));
+    if events.len() > 100 {
+        events.remove(0);
+    }
+    let json = serde_json::to_vec(&events)?;
+    if let Err(e) = fs::write(path.clone(), json) {
+        info!("Error writing event to events.json: {:?}", e);
     }
     Ok(())
 }
@@ -268,4 +384,37 @@ pub fn write_to_json<T: Debug>(path: String, event: &T) -> Result<(), serde_json
 pub fn get_event<T: Debug>(event: &T) -> VrrbNetworkEvent {
     let event_string = format!("{:?}", event);
     VrrbNetworkEvent::VrrbProtocolEvent { event: event_string }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempDb;
+
+    #[test]
+    fn test_disconnect_message_is_only_taken_from_the_peer_of_the_claim() {
+        let db = TempDb::new("protocol_address_book");
+        let mut address_book = AddressBook::restore(db.path());
+        let miner = PeerId::random();
+        let third_party = PeerId::random();
+        address_book.set_claim_pubkey(&miner, "miner pubkey".to_string());
+        address_book.set_claim_pubkey(&third_party, "third party pubkey".to_string());
+
+        let disconnect = MessageType::DisconnectMessage {
+            sender_id: third_party.to_string(),
+            pubkey: "miner pubkey".to_string(),
+        };
+        assert!(!accepts_message(
+            &address_book,
+            Some(&third_party),
+            &disconnect
+        ));
+        assert!(!accepts_message(&address_book, None, &disconnect));
+        assert!(!accepts_message(
+            &address_book,
+            Some(&PeerId::random()),
+            &disconnect
+        ));
+        assert!(accepts_message(&address_book, Some(&miner), &disconnect));
+    }
+}
@@ -0,0 +1,162 @@
+use bincode::Options;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::{self, Read, Write};
+use thiserror::Error;
+
+/// Version of the framing below. Bump this whenever the layout of the frame or
+/// the encoding of the payload changes so that older nodes reject the message
+/// instead of misreading it.
+pub const WIRE_VERSION: u8 = 1;
+/// Set in the flags byte when the payload has been deflate compressed.
+pub const FLAG_COMPRESSED: u8 = 0b0000_0001;
+/// The version byte and the flags byte.
+pub const FRAME_HEADER_LEN: usize = 2;
+/// Payloads smaller than this are sent uncompressed, deflate rarely pays for
+/// itself on small messages like txns and validator votes.
+pub const COMPRESSION_THRESHOLD: usize = 1024;
+/// Upper bound on the size of a decoded payload. Frames come straight off the
+/// network, this keeps a bogus length prefix or a deflate bomb from making the
+/// node allocate without limit.
+pub const MAX_PAYLOAD_LEN: u64 = 32 * 1024 * 1024;
+
+#[derive(Error, Debug)]
+pub enum WireError {
+    #[error("frame is {0} bytes, shorter than the frame header")]
+    FrameTooShort(usize),
+    #[error("unsupported wire version {0}, expected {}", WIRE_VERSION)]
+    UnsupportedVersion(u8),
+    #[error("unknown frame flags {0:#010b}")]
+    UnknownFlags(u8),
+    #[error("error compressing or decompressing the payload: {0}")]
+    Compression(#[from] io::Error),
+    #[error("decompressed payload exceeds {} bytes", MAX_PAYLOAD_LEN)]
+    PayloadTooLarge,
+    #[error("error encoding or decoding the payload: {0}")]
+    Codec(#[from] bincode::Error),
+}
+
+/// Encodes a value into a wire frame:
+///
+/// ```text
+/// +---------+-------+-----------------------------+
+/// | version | flags | bincode payload (maybe zip) |
+/// +---------+-------+-----------------------------+
+///   1 byte   1 byte   remaining bytes
+/// ```
+pub fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, WireError> {
+    let payload = codec().serialize(value)?;
+    let mut flags = 0u8;
+
+    let payload = if payload.len() >= COMPRESSION_THRESHOLD {
+        let compressed = compress(&payload)?;
+        if compressed.len() < payload.len() {
+            flags |= FLAG_COMPRESSED;
+            compressed
+        } else {
+            payload
+        }
+    } else {
+        payload
+    };
+
+    let mut frame = Vec::with_capacity(FRAME_HEADER_LEN + payload.len());
+    frame.push(WIRE_VERSION);
+    frame.push(flags);
+    frame.extend(payload);
+
+    Ok(frame)
+}
+
+/// Decodes a frame produced by `encode`, checking the version and flags
+/// before touching the payload.
+pub fn decode<T: DeserializeOwned>(frame: &[u8]) -> Result<T, WireError> {
+    if frame.len() < FRAME_HEADER_LEN {
+        return Err(WireError::FrameTooShort(frame.len()));
+    }
+
+    let version = frame[0];
+    let flags = frame[1];
+
+    if version != WIRE_VERSION {
+        return Err(WireError::UnsupportedVersion(version));
+    }
+
+    if flags & !FLAG_COMPRESSED != 0 {
+        return Err(WireError::UnknownFlags(flags));
+    }
+
+    let payload = &frame[FRAME_HEADER_LEN..];
+    if flags & FLAG_COMPRESSED != 0 {
+        let decompressed = decompress(payload)?;
+        Ok(codec().deserialize(&decompressed)?)
+    } else {
+        Ok(codec().deserialize(payload)?)
+    }
+}
+
+fn codec() -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .with_limit(MAX_PAYLOAD_LEN)
+}
+
+fn compress(data: &[u8]) -> Result<Vec<u8>, io::Error> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+fn decompress(data: &[u8]) -> Result<Vec<u8>, WireError> {
+    let mut decoder = DeflateDecoder::new(data).take(MAX_PAYLOAD_LEN + 1);
+    let mut buffer = Vec::new();
+    decoder.read_to_end(&mut buffer)?;
+    if buffer.len() as u64 > MAX_PAYLOAD_LEN {
+        return Err(WireError::PayloadTooLarge);
+    }
+    Ok(buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_small_payload_round_trip_uncompressed() {
+        let value = ("txn".to_string(), 100u128);
+        let frame = encode(&value).unwrap();
+        assert_eq!(frame[0], WIRE_VERSION);
+        assert_eq!(frame[1] & FLAG_COMPRESSED, 0);
+        assert_eq!(decode::<(String, u128)>(&frame).unwrap(), value);
+    }
+
+    #[test]
+    fn test_large_payload_round_trip_compressed() {
+        let value = vec![7u8; COMPRESSION_THRESHOLD * 4];
+        let frame = encode(&value).unwrap();
+        assert_eq!(frame[1] & FLAG_COMPRESSED, FLAG_COMPRESSED);
+        assert!(frame.len() < value.len());
+        assert_eq!(decode::<Vec<u8>>(&frame).unwrap(), value);
+    }
+
+    #[test]
+    fn test_rejects_unknown_version() {
+        let mut frame = encode(&1u8).unwrap();
+        frame[0] = WIRE_VERSION + 1;
+        assert!(matches!(
+            decode::<u8>(&frame),
+            Err(WireError::UnsupportedVersion(_))
+        ));
+    }
+
+    #[test]
+    fn test_rejects_short_frame() {
+        assert!(matches!(
+            decode::<u8>(&[WIRE_VERSION]),
+            Err(WireError::FrameTooShort(1))
+        ));
+    }
+}
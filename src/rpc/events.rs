@@ -0,0 +1,192 @@
+use crate::block::Block;
+use crate::blockchain::InvalidBlockErrorReason;
+use crate::reward::RewardState;
+use crate::rpc::{RpcError, JSONRPC_VERSION};
+use crate::txn::Txn;
+use ritelinked::LinkedHashMap;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use tokio::sync::broadcast;
+
+/// How many events a slow subscriber can fall behind before it misses some.
+pub const EVENT_BUFFER_SIZE: usize = 1024;
+/// The method of the JSON-RPC notifications carrying events.
+pub const EVENT_METHOD: &str = "event";
+/// The method of the notification sent when a subscriber fell behind and
+/// missed events, it should catch up by querying the node.
+pub const LAGGED_METHOD: &str = "lagged";
+
+/// Something that happened on the node that clients can subscribe to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Event {
+    NewBlock {
+        block: Box<Block>,
+    },
+    PendingTxn {
+        txn: Txn,
+    },
+    InvalidBlock {
+        block_hash: String,
+        block_height: u128,
+        miner: String,
+        reason: InvalidBlockErrorReason,
+    },
+    RewardUpdate {
+        reward_state: RewardState,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Topic {
+    NewBlocks,
+    PendingTxns,
+    InvalidBlocks,
+    Rewards,
+}
+
+/// What one subscription wants to hear about. Pending txns can be narrowed to
+/// those sent from or to some addresses, an empty set means all of them.
+#[derive(Debug, Clone)]
+pub struct Subscription {
+    pub topic: Topic,
+    pub addresses: HashSet<String>,
+}
+
+/// The subscriptions of one client connection, by subscription id.
+#[derive(Debug, Default)]
+pub struct Subscriptions {
+    next_id: u64,
+    active: LinkedHashMap<u64, Subscription>,
+}
+
+/// The JSON-RPC notification carrying an event to one subscription.
+#[derive(Debug, Serialize)]
+struct EventNotification<'a> {
+    jsonrpc: &'static str,
+    method: &'static str,
+    params: EventParams<'a>,
+}
+
+#[derive(Debug, Serialize)]
+struct EventParams<'a> {
+    subscription: u64,
+    result: &'a Event,
+}
+
+/// Publishes events from the node's threads to every subscribed connection.
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<Event>,
+}
+
+impl Event {
+    pub fn topic(&self) -> Topic {
+        match self {
+            Event::NewBlock { .. } => Topic::NewBlocks,
+            Event::PendingTxn { .. } => Topic::PendingTxns,
+            Event::InvalidBlock { .. } => Topic::InvalidBlocks,
+            Event::RewardUpdate { .. } => Topic::Rewards,
+        }
+    }
+
+    pub fn invalid_block(block: &Block, reason: InvalidBlockErrorReason) -> Event {
+        Event::InvalidBlock {
+            block_hash: block.hash.clone(),
+            block_height: block.header.block_height,
+            miner: block.header.claim.pubkey.clone(),
+            reason,
+        }
+    }
+}
+
+impl Topic {
+    pub fn parse(topic: &str) -> Result<Topic, RpcError> {
+        match topic {
+            "newBlocks" => Ok(Topic::NewBlocks),
+            "pendingTxns" => Ok(Topic::PendingTxns),
+            "invalidBlocks" => Ok(Topic::InvalidBlocks),
+            "rewards" => Ok(Topic::Rewards),
+            _ => Err(RpcError::InvalidParams(format!("unknown topic {}", topic))),
+        }
+    }
+}
+
+impl Subscription {
+    pub fn matches(&self, event: &Event) -> bool {
+        if event.topic() != self.topic {
+            return false;
+        }
+
+        match event {
+            Event::PendingTxn { txn } if !self.addresses.is_empty() => {
+                self.addresses.contains(&txn.sender_address)
+                    || self.addresses.contains(&txn.receiver_address)
+            }
+            _ => true,
+        }
+    }
+}
+
+impl Subscriptions {
+    pub fn subscribe(&mut self, subscription: Subscription) -> u64 {
+        self.next_id += 1;
+        self.active.insert(self.next_id, subscription);
+        self.next_id
+    }
+
+    pub fn unsubscribe(&mut self, id: u64) -> bool {
+        self.active.remove(&id).is_some()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.active.is_empty()
+    }
+
+    /// The JSON-RPC notifications to send for `event`, one per subscription
+    /// it matches, serialized straight to JSON since events are full of u128s
+    /// a `Value` can't hold.
+    pub fn notifications(&self, event: &Event) -> serde_json::Result<Vec<String>> {
+        self.active
+            .iter()
+            .filter(|(_, subscription)| subscription.matches(event))
+            .map(|(id, _)| {
+                serde_json::to_string(&EventNotification {
+                    jsonrpc: JSONRPC_VERSION,
+                    method: EVENT_METHOD,
+                    params: EventParams {
+                        subscription: *id,
+                        result: event,
+                    },
+                })
+            })
+            .collect()
+    }
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> EventBus {
+        let (sender, _) = broadcast::channel(capacity);
+        EventBus { sender }
+    }
+
+    /// Sends `event` to every subscriber. Events published while nobody is
+    /// subscribed are dropped.
+    pub fn publish(&self, event: Event) {
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+}
+
+/// The notification telling a subscriber it missed `missed` events.
+pub fn lagged_notification(missed: u64) -> Value {
+    json!({
+        "jsonrpc": JSONRPC_VERSION,
+        "method": LAGGED_METHOD,
+        "params": { "missed": missed },
+    })
+}
@@ -0,0 +1,202 @@
+//! A JSON-RPC 2.0 API for controlling and querying a running node. Requests
+//! and responses are newline-delimited JSON objects exchanged over a TCP
+//! connection, one request per line and one response line per request, so any
+//! language with a socket and a JSON parser can talk to the node:
+//!
+//! ```text
+//! -> {"jsonrpc":"2.0","id":1,"method":"getBalance","params":["tvrrb1..."]}
+//! <- {"jsonrpc":"2.0","id":1,"result":{"confirmed":0,"pending_in":0,...}}
+//! ```
+//!
+//! Amounts, heights and nonces are u128s and are written as plain JSON
+//! numbers, which clients should parse without going through a double. In
+//! params the node reads integers as JSON numbers up to u64::MAX only, larger
+//! ones have to be sent as decimal strings, e.g. `"18446744073709551616"`.
+//!
+//! Instead of polling, clients can `subscribe` to a topic (new blocks, pending
+//! txns for some addresses, invalid blocks or reward updates), after which the
+//! node pushes the matching `events::Event`s to them as notifications on the
+//! same connection:
+//!
+//! ```text
+//! -> {"jsonrpc":"2.0","id":2,"method":"subscribe","params":["newBlocks"]}
+//! <- {"jsonrpc":"2.0","id":2,"result":1}
+//! <- {"jsonrpc":"2.0","method":"event","params":{"subscription":1,"result":{"type":"newBlock",...}}}
+//! ```
+//!
+//! The server only listens on localhost unless configured otherwise, see
+//! `NetworkConfig::rpc_address`.
+pub mod events;
+pub mod server;
+pub mod view;
+
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use serde_json::Value;
+use thiserror::Error;
+
+pub const JSONRPC_VERSION: &str = "2.0";
+pub const DEFAULT_RPC_ADDRESS: &str = "127.0.0.1:9293";
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+// Server defined, the requested block, txn, etc. doesn't exist.
+pub const NOT_FOUND: i64 = -32001;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum RpcError {
+    #[error("parse error: {0}")]
+    Parse(String),
+    #[error("invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("method not found: {0}")]
+    MethodNotFound(String),
+    #[error("invalid params: {0}")]
+    InvalidParams(String),
+    #[error("not found: {0}")]
+    NotFound(String),
+    #[error("internal error: {0}")]
+    Internal(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RpcRequest {
+    pub jsonrpc: String,
+    #[serde(default)]
+    pub id: Value,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcErrorObject {
+    pub code: i64,
+    pub message: String,
+}
+
+// The result is kept as serialized JSON, a `Value` can't hold the u128s
+// results are full of.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RpcResponse {
+    pub jsonrpc: String,
+    pub id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Box<RawValue>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcErrorObject>,
+}
+
+impl RpcError {
+    pub fn code(&self) -> i64 {
+        match self {
+            RpcError::Parse(_) => PARSE_ERROR,
+            RpcError::InvalidRequest(_) => INVALID_REQUEST,
+            RpcError::MethodNotFound(_) => METHOD_NOT_FOUND,
+            RpcError::InvalidParams(_) => INVALID_PARAMS,
+            RpcError::NotFound(_) => NOT_FOUND,
+            RpcError::Internal(_) => INTERNAL_ERROR,
+        }
+    }
+}
+
+impl RpcResponse {
+    pub fn new(id: Value, result: Result<Box<RawValue>, RpcError>) -> RpcResponse {
+        match result {
+            Ok(result) => RpcResponse {
+                jsonrpc: JSONRPC_VERSION.to_string(),
+                id,
+                result: Some(result),
+                error: None,
+            },
+            Err(e) => RpcResponse {
+                jsonrpc: JSONRPC_VERSION.to_string(),
+                id,
+                result: None,
+                error: Some(RpcErrorObject {
+                    code: e.code(),
+                    message: e.to_string(),
+                }),
+            },
+        }
+    }
+}
+
+/// Serializes a method's result, which unlike `serde_json::to_value` works
+/// for u128s.
+pub fn to_result<T: Serialize>(value: &T) -> Result<Box<RawValue>, RpcError> {
+    serde_json::value::to_raw_value(value).map_err(|e| RpcError::Internal(e.to_string()))
+}
+
+/// The param at `index` of a positional `params` array, or the param called
+/// `name` of a named `params` object.
+pub fn param<'a>(params: &'a Value, index: usize, name: &str) -> Option<&'a Value> {
+    match params {
+        Value::Array(params) => params.get(index),
+        Value::Object(params) => params.get(name),
+        _ => None,
+    }
+}
+
+/// The string param at `index` or called `name`.
+pub fn str_param<'a>(params: &'a Value, index: usize, name: &str) -> Result<&'a str, RpcError> {
+    param(params, index, name)
+        .and_then(|value| value.as_str())
+        .ok_or_else(|| RpcError::InvalidParams(format!("expected a string {}", name)))
+}
+
+/// The unsigned integer param at `index` or called `name`, either a JSON
+/// number or a decimal string. Numbers above u64::MAX are parsed as doubles by
+/// serde_json and lose precision, so they are refused and have to be sent as
+/// strings.
+pub fn u128_param(params: &Value, index: usize, name: &str) -> Result<u128, RpcError> {
+    match param(params, index, name) {
+        Some(Value::Number(number)) => number.as_u64().map(|n| n as u128),
+        Some(Value::String(string)) => string.parse::<u128>().ok(),
+        _ => None,
+    }
+    .ok_or_else(|| {
+        RpcError::InvalidParams(format!(
+            "expected an unsigned integer {}, as a decimal string if above {}",
+            name,
+            u64::MAX
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_u128_params_above_u64_max_are_taken_as_strings() {
+        let above_u64 = u64::MAX as u128 + 1;
+        assert_eq!(u128_param(&json!([7]), 0, "height"), Ok(7));
+        assert_eq!(
+            u128_param(&json!({ "height": u64::MAX }), 0, "height"),
+            Ok(u64::MAX as u128)
+        );
+        assert_eq!(
+            u128_param(&json!(["18446744073709551616"]), 0, "height"),
+            Ok(above_u64)
+        );
+        assert_eq!(
+            u128_param(&json!({ "height": u128::MAX.to_string() }), 0, "height"),
+            Ok(u128::MAX)
+        );
+
+        let params: Value = serde_json::from_str("[18446744073709551616]").unwrap();
+        assert!(matches!(
+            u128_param(&params, 0, "height"),
+            Err(RpcError::InvalidParams(_))
+        ));
+        assert!(matches!(
+            u128_param(&json!(["-1"]), 0, "height"),
+            Err(RpcError::InvalidParams(_))
+        ));
+    }
+}
@@ -0,0 +1,485 @@
+use crate::address::Address;
+use crate::balance::{BalanceService, NATIVE_TOKEN};
+use crate::network::command_utils::Command;
+use crate::network::message_types::MessageType;
+use crate::network::network_config::NetworkConfig;
+use crate::rpc::events::{lagged_notification, EventBus, Subscription, Subscriptions, Topic};
+use crate::rpc::view::NodeView;
+use crate::rpc::{
+    param, str_param, to_result, u128_param, RpcError, RpcRequest, RpcResponse, JSONRPC_VERSION,
+};
+use crate::txn::Txn;
+use log::info;
+use serde::Serialize;
+use serde_json::value::RawValue;
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Answers JSON-RPC requests from the `NodeView` the node's threads keep up to
+/// date and turns the requests that change something into `Command`s, sent
+/// through the same channels the terminal uses. Clients can also subscribe to
+/// the node's `Event`s, which are pushed to them as JSON-RPC notifications on
+/// the same connection.
+#[derive(Clone)]
+pub struct RpcServer {
+    pub view: Arc<Mutex<NodeView>>,
+    pub events: EventBus,
+    pub command_sender: UnboundedSender<Command>,
+    pub swarm_sender: UnboundedSender<Command>,
+    pub node_id: String,
+    // Addresses in requests have to belong to this network.
+    pub network_config: NetworkConfig,
+}
+
+impl RpcServer {
+    pub fn new(
+        view: Arc<Mutex<NodeView>>,
+        events: EventBus,
+        command_sender: UnboundedSender<Command>,
+        swarm_sender: UnboundedSender<Command>,
+        node_id: String,
+        network_config: NetworkConfig,
+    ) -> RpcServer {
+        RpcServer {
+            view,
+            events,
+            command_sender,
+            swarm_sender,
+            node_id,
+            network_config,
+        }
+    }
+
+    /// Accepts connections on `address` until the listener fails, serving each
+    /// one on its own task.
+    pub async fn serve(self, address: &str) -> std::io::Result<()> {
+        let listener = TcpListener::bind(address).await?;
+        info!("JSON-RPC server listening on {}", address);
+        loop {
+            let (socket, peer) = listener.accept().await?;
+            let server = self.clone();
+            tokio::task::spawn(async move {
+                if let Err(e) = server.handle_connection(socket).await {
+                    info!("Error serving JSON-RPC connection from {}: {:?}", peer, e);
+                }
+            });
+        }
+    }
+
+    async fn handle_connection(&self, socket: TcpStream) -> std::io::Result<()> {
+        let (reader, mut writer) = socket.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        let mut subscriptions = Subscriptions::default();
+        let mut events = self.events.subscribe();
+        loop {
+            let output = tokio::select! {
+                line = lines.next_line() => {
+                    let line = if let Some(line) = line? {
+                        line
+                    } else {
+                        return Ok(());
+                    };
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    vec![serde_json::to_string(&self.handle_line(&line, &mut subscriptions))?]
+                }
+                event = events.recv() => match event {
+                    Ok(event) => subscriptions.notifications(&event)?,
+                    Err(RecvError::Lagged(missed)) if !subscriptions.is_empty() => {
+                        vec![lagged_notification(missed).to_string()]
+                    }
+                    Err(RecvError::Lagged(_)) => vec![],
+                    Err(RecvError::Closed) => return Ok(()),
+                },
+            };
+
+            for mut line in output {
+                line.push('\n');
+                writer.write_all(line.as_bytes()).await?;
+            }
+        }
+    }
+
+    /// Handles one line of input, a JSON-RPC request, and returns the response
+    /// to write back. Subscribing and unsubscribing change the
+    /// `subscriptions` of the connection the request came from.
+    pub fn handle_line(&self, line: &str, subscriptions: &mut Subscriptions) -> RpcResponse {
+        let request = match serde_json::from_str::<RpcRequest>(line) {
+            Ok(request) => request,
+            Err(e) => return RpcResponse::new(Value::Null, Err(RpcError::Parse(e.to_string()))),
+        };
+
+        if request.jsonrpc != JSONRPC_VERSION {
+            return RpcResponse::new(
+                request.id,
+                Err(RpcError::InvalidRequest(format!(
+                    "unsupported jsonrpc version {}",
+                    request.jsonrpc
+                ))),
+            );
+        }
+
+        let result = match request.method.as_str() {
+            "subscribe" => self.subscribe(&request.params, subscriptions),
+            "unsubscribe" => u128_param(&request.params, 0, "subscription")
+                .and_then(|id| to_result(&subscriptions.unsubscribe(id as u64))),
+            method => self.dispatch(method, &request.params),
+        };
+        RpcResponse::new(request.id, result)
+    }
+
+    pub fn dispatch(&self, method: &str, params: &Value) -> Result<Box<RawValue>, RpcError> {
+        match method {
+            "getBalance" => self.get_balance(params),
+            "getTxnNonce" => self.get_txn_nonce(params),
+            "getBlockByHeight" => {
+                let height = u128_param(params, 0, "height")?;
+                let block = self
+                    .view()?
+                    .get_block_by_height(height)
+                    .ok_or_else(|| RpcError::NotFound(format!("block at height {}", height)))?;
+                to_result(&block)
+            }
+            "getBlockByHash" => {
+                let hash = str_param(params, 0, "hash")?;
+                let block = self
+                    .view()?
+                    .get_block_by_hash(hash)
+                    .ok_or_else(|| RpcError::NotFound(format!("block {}", hash)))?;
+                to_result(&block)
+            }
+            "getTxn" => {
+                let txn_id = str_param(params, 0, "txn_id")?;
+                let (txn, location) = self
+                    .view()?
+                    .get_txn(txn_id)
+                    .ok_or_else(|| RpcError::NotFound(format!("txn {}", txn_id)))?;
+                to_result(&TxnResult { txn, location })
+            }
+            "sendRawTxn" => self.send_raw_txn(params),
+            "getHeight" => to_result(&self.view()?.height),
+            "getPeers" => {
+                let mut peers = self.view()?.peers.iter().cloned().collect::<Vec<_>>();
+                peers.sort();
+                to_result(&peers)
+            }
+            "getClaims" => to_result(&self.view()?.claims),
+            "getRewardState" => to_result(&self.view()?.reward_state),
+            "startMining" => self.send_command(Command::MineBlock),
+            "stopMining" => self.send_command(Command::StopMine),
+            _ => Err(RpcError::MethodNotFound(method.to_string())),
+        }
+    }
+
+    fn view(&self) -> Result<std::sync::MutexGuard<'_, NodeView>, RpcError> {
+        self.view
+            .lock()
+            .map_err(|_| RpcError::Internal("node view lock poisoned".to_string()))
+    }
+
+    fn get_balance(&self, params: &Value) -> Result<Box<RawValue>, RpcError> {
+        let address = self.address(str_param(params, 0, "address")?)?;
+
+        let view = self.view()?;
+        let network_state = view
+            .network_state
+            .as_ref()
+            .ok_or_else(|| RpcError::Internal("network state not loaded yet".to_string()))?;
+        let balance =
+            BalanceService::new(network_state, &view.txn_pool).balance(&address, NATIVE_TOKEN);
+        to_result(&balance)
+    }
+
+    /// The nonce the next txn from an address has to use.
+    fn get_txn_nonce(&self, params: &Value) -> Result<Box<RawValue>, RpcError> {
+        let address = self.address(str_param(params, 0, "address")?)?;
+
+        let view = self.view()?;
+        let network_state = view
+            .network_state
+            .as_ref()
+            .ok_or_else(|| RpcError::Internal("network state not loaded yet".to_string()))?;
+        let nonce = network_state.next_txn_nonce(&address, &view.txn_pool);
+        to_result(&nonce)
+    }
+
+    /// Broadcasts a signed txn exported with `Txn::export`, like SENDRAWTXN.
+    fn send_raw_txn(&self, params: &Value) -> Result<Box<RawValue>, RpcError> {
+        let txn = Txn::import(str_param(params, 0, "txn")?)
+            .map_err(|e| RpcError::InvalidParams(e.to_string()))?;
+        txn.finalize()
+            .map_err(|e| RpcError::InvalidParams(e.to_string()))?;
+
+        let txn_id = txn.txn_id.clone();
+        let message = MessageType::TxnMessage {
+            txn,
+            sender_id: self.node_id.clone(),
+        };
+        self.swarm_sender
+            .send(Command::SendMessage(message.as_bytes()))
+            .map_err(|e| RpcError::Internal(format!("{:?}", e)))?;
+        to_result(&txn_id)
+    }
+
+    fn send_command(&self, command: Command) -> Result<Box<RawValue>, RpcError> {
+        self.command_sender
+            .send(command)
+            .map_err(|e| RpcError::Internal(format!("{:?}", e)))?;
+        to_result(&true)
+    }
+
+    /// Subscribes to a topic, narrowed to some addresses for pending txns, e.g.
+    /// `["pendingTxns", ["tvrrb1..."]]`, and returns the subscription id that
+    /// comes with every notification.
+    fn subscribe(
+        &self,
+        params: &Value,
+        subscriptions: &mut Subscriptions,
+    ) -> Result<Box<RawValue>, RpcError> {
+        let topic = Topic::parse(str_param(params, 0, "topic")?)?;
+        let addresses = match param(params, 1, "addresses") {
+            Some(Value::Array(addresses)) => addresses
+                .iter()
+                .map(|address| self.address(address.as_str().unwrap_or_default()))
+                .collect::<Result<_, _>>()?,
+            None | Some(Value::Null) => Default::default(),
+            Some(_) => {
+                return Err(RpcError::InvalidParams(
+                    "expected an array of addresses".to_string(),
+                ))
+            }
+        };
+
+        to_result(&subscriptions.subscribe(Subscription { topic, addresses }))
+    }
+
+    // An address param, which has to belong to the node's network.
+    fn address(&self, address: &str) -> Result<String, RpcError> {
+        Address::parse_for_network(
+            address,
+            &self.network_config.address_hrp,
+            &self.network_config.address_prefix,
+        )
+        .map(|_| address.to_string())
+        .map_err(|e| RpcError::InvalidParams(e.to_string()))
+    }
+}
+
+/// The result of getTxn, `location` is where the txn is, see
+/// `NodeView::get_txn`.
+#[derive(Serialize)]
+struct TxnResult {
+    txn: Txn,
+    location: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::balance::Balance;
+    use crate::block::Block;
+    use crate::reward::RewardState;
+    use crate::rpc::events::{Event, EVENT_BUFFER_SIZE};
+    use crate::rpc::{INVALID_PARAMS, METHOD_NOT_FOUND, PARSE_ERROR};
+    use crate::signer::{MockSigner, Signer};
+    use crate::state::NetworkState;
+    use crate::test_utils::{genesis_block, testnet_address, TempDb};
+    use crate::txn::UnsignedTxn;
+    use pickledb::{PickleDb, PickleDbDumpPolicy, SerializationMethod};
+    use serde_json::json;
+    use tokio::sync::mpsc;
+
+    fn server() -> (RpcServer, mpsc::UnboundedReceiver<Command>) {
+        let (command_sender, command_receiver) = mpsc::unbounded_channel();
+        let (swarm_sender, _) = mpsc::unbounded_channel();
+        let server = RpcServer::new(
+            Arc::new(Mutex::new(NodeView::new())),
+            EventBus::new(EVENT_BUFFER_SIZE),
+            command_sender,
+            swarm_sender,
+            "node".to_string(),
+            NetworkConfig::testnet(),
+        );
+        (server, command_receiver)
+    }
+
+    // The result of a successful response, as the JSON the client reads.
+    fn result(response: RpcResponse) -> String {
+        match (response.result, response.error) {
+            (Some(result), None) => result.get().to_string(),
+            (_, error) => panic!("expected a result, got {:?}", error),
+        }
+    }
+
+    #[test]
+    fn test_requests_are_dispatched_to_methods_and_commands() {
+        let (server, mut command_receiver) = server();
+        let mut subscriptions = Subscriptions::default();
+
+        let response = server.handle_line(
+            r#"{"jsonrpc":"2.0","id":1,"method":"getHeight"}"#,
+            &mut subscriptions,
+        );
+        assert_eq!(response.id, json!(1));
+        assert_eq!(result(response), "null");
+
+        let response = server.handle_line(
+            r#"{"jsonrpc":"2.0","id":2,"method":"startMining"}"#,
+            &mut subscriptions,
+        );
+        assert_eq!(result(response), "true");
+        assert!(matches!(
+            command_receiver.try_recv(),
+            Ok(Command::MineBlock)
+        ));
+
+        let response = server.handle_line(
+            r#"{"jsonrpc":"2.0","id":3,"method":"getBalance","params":{"address":"nope"}}"#,
+            &mut subscriptions,
+        );
+        assert_eq!(response.error.unwrap().code, INVALID_PARAMS);
+        let mainnet_address = Address::from_pubkey("vrrb", &MockSigner::new(2).pubkey());
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 3,
+            "method": "getBalance",
+            "params": [mainnet_address.to_string()],
+        });
+        let response = server.handle_line(&request.to_string(), &mut subscriptions);
+        assert_eq!(response.error.unwrap().code, INVALID_PARAMS);
+
+        let response = server.handle_line(
+            r#"{"jsonrpc":"2.0","id":4,"method":"mineAll"}"#,
+            &mut subscriptions,
+        );
+        assert_eq!(response.error.unwrap().code, METHOD_NOT_FOUND);
+        let response = server.handle_line("{", &mut subscriptions);
+        assert_eq!(response.error.unwrap().code, PARSE_ERROR);
+    }
+
+    #[test]
+    fn test_pending_txns_notified_for_subscribed_addresses_only() {
+        let (server, _) = server();
+        let mut subscriptions = Subscriptions::default();
+        let watched = testnet_address(&MockSigner::new(2));
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "subscribe",
+            "params": ["pendingTxns", [watched]],
+        });
+        let response = server.handle_line(&request.to_string(), &mut subscriptions);
+        if let Some(error) = response.error {
+            panic!("subscribe failed: {}", error.message);
+        }
+
+        let amount = u128::MAX;
+        let txn = |receiver: &str| Event::PendingTxn {
+            txn: UnsignedTxn::new(0, "sender".to_string(), receiver.to_string(), amount, 0)
+                .sign(&MockSigner::new(1))
+                .unwrap(),
+        };
+        let notifications = subscriptions.notifications(&txn(&watched)).unwrap();
+        assert_eq!(notifications.len(), 1);
+        assert!(notifications[0].contains("\"method\":\"event\""));
+        assert!(notifications[0].contains("\"subscription\":1"));
+        assert!(notifications[0].contains(&format!("\"txn_amount\":{}", amount)));
+        assert!(subscriptions
+            .notifications(&txn("someone else"))
+            .unwrap()
+            .is_empty());
+
+        let response = server.handle_line(
+            r#"{"jsonrpc":"2.0","id":2,"method":"unsubscribe","params":[1]}"#,
+            &mut subscriptions,
+        );
+        assert_eq!(result(response), "true");
+        assert!(subscriptions
+            .notifications(&txn(&watched))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_queries_answer_from_a_populated_node_view() {
+        let (server, _) = server();
+        let state_db = TempDb::new("rpc_state");
+        let chain_db = TempDb::new("rpc_chain");
+        let signer = MockSigner::new(1);
+        let address = testnet_address(&signer);
+        // Doesn't fit in a u64, so it can't go through a `Value`.
+        let amount = u64::MAX as u128 + 1;
+
+        let block = genesis_block(&signer);
+        let claim = block.claims[&signer.pubkey()].clone();
+        let mut chain = PickleDb::new(
+            chain_db.path(),
+            PickleDbDumpPolicy::DumpUponRequest,
+            SerializationMethod::Bin,
+        );
+        chain.set(&block.header.last_hash, &block).unwrap();
+        chain.dump().unwrap();
+
+        let mut network_state = NetworkState::restore(state_db.path());
+        let mut ledger = network_state.db_to_ledger();
+        ledger.credits.insert(address.clone(), amount);
+        network_state.update_ledger(ledger, network_state.reward_state);
+        let txn = UnsignedTxn::new(0, address.clone(), "receiver".to_string(), amount, 0)
+            .sign(&signer)
+            .unwrap();
+        let reward_state = RewardState::start();
+        {
+            let mut view = server.view.lock().unwrap();
+            view.chain_db = Some(chain_db.path().to_string());
+            view.add_block(&block);
+            view.network_state = Some(network_state);
+            view.reward_state = Some(reward_state);
+            view.txn_pool
+                .pending
+                .insert(txn.txn_id.clone(), txn.clone());
+            view.claims.insert(claim.pubkey.clone(), claim);
+        }
+        let request = |method: &str, params: Value| {
+            let request = json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params});
+            result(server.handle_line(&request.to_string(), &mut Subscriptions::default()))
+        };
+
+        assert_eq!(request("getHeight", json!([])), "0");
+        let by_height: Block =
+            serde_json::from_str(&request("getBlockByHeight", json!([0]))).unwrap();
+        assert_eq!(by_height.hash, block.hash);
+        let by_hash: Block =
+            serde_json::from_str(&request("getBlockByHash", json!([block.hash]))).unwrap();
+        assert_eq!(by_hash.header.block_height, 0);
+
+        let balance = request("getBalance", json!([address]));
+        assert!(balance.contains(&format!("\"confirmed\":{}", amount)));
+        assert_eq!(
+            serde_json::from_str::<Balance>(&balance).unwrap(),
+            Balance {
+                confirmed: amount,
+                pending_in: 0,
+                pending_out: amount,
+                spendable: 0,
+            }
+        );
+
+        let found = request("getTxn", json!([txn.txn_id]));
+        assert!(found.contains(&format!("\"txn_amount\":{}", amount)));
+        assert!(found.contains("\"location\":\"pending\""));
+        assert_eq!(request("getTxnNonce", json!([address])), "1");
+        assert_eq!(
+            request("getClaims", json!([])),
+            serde_json::to_string(&server.view.lock().unwrap().claims).unwrap()
+        );
+        assert_eq!(
+            request("getRewardState", json!([])),
+            serde_json::to_string(&reward_state).unwrap()
+        );
+    }
+}
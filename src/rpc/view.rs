@@ -0,0 +1,112 @@
+use crate::block::Block;
+use crate::claim::Claim;
+use crate::miner::Miner;
+use crate::pool::{Pool, PoolKind};
+use crate::reward::RewardState;
+use crate::state::NetworkState;
+use crate::txn::Txn;
+use pickledb::{PickleDb, PickleDbDumpPolicy};
+use ritelinked::LinkedHashMap;
+use std::collections::HashSet;
+
+/// What the RPC server knows about the node. The node's threads own the
+/// miner, the blockchain and the swarm, so they keep this view up to date as
+/// they change and the server answers queries from it without waiting on them.
+///
+/// Confirmed blocks aren't kept in memory, only where to find them in the
+/// chain db, which stores each block under the hash of its parent.
+#[derive(Debug, Clone)]
+pub struct NodeView {
+    pub chain_db: Option<String>,
+    pub height: Option<u128>,
+    // K: block hash, V: chain db key of the block.
+    pub block_keys: LinkedHashMap<String, String>,
+    // K: block height, V: block hash.
+    pub block_heights: LinkedHashMap<u128, String>,
+    // K: txn id, V: hash of the block the txn is in.
+    pub txn_blocks: LinkedHashMap<String, String>,
+    pub network_state: Option<NetworkState>,
+    pub reward_state: Option<RewardState>,
+    pub txn_pool: Pool<String, Txn>,
+    pub claims: LinkedHashMap<String, Claim>,
+    pub mining: bool,
+    pub peers: HashSet<String>,
+}
+
+impl NodeView {
+    pub fn new() -> NodeView {
+        NodeView {
+            chain_db: None,
+            height: None,
+            block_keys: LinkedHashMap::new(),
+            block_heights: LinkedHashMap::new(),
+            txn_blocks: LinkedHashMap::new(),
+            network_state: None,
+            reward_state: None,
+            txn_pool: Pool::new(PoolKind::Txn),
+            claims: LinkedHashMap::new(),
+            mining: false,
+            peers: HashSet::new(),
+        }
+    }
+
+    /// Copies the state, pools and claims the miner thread works with.
+    pub fn update_from_miner(&mut self, miner: &Miner) {
+        self.network_state = Some(miner.network_state.clone());
+        self.reward_state = Some(miner.reward_state);
+        self.txn_pool = miner.txn_pool.clone();
+        self.claims = miner.claim_map.clone();
+        self.mining = miner.mining;
+    }
+
+    /// Indexes a confirmed block, which the blockchain thread has already
+    /// written to the chain db.
+    pub fn add_block(&mut self, block: &Block) {
+        let height = block.header.block_height;
+        self.block_keys
+            .insert(block.hash.clone(), block.header.last_hash.clone());
+        self.block_heights.insert(height, block.hash.clone());
+        block.txns.keys().for_each(|txn_id| {
+            self.txn_blocks.insert(txn_id.clone(), block.hash.clone());
+        });
+
+        if self.height < Some(height) {
+            self.height = Some(height);
+        }
+    }
+
+    pub fn get_block_by_hash(&self, hash: &str) -> Option<Block> {
+        let key = self.block_keys.get(hash)?;
+        let db = PickleDb::load_bin(self.chain_db.as_ref()?, PickleDbDumpPolicy::NeverDump).ok()?;
+        db.get::<Block>(key)
+    }
+
+    pub fn get_block_by_height(&self, height: u128) -> Option<Block> {
+        self.get_block_by_hash(self.block_heights.get(&height)?)
+    }
+
+    /// The txn with id `txn_id` and where it is: "pending" or "confirmed" in
+    /// the txn pool, or in the block with the returned hash.
+    pub fn get_txn(&self, txn_id: &str) -> Option<(Txn, String)> {
+        if let Some(txn) = self.txn_pool.pending.get(txn_id) {
+            return Some((txn.clone(), "pending".to_string()));
+        }
+        if let Some(txn) = self.txn_pool.confirmed.get(txn_id) {
+            return Some((txn.clone(), "confirmed".to_string()));
+        }
+
+        let block_hash = self.txn_blocks.get(txn_id)?;
+        let txn = self
+            .get_block_by_hash(block_hash)?
+            .txns
+            .get(txn_id)?
+            .clone();
+        Some((txn, block_hash.clone()))
+    }
+}
+
+impl Default for NodeView {
+    fn default() -> NodeView {
+        NodeView::new()
+    }
+}
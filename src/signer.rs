@@ -0,0 +1,218 @@
+use crate::keystore::{Keystore, KeystoreError};
+use secp256k1::{
+    key::{PublicKey, SecretKey},
+    Message, Secp256k1, Signature,
+};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SignerError {
+    #[error("invalid key or digest: {0}")]
+    Key(#[from] secp256k1::Error),
+    #[error("keystore error: {0}")]
+    Keystore(#[from] KeystoreError),
+    #[error("signer unavailable: {0}")]
+    Unavailable(String),
+}
+
+/// Something that holds a secp256k1 key and signs with it. Wallets, miners and
+/// block headers sign through this trait so the key can live in memory, in an
+/// encrypted keystore, or in a remote or hardware backend without the signing
+/// call sites knowing the difference.
+pub trait Signer: fmt::Debug + Send + Sync {
+    /// The public key of the signing key, as a hex string.
+    fn pubkey(&self) -> String;
+
+    /// Signs a 32 byte digest, see `message_digest`.
+    fn sign_digest(&self, digest: &Message) -> Result<Signature, SignerError>;
+
+    fn sign(&self, message: &str) -> Result<Signature, SignerError> {
+        self.sign_digest(&message_digest(message))
+    }
+}
+
+/// The digest that gets signed for `message`: the message padded with zeros to
+/// at least 32 bytes and hashed with blake3.
+pub fn message_digest(message: &str) -> Message {
+    let mut bytes = message.as_bytes().to_vec();
+    if bytes.len() < 32 {
+        bytes.resize(32, 0);
+    }
+
+    let message_hash = blake3::hash(&bytes);
+    Message::from_slice(message_hash.as_bytes()).expect("blake3 hashes are 32 bytes")
+}
+
+/// Verifies `signature` by `pubkey` over `message`, both hex encoded.
+pub fn verify(message: &str, signature: &str, pubkey: &str) -> bool {
+    let signature = if let Ok(signature) = Signature::from_str(signature) {
+        signature
+    } else {
+        return false;
+    };
+    let pubkey = if let Ok(pubkey) = PublicKey::from_str(pubkey) {
+        pubkey
+    } else {
+        return false;
+    };
+
+    let secp = Secp256k1::verification_only();
+    secp.verify(&message_digest(message), &signature, &pubkey)
+        .is_ok()
+}
+
+/// Signs with a secret key held in memory.
+#[derive(Clone)]
+pub struct KeySigner {
+    secret_key: SecretKey,
+    pubkey: PublicKey,
+}
+
+impl KeySigner {
+    pub fn new(secret_key: SecretKey) -> KeySigner {
+        let secp = Secp256k1::signing_only();
+        let pubkey = PublicKey::from_secret_key(&secp, &secret_key);
+        KeySigner { secret_key, pubkey }
+    }
+
+    pub fn from_hex(secret_key: &str) -> Result<KeySigner, SignerError> {
+        Ok(KeySigner::new(SecretKey::from_str(secret_key)?))
+    }
+}
+
+impl Signer for KeySigner {
+    fn pubkey(&self) -> String {
+        self.pubkey.to_string()
+    }
+
+    fn sign_digest(&self, digest: &Message) -> Result<Signature, SignerError> {
+        let secp = Secp256k1::signing_only();
+        Ok(secp.sign(digest, &self.secret_key))
+    }
+}
+
+// Never print the secret key.
+impl fmt::Debug for KeySigner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("KeySigner")
+            .field("pubkey", &self.pubkey.to_string())
+            .finish()
+    }
+}
+
+/// Signs with the wallet master key stored in an encrypted keystore file. The
+/// keystore is decrypted for every signature, so the secret key is only in
+/// memory while signing.
+pub struct KeystoreSigner {
+    path: String,
+    password: String,
+    pubkey: String,
+}
+
+impl KeystoreSigner {
+    /// Opens the keystore at `path` once to check the password.
+    pub fn open(path: &str, password: &str) -> Result<KeystoreSigner, SignerError> {
+        let signer = KeystoreSigner {
+            path: path.to_string(),
+            password: password.to_string(),
+            pubkey: Keystore::load(path)?.pubkey,
+        };
+        let key_signer = signer.unlock()?;
+        if key_signer.pubkey() != signer.pubkey {
+            return Err(KeystoreError::InvalidSecret.into());
+        }
+
+        Ok(signer)
+    }
+
+    fn unlock(&self) -> Result<KeySigner, SignerError> {
+        let secret = Keystore::load(&self.path)?.open(&self.password)?;
+        if secret.len() < 32 {
+            return Err(KeystoreError::InvalidSecret.into());
+        }
+        Ok(KeySigner::new(SecretKey::from_slice(&secret[..32])?))
+    }
+}
+
+impl Signer for KeystoreSigner {
+    fn pubkey(&self) -> String {
+        self.pubkey.clone()
+    }
+
+    fn sign_digest(&self, digest: &Message) -> Result<Signature, SignerError> {
+        self.unlock()?.sign_digest(digest)
+    }
+}
+
+impl fmt::Debug for KeystoreSigner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("KeystoreSigner")
+            .field("path", &self.path)
+            .field("pubkey", &self.pubkey)
+            .finish()
+    }
+}
+
+/// A test double that signs with a key derived from a seed and records every
+/// digest it signed. It can be switched to fail, to exercise the error paths of
+/// signing call sites.
+#[derive(Debug)]
+pub struct MockSigner {
+    inner: KeySigner,
+    pub signed: Mutex<Vec<Message>>,
+    pub fail: bool,
+}
+
+impl MockSigner {
+    pub fn new(seed: u8) -> MockSigner {
+        let secret_key = SecretKey::from_slice(&[seed.max(1); 32])
+            .expect("Repeated non zero byte is a valid key");
+        MockSigner {
+            inner: KeySigner::new(secret_key),
+            signed: Mutex::new(vec![]),
+            fail: false,
+        }
+    }
+
+    pub fn failing() -> MockSigner {
+        MockSigner {
+            fail: true,
+            ..MockSigner::new(1)
+        }
+    }
+}
+
+impl Signer for MockSigner {
+    fn pubkey(&self) -> String {
+        self.inner.pubkey()
+    }
+
+    fn sign_digest(&self, digest: &Message) -> Result<Signature, SignerError> {
+        if self.fail {
+            return Err(SignerError::Unavailable(
+                "mock signer set to fail".to_string(),
+            ));
+        }
+        self.signed.lock().unwrap().push(*digest);
+        self.inner.sign_digest(digest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signatures_verify_against_pubkey() {
+        let signer = MockSigner::new(7);
+        let signature = signer.sign("a short message").unwrap().to_string();
+
+        assert!(verify("a short message", &signature, &signer.pubkey()));
+        assert!(!verify("another message", &signature, &signer.pubkey()));
+        assert_eq!(signer.signed.lock().unwrap().len(), 1);
+        assert!(MockSigner::failing().sign("a short message").is_err());
+    }
+}
@@ -1,5 +1,5 @@
-use crate::network::chunkable::Chunkable;
-use crate::network::node::MAX_TRANSMIT_SIZE;
+use crate::network::chunkable::{chunk_bytes, Chunkable};
+use crate::network::node::MAX_CHUNK_SIZE;
 use crate::pool::Pool;
 use crate::txn::Txn;
 use crate::{block::Block, claim::Claim, reward::RewardState};
@@ -14,6 +14,9 @@ pub struct Ledger {
     pub credits: LinkedHashMap<String, u128>,
     pub debits: LinkedHashMap<String, u128>,
     pub claims: LinkedHashMap<String, Claim>,
+    // The next txn nonce expected from each address that has sent a txn.
+    #[serde(default)]
+    pub nonces: LinkedHashMap<String, u128>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -55,8 +58,23 @@ impl NetworkState {
         let (credits_map, debits_map, reward_state, _claims) =
             NetworkState::restore_state_objects(&db);
 
+        // The hashes are stored along with the ledger so that a restarted node
+        // computes the same state hash for the next block as the network. A db
+        // stored without them only gets the ledger back.
+        if let Some(state_hash) = db.get::<String>("statehash") {
+            return NetworkState {
+                path: path.to_string(),
+                credits: db.get("credithash"),
+                debits: db.get("debithash"),
+                reward_state,
+                state_hash: Some(state_hash),
+            };
+        }
+
+        // Dbs stored with their hashes keep them above, this only changes the
+        // debits hash of older dbs, which used to digest the credits here.
         let credits = digest_bytes(format!("{:?}", &credits_map).as_bytes());
-        let debits = digest_bytes(format!("{:?}", &credits_map).as_bytes());
+        let debits = digest_bytes(format!("{:?}", &debits_map).as_bytes());
 
         NetworkState {
             path: path.to_string(),
@@ -135,13 +153,20 @@ impl NetworkState {
         }
     }
 
+    /// The hash of the txn nonces once `block` is applied, so that the state
+    /// hash commits to which nonces every account has used.
+    pub fn nonce_hash(&self, block: &Block) -> String {
+        digest_bytes(format!("{:?}", self.next_nonces(block)).as_bytes())
+    }
+
     pub fn hash(&mut self, block: Block) -> String {
         let credit_hash = self.clone().credit_hash(&block);
         let debit_hash = self.clone().debit_hash(&block);
+        let nonce_hash = self.nonce_hash(&block);
         let reward_state_hash = digest_bytes(format!("{:?}", self.reward_state).as_bytes());
         let payload = format!(
-            "{:?},{:?},{:?},{:?}",
-            self.state_hash, credit_hash, debit_hash, reward_state_hash
+            "{:?},{:?},{:?},{:?},{:?}",
+            self.state_hash, credit_hash, debit_hash, nonce_hash, reward_state_hash
         );
         let new_state_hash = digest_bytes(payload.as_bytes());
         new_state_hash
@@ -201,6 +226,8 @@ impl NetworkState {
             }
         });
 
+        let nonces = self.next_nonces(block);
+
         block.claims.iter().for_each(|(k, v)| {
             claims.insert(k.clone(), v.clone());
         });
@@ -236,11 +263,43 @@ impl NetworkState {
         if let Err(_) = db.set("claims", &claims) {
             println!("Error setting claims to state");
         };
+        if let Err(_) = db.set("nonces", &nonces) {
+            println!("Error setting txn nonces to state");
+        };
+        self.set_hashes(&mut db);
+        if let Err(e) = db.dump() {
+            info!("Error dumping state to file: {:?}", e)
+        }
+    }
+
+    /// Stores the ledger db with the current state hashes, so that the node
+    /// resumes from this state the next time it starts.
+    pub fn flush(&self) {
+        let mut db = self.get_ledger_db();
+        self.set_hashes(&mut db);
         if let Err(e) = db.dump() {
             info!("Error dumping state to file: {:?}", e)
         }
     }
 
+    fn set_hashes(&self, db: &mut PickleDb) {
+        if let Some(state_hash) = &self.state_hash {
+            if let Err(_) = db.set("statehash", state_hash) {
+                println!("Error setting state hash to state");
+            }
+        }
+        if let Some(credits) = &self.credits {
+            if let Err(_) = db.set("credithash", credits) {
+                println!("Error setting credits hash to state");
+            }
+        }
+        if let Some(debits) = &self.debits {
+            if let Err(_) = db.set("debithash", debits) {
+                println!("Error setting debits hash to state");
+            }
+        }
+    }
+
     pub fn nonce_up(&mut self) {
         let mut new_claim_map = LinkedHashMap::new();
         self.get_claims().clone().iter().for_each(|(pk, claim)| {
@@ -332,6 +391,34 @@ impl NetworkState {
         claims
     }
 
+    // The txn nonces of the ledger with the nonces used by the txns in `block`.
+    fn next_nonces(&self, block: &Block) -> LinkedHashMap<String, u128> {
+        let mut nonces = self.get_nonces();
+        block.txns.iter().for_each(|(_txn_id, txn)| {
+            let next_nonce = txn.nonce + 1;
+            if let Some(entry) = nonces.get_mut(&txn.sender_address) {
+                if *entry < next_nonce {
+                    *entry = next_nonce
+                }
+            } else {
+                nonces.insert(txn.sender_address.clone(), next_nonce);
+            }
+        });
+
+        nonces
+    }
+
+    pub fn get_nonces(&self) -> LinkedHashMap<String, u128> {
+        let db = self.get_ledger_db();
+        let nonces: LinkedHashMap<String, u128> = if let Some(map) = db.get("nonces") {
+            map
+        } else {
+            LinkedHashMap::new()
+        };
+
+        nonces
+    }
+
     pub fn get_reward_state(&self) -> RewardState {
         let db = self.get_ledger_db();
         if let Some(reward_state) = db.get("rewardstate") {
@@ -376,6 +463,30 @@ impl NetworkState {
             return 0u128;
         }
     }
+
+    /// The nonce the next txn sent from `address` has to use at least. Txns
+    /// with a lower nonce have already been confirmed or were replayed.
+    pub fn get_account_txn_nonce(&self, address: &str) -> u128 {
+        let nonces = self.get_nonces();
+        if let Some(nonce) = nonces.get(address) {
+            return *nonce;
+        } else {
+            return 0u128;
+        }
+    }
+
+    /// The nonce of the next txn from `address`: after the last one in a block
+    /// and after the ones in `txn_pool` that aren't in a block yet.
+    pub fn next_txn_nonce(&self, address: &str, txn_pool: &Pool<String, Txn>) -> u128 {
+        txn_pool
+            .pending
+            .values()
+            .chain(txn_pool.confirmed.values())
+            .filter(|txn| txn.sender_address == address)
+            .map(|txn| txn.nonce + 1)
+            .fold(self.get_account_txn_nonce(address), u128::max)
+    }
+
     pub fn update_ledger(&mut self, ledger: Ledger, reward_state: RewardState) {
         let mut db = self.get_ledger_db();
         if let Err(_) = db.set("credits", &ledger.credits) {
@@ -390,6 +501,10 @@ impl NetworkState {
         if let Err(_) = db.set("claims", &ledger.claims) {
             println!("Error setting claims to ledger");
         }
+        if let Err(_) = db.set("nonces", &ledger.nonces) {
+            println!("Error setting txn nonces to ledger");
+        }
+        self.set_hashes(&mut db);
         if let Err(_) = db.dump() {
             info!("Error dumping ledger to db");
         }
@@ -438,14 +553,6 @@ impl NetworkState {
         }
     }
 
-    pub fn pending_balance(
-        &self,
-        _address: String,
-        _txn_pool: &Pool<String, Txn>,
-    ) -> Option<(u128, u128)> {
-        None
-    }
-
     pub fn credits_as_bytes(credits: &LinkedHashMap<String, u128>) -> Vec<u8> {
         NetworkState::credits_to_string(credits).as_bytes().to_vec()
     }
@@ -506,11 +613,13 @@ impl NetworkState {
         let credits = self.get_credits();
         let debits = self.get_debits();
         let claims = self.get_claims();
+        let nonces = self.get_nonces();
 
         Ledger {
             credits,
             debits,
             claims,
+            nonces,
         }
     }
 }
@@ -553,30 +662,7 @@ impl Components {
 
 impl Chunkable for Components {
     fn chunk(&self) -> Option<Vec<Vec<u8>>> {
-        let bytes_len = self.as_bytes().len();
-        if bytes_len > MAX_TRANSMIT_SIZE {
-            let mut n_chunks = bytes_len / MAX_TRANSMIT_SIZE;
-            if bytes_len % MAX_TRANSMIT_SIZE != 0 {
-                n_chunks += 1;
-            }
-            let mut chunks_vec = vec![];
-            let mut last_slice_end = 0;
-            (1..=n_chunks)
-                .map(|n| n * MAX_TRANSMIT_SIZE)
-                .enumerate()
-                .for_each(|(index, slice_end)| {
-                    if index + 1 == n_chunks {
-                        chunks_vec.push(self.clone().as_bytes()[last_slice_end..].to_vec());
-                    } else {
-                        chunks_vec
-                            .push(self.clone().as_bytes()[last_slice_end..slice_end].to_vec());
-                        last_slice_end = slice_end;
-                    }
-                });
-            Some(chunks_vec)
-        } else {
-            Some(vec![self.clone().as_bytes()])
-        }
+        Some(chunk_bytes(&self.as_bytes(), MAX_CHUNK_SIZE))
     }
 }
 
@@ -591,3 +677,46 @@ impl Clone for NetworkState {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signer::MockSigner;
+    use crate::test_utils::{genesis_block, TempDb};
+
+    #[test]
+    fn test_restored_state_keeps_the_hashes_of_the_stored_one() {
+        let db = TempDb::new("state");
+        let genesis = genesis_block(&MockSigner::new(1));
+
+        let mut network_state = NetworkState::restore(db.path());
+        network_state.dump(&genesis);
+        let restored = NetworkState::restore(db.path());
+
+        assert_eq!(restored.state_hash, Some(genesis.hash.clone()));
+        assert_eq!(restored.credits, network_state.credits);
+        assert_eq!(restored.debits, network_state.debits);
+        assert_eq!(restored.reward_state, network_state.reward_state);
+    }
+
+    #[test]
+    fn test_state_restored_without_hashes_digests_the_debits() {
+        let db = TempDb::new("state_without_hashes");
+        let mut network_state = NetworkState::restore(db.path());
+        let mut ledger = network_state.db_to_ledger();
+        ledger.credits.insert("receiver".to_string(), 20);
+        ledger.debits.insert("sender".to_string(), 20);
+        network_state.update_ledger(ledger.clone(), network_state.reward_state);
+
+        let restored = NetworkState::restore(db.path());
+        assert_eq!(restored.state_hash, None);
+        assert_eq!(
+            restored.credits,
+            Some(digest_bytes(format!("{:?}", &ledger.credits).as_bytes()))
+        );
+        assert_eq!(
+            restored.debits,
+            Some(digest_bytes(format!("{:?}", &ledger.debits).as_bytes()))
+        );
+    }
+}
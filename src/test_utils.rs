@@ -0,0 +1,83 @@
+//! Fixtures shared by the unit tests.
+use crate::address::Address;
+use crate::block::Block;
+use crate::claim::Claim;
+use crate::network::network_config::TESTNET_ADDRESS_HRP;
+use crate::reward::RewardState;
+use crate::signer::{MockSigner, Signer};
+use std::path::{Path, PathBuf};
+
+/// A db path in the temp dir that no other test uses. The file is removed
+/// when the `TempDb` is dropped, also when the test fails before its end.
+#[derive(Debug)]
+pub struct TempDb {
+    path: String,
+}
+
+impl TempDb {
+    pub fn new(name: &str) -> TempDb {
+        let path = std::env::temp_dir()
+            .join(format!(
+                "vrrb_{}_{}_{}.db",
+                name,
+                std::process::id(),
+                rand::random::<u64>()
+            ))
+            .to_string_lossy()
+            .into_owned();
+
+        TempDb { path }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+impl Drop for TempDb {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// A dir in the temp dir that no other test uses, e.g. for a data dir. It is
+/// removed with everything in it when the `TempDir` is dropped.
+#[derive(Debug)]
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new(name: &str) -> TempDir {
+        let path = std::env::temp_dir().join(format!(
+            "vrrb_{}_{}_{}",
+            name,
+            std::process::id(),
+            rand::random::<u64>()
+        ));
+
+        TempDir { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// The testnet address of the pubkey of `signer`.
+pub fn testnet_address(signer: &dyn Signer) -> String {
+    Address::from_pubkey(TESTNET_ADDRESS_HRP, &signer.pubkey()).to_string()
+}
+
+/// A genesis block of chain 0, mined with a claim of `signer` paying out to
+/// "address".
+pub fn genesis_block(signer: &MockSigner) -> Block {
+    let claim = Claim::new(signer.pubkey(), "address".to_string(), 0);
+    Block::genesis(&RewardState::start(), claim, signer, 0).unwrap()
+}
@@ -1,18 +1,42 @@
+use crate::address::Address;
+use crate::balance::{BalanceService, NATIVE_TOKEN};
+use crate::multisig::{MultisigError, MultisigPolicy};
 use crate::pool::Pool;
+use crate::signer::{self, Signer, SignerError};
 use crate::state::NetworkState;
 use crate::verifiable::Verifiable;
-use crate::wallet::WalletAccount;
-use bytebuffer::ByteBuffer;
-use secp256k1::{Message, PublicKey, Secp256k1, Signature};
+use crate::wallet::{WalletAccount, WalletError};
 use serde::{Deserialize, Serialize};
 use sha256::digest_bytes;
 use std::collections::HashMap;
 use std::fmt;
-use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
 use uuid::Uuid;
 
+#[derive(Error, Debug)]
+pub enum TxnError {
+    #[error("Error decoding the exported txn: {0}")]
+    Decode(#[from] hex::FromHexError),
+    #[error("Error parsing the exported txn: {0}")]
+    Parse(#[from] serde_json::Error),
+}
+
+/// A txn built by a node, which knows the sender's next nonce, but not yet signed.
+/// It is exported as a string, carried to the machine holding the sender's keys,
+/// signed there and the resulting `Txn` is exported back to a node to broadcast,
+/// so that the keys never have to be on a networked machine.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UnsignedTxn {
+    pub txn_timestamp: u128,
+    pub sender_address: String,
+    pub receiver_address: String,
+    pub txn_amount: u128,
+    pub nonce: u128,
+    pub chain_id: u64,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Txn {
     pub txn_id: String,
@@ -26,48 +50,60 @@ pub struct Txn {
     pub txn_signature: String,
     pub validators: HashMap<String, bool>,
     pub nonce: u128,
+    pub chain_id: u64,
+    // Set for txns spending from a multisig address, which carry a signature
+    // per cosigner in `signatures` (pubkey -> signature) instead of
+    // `txn_signature`.
+    #[serde(default)]
+    pub multisig: Option<MultisigPolicy>,
+    #[serde(default)]
+    pub signatures: HashMap<String, String>,
 }
 
 impl Txn {
+    /// Builds a txn from address number `address_number` of the sender's wallet,
+    /// signed with that address's child key.
     pub fn new(
         sender: Arc<Mutex<WalletAccount>>,
-        sender_address: String,
+        address_number: u32,
         receiver: String,
         amount: u128,
         nonce: u128,
-    ) -> Txn {
-        let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    ) -> Result<Txn, WalletError> {
+        let wallet = sender.lock().unwrap();
+        let sender_address = wallet.derive_address(address_number)?;
+        let signer = wallet.address_signer(address_number)?;
+        Ok(UnsignedTxn::new(wallet.chain_id, sender_address, receiver, amount, nonce)
+            .sign(&signer)?)
+    }
 
-        let payload = format!(
-            "{},{},{},{},{},{}",
-            &time.as_nanos().to_string(),
-            &sender_address,
-            &sender.lock().unwrap().pubkey.clone(),
-            &receiver,
-            &amount.to_string(),
-            &nonce
-        );
-        let signature = sender.lock().unwrap().sign(&payload).unwrap();
-        let uid_payload = format!(
-            "{},{},{}",
-            &payload,
-            Uuid::new_v4().to_string(),
-            &signature.to_string()
-        );
+    /// The message signed by the sender. The chain id is part of the payload so a
+    /// signed txn can't be replayed on another network.
+    pub fn build_payload(
+        chain_id: u64,
+        timestamp: u128,
+        sender_address: &str,
+        sender_public_key: &str,
+        receiver_address: &str,
+        amount: u128,
+        nonce: u128,
+    ) -> String {
+        format!(
+            "{},{},{},{},{},{},{}",
+            chain_id, timestamp, sender_address, sender_public_key, receiver_address, amount, nonce
+        )
+    }
 
-        Txn {
-            txn_id: digest_bytes(uid_payload.as_bytes()),
-            txn_timestamp: time.as_nanos(),
-            sender_address: sender_address,
-            sender_public_key: sender.lock().unwrap().pubkey.clone(),
-            receiver_address: receiver,
-            txn_token: None,
-            txn_amount: amount,
-            txn_payload: payload,
-            txn_signature: signature.to_string(),
-            validators: HashMap::new(),
-            nonce,
-        }
+    pub fn get_payload(&self) -> String {
+        Txn::build_payload(
+            self.chain_id,
+            self.txn_timestamp,
+            &self.sender_address,
+            &self.sender_public_key,
+            &self.receiver_address,
+            self.txn_amount,
+            self.nonce,
+        )
     }
 
     // TODO: convert to_message into a function of the verifiable trait,
@@ -89,6 +125,17 @@ impl Txn {
         serde_json::from_str::<Txn>(string).unwrap()
     }
 
+    /// Encodes the signed txn as a single line that can be copied between
+    /// machines or saved to a file.
+    pub fn export(&self) -> String {
+        hex::encode(self.to_string())
+    }
+
+    pub fn import(exported: &str) -> Result<Txn, TxnError> {
+        let bytes = hex::decode(exported.trim())?;
+        Ok(serde_json::from_slice::<Txn>(&bytes)?)
+    }
+
     pub fn get_field_names(&self) -> Vec<String> {
         vec![
             "txn_id".to_string(),
@@ -103,8 +150,182 @@ impl Txn {
             "txn_signature".to_string(),
             "validators".to_string(),
             "nonce".to_string(),
+            "chain_id".to_string(),
+            "multisig".to_string(),
+            "signatures".to_string(),
         ]
     }
+
+    /// Adds a cosigner's signature to a multisig txn. Signatures from keys
+    /// outside the policy or over a different payload are rejected.
+    pub fn add_signature(&mut self, pubkey: &str, signature: &str) -> Result<(), MultisigError> {
+        let is_cosigner = self
+            .multisig
+            .as_ref()
+            .map_or(false, |policy| policy.is_cosigner(pubkey));
+        if !is_cosigner || !signer::verify(&self.txn_payload, signature, pubkey) {
+            return Err(MultisigError::NotACosigner(pubkey.to_string()));
+        }
+
+        self.signatures
+            .insert(pubkey.to_string(), signature.to_string());
+        Ok(())
+    }
+
+    /// Collects the signatures cosigners added to their own copies of this txn.
+    pub fn merge_signatures(&mut self, other: &Txn) -> Result<(), MultisigError> {
+        for (pubkey, signature) in other.signatures.iter() {
+            self.add_signature(pubkey, signature)?;
+        }
+        Ok(())
+    }
+
+    /// The number of cosigners that have validly signed the txn.
+    pub fn valid_signature_count(&self) -> usize {
+        let policy = if let Some(policy) = &self.multisig {
+            policy
+        } else {
+            return 0;
+        };
+
+        self.signatures
+            .iter()
+            .filter(|(pubkey, signature)| {
+                policy.is_cosigner(pubkey)
+                    && signer::verify(&self.txn_payload, signature, pubkey)
+            })
+            .count()
+    }
+
+    /// Checks that a multisig txn has collected enough signatures to be
+    /// broadcast.
+    pub fn finalize(&self) -> Result<(), MultisigError> {
+        if let Some(policy) = &self.multisig {
+            let have = self.valid_signature_count();
+            if have < policy.threshold {
+                return Err(MultisigError::NotEnoughSignatures {
+                    have,
+                    need: policy.threshold,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl UnsignedTxn {
+    pub fn new(
+        chain_id: u64,
+        sender_address: String,
+        receiver_address: String,
+        amount: u128,
+        nonce: u128,
+    ) -> UnsignedTxn {
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        UnsignedTxn {
+            txn_timestamp: time.as_nanos(),
+            sender_address,
+            receiver_address,
+            txn_amount: amount,
+            nonce,
+            chain_id,
+        }
+    }
+
+    /// Signs the txn with `signer`, which has to hold the key controlling the
+    /// sender address.
+    pub fn sign(self, signer: &dyn Signer) -> Result<Txn, SignerError> {
+        let sender_public_key = signer.pubkey();
+        let payload = Txn::build_payload(
+            self.chain_id,
+            self.txn_timestamp,
+            &self.sender_address,
+            &sender_public_key,
+            &self.receiver_address,
+            self.txn_amount,
+            self.nonce,
+        );
+        let signature = signer.sign(&payload)?;
+        let uid_payload = format!(
+            "{},{},{}",
+            &payload,
+            Uuid::new_v4().to_string(),
+            &signature.to_string()
+        );
+
+        Ok(Txn {
+            txn_id: digest_bytes(uid_payload.as_bytes()),
+            txn_timestamp: self.txn_timestamp,
+            sender_address: self.sender_address,
+            sender_public_key,
+            receiver_address: self.receiver_address,
+            txn_token: None,
+            txn_amount: self.txn_amount,
+            txn_payload: payload,
+            txn_signature: signature.to_string(),
+            validators: HashMap::new(),
+            nonce: self.nonce,
+            chain_id: self.chain_id,
+            multisig: None,
+            signatures: HashMap::new(),
+        })
+    }
+
+    /// Turns the txn into a multisig txn from the address of `policy`, without
+    /// any signatures yet. Cosigners add theirs with `Txn::add_signature`.
+    pub fn into_multisig(self, policy: MultisigPolicy) -> Txn {
+        let payload = Txn::build_payload(
+            self.chain_id,
+            self.txn_timestamp,
+            &self.sender_address,
+            &policy.to_string(),
+            &self.receiver_address,
+            self.txn_amount,
+            self.nonce,
+        );
+        let uid_payload = format!("{},{}", &payload, Uuid::new_v4().to_string());
+
+        Txn {
+            txn_id: digest_bytes(uid_payload.as_bytes()),
+            txn_timestamp: self.txn_timestamp,
+            sender_address: self.sender_address,
+            sender_public_key: policy.to_string(),
+            receiver_address: self.receiver_address,
+            txn_token: None,
+            txn_amount: self.txn_amount,
+            txn_payload: payload,
+            txn_signature: String::new(),
+            validators: HashMap::new(),
+            nonce: self.nonce,
+            chain_id: self.chain_id,
+            multisig: Some(policy),
+            signatures: HashMap::new(),
+        }
+    }
+
+    pub fn export(&self) -> String {
+        hex::encode(serde_json::to_string(self).unwrap())
+    }
+
+    pub fn import(exported: &str) -> Result<UnsignedTxn, TxnError> {
+        let bytes = hex::decode(exported.trim())?;
+        Ok(serde_json::from_slice::<UnsignedTxn>(&bytes)?)
+    }
+}
+
+impl fmt::Display for UnsignedTxn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "UnsignedTxn(\n \
+            sender_address: {},\n \
+            receiver_address: {},\n \
+            txn_amount: {},\n \
+            nonce: {},\n \
+            chain_id: {}",
+            self.sender_address, self.receiver_address, self.txn_amount, self.nonce, self.chain_id
+        )
+    }
 }
 
 impl Verifiable for Txn {
@@ -125,53 +346,53 @@ impl Verifiable for Txn {
             return false;
         }
 
+        if !self.check_txn_nonce(network_state) {
+            return false;
+        }
+
         true
     }
 
     fn valid_txn_signature(&self) -> bool {
-        let message = self.txn_payload.clone();
-        let message_bytes = message.as_bytes().to_owned();
-
-        let mut buffer = ByteBuffer::new();
-        buffer.write_bytes(&message_bytes);
-        while buffer.len() < 32 {
-            buffer.write_u8(0);
+        // The payload is what gets signed, make sure it actually commits to
+        // the fields of this txn before checking the signature over it.
+        let message = self.get_payload();
+        if message != self.txn_payload {
+            return false;
         }
-        let new_message = buffer.to_bytes();
-        let message_hash = blake3::hash(&new_message);
-        let message_hash = Message::from_slice(message_hash.as_bytes()).unwrap();
-        let secp = Secp256k1::new();
-        let valid = secp.verify(
-            &message_hash,
-            &Signature::from_str(&self.txn_signature).unwrap(),
-            &PublicKey::from_str(&self.sender_public_key).unwrap(),
-        );
 
-        match valid {
-            Ok(()) => return true,
-            _ => return false,
+        if let Some(policy) = &self.multisig {
+            // The policy is committed to through the sender public key field
+            // of the payload, which has to match the policy the txn carries.
+            if policy.validate().is_err() || policy.to_string() != self.sender_public_key {
+                return false;
+            }
+            if let Err(e) = self.finalize() {
+                println!("Invalid multisig txn: {}", e);
+                return false;
+            }
+            return true;
         }
+
+        signer::verify(&message, &self.txn_signature, &self.sender_public_key)
     }
 
     fn valid_amount(&self, network_state: &NetworkState, txn_pool: &Pool<String, Txn>) -> bool {
-        let (_, pending_debits) = if let Some((credit_amount, debit_amount)) =
-            network_state.pending_balance(self.sender_address.clone(), txn_pool)
-        {
-            (credit_amount, debit_amount)
-        } else {
-            (0, 0)
-        };
-
-        let mut address_balance = network_state.get_balance(&self.sender_address);
+        let balance_service = BalanceService::new(network_state, txn_pool);
+        let balance = balance_service.balance(&self.sender_address, NATIVE_TOKEN);
 
-        address_balance = if let Some(amount) = address_balance.checked_sub(pending_debits) {
-            amount
-        } else {
-            println!("Invalid balance, not enough coins!");
-            return false;
+        // The txn being validated may already be in the pool, it must not
+        // count against its own balance.
+        let already_pending = match txn_pool
+            .pending
+            .get(&self.txn_id)
+            .or_else(|| txn_pool.confirmed.get(&self.txn_id))
+        {
+            Some(txn) => txn.txn_amount,
+            None => 0,
         };
 
-        if address_balance < self.txn_amount {
+        if balance.spendable + already_pending < self.txn_amount {
             println!("Invalid balance, not enough coins");
             return false;
         }
@@ -192,8 +413,41 @@ impl Verifiable for Txn {
         true
     }
 
-    fn check_txn_nonce(&self, _network_state: &NetworkState) -> bool {
-        //TODO: Add get_account_txn_nonce to network state to get the txn nonce
+    fn check_txn_nonce(&self, network_state: &NetworkState) -> bool {
+        if self.nonce < network_state.get_account_txn_nonce(&self.sender_address) {
+            println!("Invalid txn nonce, txn was already confirmed");
+            return false;
+        }
+        true
+    }
+
+    fn valid_chain_id(&self, chain_id: u64) -> bool {
+        self.chain_id == chain_id
+    }
+
+    fn valid_addresses(&self, address_hrp: &str, address_prefix: &str) -> bool {
+        // The sender address must be controlled by the key, or the multisig
+        // policy, that signed the txn, otherwise anyone could sign a spend from
+        // someone else's address.
+        let controls_sender = |sender: &Address| match &self.multisig {
+            Some(policy) => sender.matches_policy(policy),
+            None => sender.matches_pubkey(&self.sender_public_key),
+        };
+        match Address::parse_for_network(&self.sender_address, address_hrp, address_prefix) {
+            Ok(sender) if controls_sender(&sender) => {}
+            _ => {
+                println!("Invalid sender address");
+                return false;
+            }
+        }
+
+        if let Err(e) =
+            Address::parse_for_network(&self.receiver_address, address_hrp, address_prefix)
+        {
+            println!("Invalid receiver address: {}", e);
+            return false;
+        }
+
         true
     }
 }
@@ -210,7 +464,8 @@ impl fmt::Display for Txn {
             receiver_address: {},\n \
             txn_token: {:?},\n \
             txn_amount: {},\n \
-            txn_signature: {}",
+            txn_signature: {},\n \
+            chain_id: {}",
             self.txn_id,
             self.txn_timestamp.to_string(),
             self.sender_address,
@@ -219,6 +474,7 @@ impl fmt::Display for Txn {
             self.txn_token,
             self.txn_amount,
             self.txn_signature,
+            self.chain_id,
         )
     }
 }
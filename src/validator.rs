@@ -22,10 +22,15 @@ impl TxnValidator {
         txn: Txn,
         network_state: &NetworkState,
         txn_pool: &Pool<String, Txn>,
+        chain_id: u64,
+        address_hrp: &str,
+        address_prefix: &str,
     ) -> TxnValidator {
         TxnValidator {
             pubkey,
-            vote: txn.clone().valid_txn(network_state, txn_pool),
+            vote: txn.valid_chain_id(chain_id)
+                && txn.valid_addresses(address_hrp, address_prefix)
+                && txn.clone().valid_txn(network_state, txn_pool),
             txn,
         }
     }
@@ -1,5 +1,6 @@
 use crate::block::Block;
 use crate::blockchain::{InvalidBlockError, InvalidBlockErrorReason};
+use crate::chain_spec::ChainSpec;
 use crate::pool::Pool;
 use crate::reward::RewardState;
 use crate::state::NetworkState;
@@ -13,13 +14,19 @@ pub trait Verifiable {
         _last_block: &Block,
         _network_state: &NetworkState,
         _reward_state: &RewardState,
+        _chain_spec: &ChainSpec,
     ) -> Result<(), InvalidBlockError> {
         Err(InvalidBlockError {
             details: InvalidBlockErrorReason::General,
         })
     }
 
-    fn valid_genesis(&self, _network_state: &NetworkState, _reward_state: &RewardState) -> bool {
+    fn valid_genesis(
+        &self,
+        _network_state: &NetworkState,
+        _reward_state: &RewardState,
+        _genesis_hash: Option<&str>,
+    ) -> bool {
         false
     }
 
@@ -39,7 +46,7 @@ pub trait Verifiable {
         false
     }
 
-    fn valid_txns(&self) -> bool {
+    fn valid_txns(&self, _validator_threshold: f64) -> bool {
         false
     }
 
@@ -78,4 +85,12 @@ pub trait Verifiable {
     fn check_txn_nonce(&self, _network_state: &NetworkState) -> bool {
         false
     }
+
+    fn valid_chain_id(&self, _chain_id: u64) -> bool {
+        false
+    }
+
+    fn valid_addresses(&self, _address_hrp: &str, _address_prefix: &str) -> bool {
+        false
+    }
 }
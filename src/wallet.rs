@@ -1,121 +1,212 @@
+use crate::address::{Address, AddressError};
+use crate::balance::{Balance, BalanceService, NATIVE_TOKEN};
 use crate::block::Block;
 use crate::claim::Claim;
+use crate::keystore::{Keystore, KeystoreError, KEYSTORE_VERSION};
+use crate::multisig::{MultisigError, MultisigPolicy};
+use crate::network::network_config::NetworkConfig;
+use crate::pool::Pool;
+use crate::signer::{self, KeySigner, Signer, SignerError};
 use crate::state::NetworkState;
-use crate::txn::Txn;
-use bytebuffer::ByteBuffer;
+use crate::txn::{Txn, UnsignedTxn};
+use bip39::Mnemonic;
+use hmac::{Hmac, Mac, NewMac};
+use rand::RngCore;
 use ritelinked::LinkedHashMap;
 use secp256k1::Error;
 use secp256k1::{
     key::{PublicKey, SecretKey},
     Signature,
 };
-use secp256k1::{Message, Secp256k1};
+use secp256k1::Secp256k1;
 use serde::{Deserialize, Serialize};
-use sha256::digest_bytes;
+use sha2::Sha512;
 use std::fmt;
 use std::str::FromStr;
+use std::collections::HashSet;
 use std::sync::{Arc, Mutex};
-use uuid::Uuid;
-
-const STARTING_BALANCE: u128 = 1000;
+use thiserror::Error;
+
+pub const DEFAULT_MNEMONIC_WORDS: usize = 12;
+/// The HMAC key BIP32 uses to derive the master key from a seed.
+pub const MASTER_KEY_HMAC_KEY: &[u8] = b"Bitcoin seed";
+/// BIP32 child numbers at or above this are hardened, i.e. derived from the
+/// parent secret key rather than the parent public key.
+pub const HARDENED_OFFSET: u32 = 0x8000_0000;
+/// Address discovery stops after this many unused addresses in a row.
+pub const ADDRESS_GAP_LIMIT: u32 = 20;
+
+type HmacSha512 = Hmac<Sha512>;
+
+#[derive(Error, Debug)]
+pub enum WalletError {
+    #[error("mnemonic phrases must have 12 or 24 words, got {0}")]
+    InvalidWordCount(usize),
+    #[error("invalid mnemonic phrase: {0}")]
+    InvalidMnemonic(#[from] bip39::Error),
+    #[error("invalid key: {0}")]
+    InvalidKey(#[from] secp256k1::Error),
+    #[error("address number {0} is out of range")]
+    InvalidAddressNumber(u32),
+    #[error("invalid address: {0}")]
+    InvalidAddress(#[from] AddressError),
+    #[error("address {0} does not belong to this wallet")]
+    UnknownAddress(String),
+    #[error("txn is for chain {found}, the wallet is on chain {expected}")]
+    WrongChain { expected: u64, found: u64 },
+    #[error("multisig error: {0}")]
+    Multisig(#[from] MultisigError),
+    #[error("signing error: {0}")]
+    Signer(#[from] SignerError),
+    #[error("watch-only wallets hold no keys and can't sign")]
+    WatchOnly,
+    #[error("invalid watch-only wallet export: {0}")]
+    InvalidExport(#[from] serde_json::Error),
+}
 
 /// The WalletAccount struct is the user/node wallet in which coins, tokens and contracts
-/// are held. The WalletAccount has a private/public keypair
-/// phrase are used to restore the Wallet. The private key is
-/// also used to sign transactions, claims and mined blocks for network validation.
-/// Private key signatures can be verified with the wallet's public key, the message that was
-/// signed and the signature.
+/// are held. The WalletAccount has a master private/public keypair derived from the
+/// mnemonic phrase used to restore the Wallet. The master private key is
+/// used to sign claims and mined blocks for network validation. Every address has
+/// its own child keypair, derived from the master key, which signs the address's
+/// transactions. Private key signatures can be verified with the matching public key,
+/// the message that was signed and the signature. A watch-only wallet has no keys at
+/// all, only the addresses it follows, see `WalletAccount::watch_only`.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct WalletAccount {
+    // The secrets never leave the wallet in serialized form, they are only
+    // written to disk encrypted, see `save`.
+    #[serde(skip)]
     secretkey: String,
+    #[serde(skip)]
+    chain_code: [u8; 32],
     welcome_message: String,
     pub pubkey: String,
     pub addresses: LinkedHashMap<u32, String>,
     pub total_balances: LinkedHashMap<String, LinkedHashMap<String, u128>>,
     pub available_balances: LinkedHashMap<String, LinkedHashMap<String, u128>>,
     pub claims: LinkedHashMap<u128, Claim>,
-    pub txn_nonce: u128,
+    // The nonce of the next txn of each address, as far as the wallet knows.
+    #[serde(default)]
+    pub txn_nonces: LinkedHashMap<String, u128>,
+    pub chain_id: u64,
+    pub address_prefix: String,
+    pub address_hrp: String,
+    // The multisig addresses this wallet is a cosigner of, by address.
+    #[serde(default)]
+    pub multisig_policies: LinkedHashMap<String, MultisigPolicy>,
+    // Set when the wallet only follows addresses and has no secret to sign
+    // with.
+    #[serde(default)]
+    pub watch_only: bool,
 }
 
 impl WalletAccount {
-    /// Initiate a new wallet.
-    pub fn new() -> WalletAccount {
-        // Initialize a new Secp256k1 context
-        let secp = Secp256k1::new();
-
-        // Generate a random number used to seed the new keypair for the wallet
-        // TODO: Instead of using the rng, use a mnemonic seed.
-        let mut rng = rand::thread_rng();
-        // Generate a new secret/public key pair using the random seed.
-        let (secret_key, public_key) = secp.generate_keypair(&mut rng);
-        // Generate 100 addresses by hashing a universally unique IDs + secret_key + public_key
-        let mut address_bytes = public_key.to_string().as_bytes().to_vec();
-        address_bytes.push(1u8);
-        let address = digest_bytes(digest_bytes(&address_bytes).as_bytes());
-        // add the testnet prefix to the wallet address (TODO: add handling of testnet/mainnet)
-        let mut address_prefix: String = "0x192".to_string();
-        // push the hashed uuid string to the end of the address prefix
-        address_prefix.push_str(&address);
-
-        // Print the private key string so that the user can save it.
-        // TODO: require a confirmation the private key being saved by the user
-        let welcome_message = format!(
-            "{}\nSECRET KEY: {:?}\nPUBLIC KEY: {:?}\nADDRESS: {}\n",
-            "DO NOT SHARE OR LOSE YOUR SECRET KEY:",
-            &secret_key,
-            &public_key,
-            &address_prefix,
-        );
-        let mut addresses = LinkedHashMap::new();
-        addresses.insert(1, address_prefix.clone());
+    /// Initiate a new wallet on the network described by `network_config` from a
+    /// freshly generated 12 word mnemonic without a passphrase. Use
+    /// `generate_mnemonic` and `from_mnemonic` when the phrase has to be shown to
+    /// the user, it is the only way to restore the wallet.
+    pub fn new(network_config: &NetworkConfig) -> WalletAccount {
+        let mnemonic = WalletAccount::generate_mnemonic(DEFAULT_MNEMONIC_WORDS)
+            .expect("Default mnemonic word count is valid");
+        WalletAccount::from_mnemonic(&mnemonic.to_string(), "", network_config)
+            .expect("Freshly generated mnemonic is valid")
+    }
 
-        let mut total_balances = LinkedHashMap::new();
-        let mut vrrb_balances = LinkedHashMap::new();
-        vrrb_balances.insert("VRRB".to_string(), STARTING_BALANCE);
-        total_balances.insert(address_prefix.clone(), vrrb_balances);
-
-        // Generate a wallet struct by assigning the variables to the fields.
-        let wallet = Self {
-            secretkey: secret_key.to_string(),
-            welcome_message,
-            pubkey: public_key.to_string(),
-            addresses,
-            total_balances: total_balances.clone(),
-            available_balances: total_balances,
-            claims: LinkedHashMap::new(),
-            txn_nonce: 0,
+    /// Generates a new BIP39 mnemonic of 12 or 24 words from the thread rng.
+    pub fn generate_mnemonic(word_count: usize) -> Result<Mnemonic, WalletError> {
+        let entropy_len = match word_count {
+            12 => 16,
+            24 => 32,
+            _ => return Err(WalletError::InvalidWordCount(word_count)),
         };
 
-        wallet
+        let mut entropy = vec![0u8; entropy_len];
+        rand::thread_rng().fill_bytes(&mut entropy);
+
+        Ok(Mnemonic::from_entropy(&entropy)?)
+    }
+
+    /// Creates or restores the wallet derived from a 12 or 24 word BIP39 mnemonic
+    /// and an optional passphrase (pass "" for none). The same phrase and
+    /// passphrase always restore the same keys and addresses.
+    pub fn from_mnemonic(
+        phrase: &str,
+        passphrase: &str,
+        network_config: &NetworkConfig,
+    ) -> Result<WalletAccount, WalletError> {
+        let mnemonic = Mnemonic::parse(phrase)?;
+        let word_count = mnemonic.word_count();
+        if word_count != 12 && word_count != 24 {
+            return Err(WalletError::InvalidWordCount(word_count));
+        }
+
+        let seed = mnemonic.to_seed(passphrase);
+        let (secretkey, chain_code) = master_key_from_seed(&seed)?;
+
+        Ok(WalletAccount::from_secret_key(
+            secretkey,
+            chain_code,
+            network_config.chain_id,
+            network_config.address_prefix.clone(),
+            network_config.address_hrp.clone(),
+        ))
     }
 
     pub fn get_welcome_message(&self) -> String {
         self.welcome_message.clone()
     }
 
-    pub fn restore_from_private_key(private_key: String) -> WalletAccount {
+    /// Restores a wallet from its master secret key alone. A raw key carries no
+    /// chain code, so one is derived from the key itself, which keeps the
+    /// addresses of the restored wallet deterministic.
+    pub fn restore_from_private_key(
+        private_key: String,
+        network_config: &NetworkConfig,
+    ) -> WalletAccount {
         let secretkey = SecretKey::from_str(&private_key).unwrap();
+        let (_, chain_code) = master_key_from_seed(&secretkey[..]).unwrap();
+        WalletAccount::from_secret_key(
+            secretkey,
+            chain_code,
+            network_config.chain_id,
+            network_config.address_prefix.clone(),
+            network_config.address_hrp.clone(),
+        )
+    }
+
+    fn from_secret_key(
+        secretkey: SecretKey,
+        chain_code: [u8; 32],
+        chain_id: u64,
+        address_prefix: String,
+        address_hrp: String,
+    ) -> WalletAccount {
         let secp = Secp256k1::new();
         let pubkey = PublicKey::from_secret_key(&secp, &secretkey);
 
-
         let mut wallet = WalletAccount {
             secretkey: secretkey.to_string(),
+            chain_code,
             welcome_message: String::new(),
             pubkey: pubkey.to_string(),
             addresses: LinkedHashMap::new(),
             total_balances: LinkedHashMap::new(),
             available_balances: LinkedHashMap::new(),
             claims: LinkedHashMap::new(),
-            txn_nonce: 0,
+            txn_nonces: LinkedHashMap::new(),
+            chain_id,
+            address_prefix,
+            address_hrp,
+            multisig_policies: LinkedHashMap::new(),
+            watch_only: false,
         };
 
         wallet.get_new_addresses(1);
 
         let welcome_message = format!(
-            "{}\nSECRET KEY: {:?}\nPUBLIC KEY: {:?}\nADDRESS: {}\n",
-            "DO NOT SHARE OR LOSE YOUR SECRET KEY:",
-            &wallet.secretkey,
+            "{}\nPUBLIC KEY: {:?}\nADDRESS: {}\n",
+            "KEEP YOUR MNEMONIC PHRASE SAFE, IT IS THE ONLY WAY TO RESTORE YOUR WALLET",
             &wallet.pubkey,
             &wallet.addresses.get(&1).unwrap(),
         );
@@ -125,24 +216,260 @@ impl WalletAccount {
         wallet
     }
 
-    pub fn get_txn_nonce(&mut self, _network_state: &NetworkState) {
-        // TODO: add a get_account_txn_nonce() function to network state to update
-        // txn nonce in walet when restored.
+    /// Builds a watch-only wallet following `entries`, each either the public
+    /// key of an address or the address itself. It tracks the balances and
+    /// history of the addresses like any wallet but has no secret, so every
+    /// attempt to sign fails with `WalletError::WatchOnly`.
+    pub fn watch_only(
+        entries: &[String],
+        network_config: &NetworkConfig,
+    ) -> Result<WalletAccount, WalletError> {
+        let mut addresses = LinkedHashMap::new();
+        for entry in entries {
+            let address = if let Ok(pubkey) = PublicKey::from_str(entry) {
+                Address::from_pubkey(&network_config.address_hrp, &pubkey.to_string())
+                    .to_string()
+            } else {
+                Address::parse_for_network(
+                    entry,
+                    &network_config.address_hrp,
+                    &network_config.address_prefix,
+                )?;
+                entry.clone()
+            };
+            if !addresses.values().any(|known| *known == address) {
+                addresses.insert(addresses.len() as u32 + 1, address);
+            }
+        }
+
+        Ok(WalletAccount {
+            secretkey: String::new(),
+            chain_code: [0u8; 32],
+            welcome_message: String::new(),
+            pubkey: String::new(),
+            addresses,
+            total_balances: LinkedHashMap::new(),
+            available_balances: LinkedHashMap::new(),
+            claims: LinkedHashMap::new(),
+            txn_nonces: LinkedHashMap::new(),
+            chain_id: network_config.chain_id,
+            address_prefix: network_config.address_prefix.clone(),
+            address_hrp: network_config.address_hrp.clone(),
+            multisig_policies: LinkedHashMap::new(),
+            watch_only: true,
+        })
+    }
+
+    /// Exports the public parts of the wallet as a watch-only wallet, to be
+    /// imported with `import_watch_only` on a machine that must not hold the
+    /// keys.
+    pub fn export_watch_only(&self) -> String {
+        let watch_only = WalletAccount {
+            secretkey: String::new(),
+            chain_code: [0u8; 32],
+            welcome_message: String::new(),
+            claims: LinkedHashMap::new(),
+            watch_only: true,
+            ..self.clone()
+        };
+
+        serde_json::to_string_pretty(&watch_only).unwrap()
+    }
+
+    /// Imports a wallet exported with `export_watch_only`. Whatever the export
+    /// says, the imported wallet is watch-only.
+    pub fn import_watch_only(exported: &str) -> Result<WalletAccount, WalletError> {
+        let mut wallet: WalletAccount = serde_json::from_str(exported)?;
+        wallet.watch_only = true;
+        for address in wallet.all_addresses() {
+            Address::parse_for_network(&address, &wallet.address_hrp, &wallet.address_prefix)?;
+        }
+
+        Ok(wallet)
+    }
+
+    /// Writes the wallet to an encrypted keystore at `path`, with the secret
+    /// encrypted under `password`.
+    pub fn save(&self, path: &str, password: &str) -> Result<(), KeystoreError> {
+        let (kdf, cipher) = Keystore::seal(&self.keystore_secret()?, password)?;
+        let keystore = Keystore {
+            version: KEYSTORE_VERSION,
+            kdf,
+            cipher,
+            pubkey: self.pubkey.clone(),
+            addresses: self.addresses.clone(),
+            claims: self.claims.clone(),
+            txn_nonces: self.txn_nonces.clone(),
+            chain_id: self.chain_id,
+            address_prefix: self.address_prefix.clone(),
+            address_hrp: self.address_hrp.clone(),
+            multisig_policies: self.multisig_policies.clone(),
+        };
+
+        keystore.save(path)
+    }
+
+    /// Rewrites the public parts of the keystore at `path` (addresses, claims
+    /// and txn nonce) keeping the encrypted secret, so no password is needed.
+    pub fn update_keystore(&self, path: &str) -> Result<(), KeystoreError> {
+        let mut keystore = Keystore::load(path)?;
+        if keystore.pubkey != self.pubkey {
+            return Err(KeystoreError::InvalidSecret);
+        }
+
+        keystore.addresses = self.addresses.clone();
+        keystore.claims = self.claims.clone();
+        keystore.txn_nonces = self.txn_nonces.clone();
+        keystore.multisig_policies = self.multisig_policies.clone();
+        keystore.save(path)
+    }
+
+    /// Unlocks the keystore at `path` with `password`.
+    pub fn load(path: &str, password: &str) -> Result<WalletAccount, KeystoreError> {
+        let keystore = Keystore::load(path)?;
+        let secret = keystore.open(password)?;
+        if secret.len() != 64 {
+            return Err(KeystoreError::InvalidSecret);
+        }
+
+        let secretkey =
+            SecretKey::from_slice(&secret[..32]).map_err(|_| KeystoreError::InvalidSecret)?;
+        let mut chain_code = [0u8; 32];
+        chain_code.copy_from_slice(&secret[32..]);
+
+        let mut wallet = WalletAccount::from_secret_key(
+            secretkey,
+            chain_code,
+            keystore.chain_id,
+            keystore.address_prefix,
+            keystore.address_hrp,
+        );
+        if wallet.pubkey != keystore.pubkey {
+            return Err(KeystoreError::InvalidSecret);
+        }
+
+        wallet.addresses.extend(keystore.addresses);
+        wallet.claims = keystore.claims;
+        wallet.txn_nonces = keystore.txn_nonces;
+        wallet.multisig_policies = keystore.multisig_policies;
+
+        Ok(wallet)
+    }
+
+    pub fn change_password(
+        path: &str,
+        old_password: &str,
+        new_password: &str,
+    ) -> Result<(), KeystoreError> {
+        let mut keystore = Keystore::load(path)?;
+        keystore.change_password(old_password, new_password)?;
+        keystore.save(path)
+    }
+
+    /// The master secret key followed by the chain code.
+    fn keystore_secret(&self) -> Result<Vec<u8>, KeystoreError> {
+        let secretkey =
+            SecretKey::from_str(&self.secretkey).map_err(|_| KeystoreError::InvalidSecret)?;
+        let mut secret = secretkey[..].to_vec();
+        secret.extend_from_slice(&self.chain_code);
+        Ok(secret)
+    }
+
+    /// The nonce of the next txn from `address`, see
+    /// `NetworkState::next_txn_nonce`. Never below the wallet's own counter
+    /// for `address`, which covers txns it sent that haven't reached the pool
+    /// yet.
+    pub fn get_txn_nonce(
+        &self,
+        address: &str,
+        network_state: &NetworkState,
+        txn_pool: &Pool<String, Txn>,
+    ) -> u128 {
+        network_state
+            .next_txn_nonce(address, txn_pool)
+            .max(self.txn_nonce(address))
+    }
+
+    /// The wallet's own counter of the next txn nonce of `address`.
+    pub fn txn_nonce(&self, address: &str) -> u128 {
+        self.txn_nonces.get(address).copied().unwrap_or(0)
+    }
+
+    /// Records that the next txn from `address` uses `nonce`.
+    pub fn set_txn_nonce(&mut self, address: &str, nonce: u128) {
+        self.txn_nonces.insert(address.to_string(), nonce);
     }
 
     pub fn get_new_addresses(&mut self, number_of_addresses: u8) {
-        let mut counter = 1u8;
-        (counter..=number_of_addresses).for_each(|n| {
-            let mut address_bytes = self.pubkey.as_bytes().to_vec();
-            address_bytes.push(n);
-            let address = digest_bytes(digest_bytes(&address_bytes).as_bytes());
-            let mut address_prefix: String = "0x192".to_string();
-            address_prefix.push_str(&address);
-            self.addresses.insert(n as u32, address_prefix);
-            counter += 1
+        (1..=number_of_addresses as u32).for_each(|n| {
+            if let Ok(address) = self.derive_address(n) {
+                self.addresses.insert(n, address);
+            }
         })
     }
 
+    /// Derives the secret key of address number `address_number` as the
+    /// hardened BIP32 child of the master key with the same index.
+    pub fn derive_child_key(&self, address_number: u32) -> Result<SecretKey, WalletError> {
+        self.ensure_can_sign()?;
+        if address_number >= HARDENED_OFFSET {
+            return Err(WalletError::InvalidAddressNumber(address_number));
+        }
+
+        let parent = SecretKey::from_str(&self.secretkey)?;
+        let mut mac =
+            HmacSha512::new_from_slice(&self.chain_code).expect("HMAC accepts keys of any length");
+        mac.update(&[0u8]);
+        mac.update(&parent[..]);
+        mac.update(&(HARDENED_OFFSET + address_number).to_be_bytes());
+        let result = mac.finalize().into_bytes();
+
+        let mut child = parent;
+        child.add_assign(&result[..32])?;
+
+        Ok(child)
+    }
+
+    pub fn get_address_pubkey(&self, address_number: u32) -> Result<String, WalletError> {
+        Ok(self.address_signer(address_number)?.pubkey())
+    }
+
+    /// The bech32m address controlled by the child key of `address_number`.
+    pub fn derive_address(&self, address_number: u32) -> Result<String, WalletError> {
+        let pubkey = self.get_address_pubkey(address_number)?;
+        Ok(Address::from_pubkey(&self.address_hrp, &pubkey).to_string())
+    }
+
+    /// Rediscovers the addresses of a restored wallet by deriving addresses in
+    /// order and looking each one up in the ledger built from the chain, until
+    /// `ADDRESS_GAP_LIMIT` addresses in a row have never been used. Returns the
+    /// number of the highest used address.
+    pub fn discover_addresses(&mut self, network_state: &NetworkState) -> u32 {
+        let mut used: HashSet<String> = network_state.get_credits().keys().cloned().collect();
+        used.extend(network_state.get_debits().keys().cloned());
+
+        let mut last_used = 0u32;
+        let mut address_number = 1u32;
+        while address_number - last_used <= ADDRESS_GAP_LIMIT {
+            match self.derive_address(address_number) {
+                Ok(address) if used.contains(&address) => last_used = address_number,
+                Ok(_) => {}
+                Err(_) => break,
+            }
+            address_number += 1;
+        }
+
+        (1..=last_used.max(1)).for_each(|n| {
+            if !self.addresses.contains_key(&n) {
+                if let Ok(address) = self.derive_address(n) {
+                    self.addresses.insert(n, address);
+                }
+            }
+        });
+
+        last_used
+    }
+
     pub fn get_wallet_addresses(&self) -> LinkedHashMap<u32, String> {
         self.addresses.clone()
     }
@@ -151,49 +478,43 @@ impl WalletAccount {
         self.total_balances.clone()
     }
 
-    pub fn update_balances(&mut self, network_state: NetworkState) {
-        let mut balance_map = LinkedHashMap::new();
-        self.get_balances(network_state)
+    /// Refreshes the confirmed (`total_balances`) and spendable
+    /// (`available_balances`) amounts of every address of the wallet.
+    pub fn update_balances(&mut self, balance_service: &BalanceService) {
+        let mut total_balances = LinkedHashMap::new();
+        let mut available_balances = LinkedHashMap::new();
+        self.get_balances(balance_service)
             .iter()
-            .for_each(|(address, balance)| {
-                let mut vrrb_map = LinkedHashMap::new();
-                vrrb_map.insert("VRRB".to_string(), *balance);
-                balance_map.insert(address.clone(), vrrb_map);
+            .for_each(|(address, tokens)| {
+                let mut total = LinkedHashMap::new();
+                let mut available = LinkedHashMap::new();
+                tokens.iter().for_each(|(token, balance)| {
+                    total.insert(token.clone(), balance.confirmed);
+                    available.insert(token.clone(), balance.spendable);
+                });
+                total_balances.insert(address.clone(), total);
+                available_balances.insert(address.clone(), available);
             });
 
-        self.total_balances = balance_map;
+        self.total_balances = total_balances;
+        self.available_balances = available_balances;
     }
 
-    pub fn get_balances(&self, network_state: NetworkState) -> LinkedHashMap<String, u128> {
-        let mut balance_map = LinkedHashMap::new();
-
-        self.addresses.iter().for_each(|(_, address)| {
-            let balance = network_state.get_balance(&address);
-            balance_map.insert(address.clone(), balance);
-        });
-
-        balance_map
+    pub fn get_balances(
+        &self,
+        balance_service: &BalanceService,
+    ) -> LinkedHashMap<String, LinkedHashMap<String, Balance>> {
+        balance_service.balances(&self.all_addresses())
     }
 
     pub fn get_address_balance(
         &mut self,
-        network_state: NetworkState,
+        balance_service: &BalanceService,
         address_number: u32,
-    ) -> Option<u128> {
-        self.update_balances(network_state);
-        if let Some(address) = self.addresses.get(&address_number) {
-            if let Some(entry) = self.total_balances.get(&address.clone()) {
-                if let Some(amount) = entry.get("VRRB") {
-                    return Some(*amount);
-                } else {
-                    return None;
-                }
-            } else {
-                return None;
-            }
-        } else {
-            return None;
-        }
+    ) -> Option<Balance> {
+        self.update_balances(balance_service);
+        let address = self.addresses.get(&address_number)?;
+        Some(balance_service.balance(address, NATIVE_TOKEN))
     }
 
     pub fn n_claims_owned(&self) -> u128 {
@@ -212,58 +533,62 @@ impl WalletAccount {
         self.secretkey.clone()
     }
 
-    pub fn sign(&self, message: &str) -> Result<Signature, Error> {
-        let message_bytes = message.as_bytes().to_owned();
-        let mut buffer = ByteBuffer::new();
-        buffer.write_bytes(&message_bytes);
-        while buffer.len() < 32 {
-            buffer.write_u8(0);
-        }
+    /// The signer for the master key, used for claims and mined blocks.
+    pub fn signer(&self) -> Result<KeySigner, WalletError> {
+        self.ensure_can_sign()?;
+        Ok(KeySigner::from_hex(&self.secretkey)?)
+    }
 
-        let new_message = buffer.to_bytes();
-        let message_hash = blake3::hash(&new_message);
-        let message_hash = Message::from_slice(message_hash.as_bytes())?;
-        let secp = Secp256k1::new();
-        let sk = SecretKey::from_str(&self.secretkey).unwrap();
-        let sig = secp.sign(&message_hash, &sk);
-        Ok(sig)
+    /// The signer for the child key of `address_number`, used for the
+    /// address's txns.
+    pub fn address_signer(&self, address_number: u32) -> Result<KeySigner, WalletError> {
+        Ok(KeySigner::new(self.derive_child_key(address_number)?))
+    }
+
+    /// Signs with the master key.
+    pub fn sign(&self, message: &str) -> Result<Signature, WalletError> {
+        Ok(self.signer()?.sign(message)?)
+    }
+
+    /// Signs with the child key of `address_number`.
+    pub fn sign_with_address(
+        &self,
+        address_number: u32,
+        message: &str,
+    ) -> Result<Signature, WalletError> {
+        Ok(self.address_signer(address_number)?.sign(message)?)
     }
 
     /// Verify a signature with the signers public key, the message payload and the signature.
     pub fn verify(message: String, signature: Signature, pk: PublicKey) -> Result<bool, Error> {
-        let message_bytes = message.as_bytes().to_owned();
-
-        let mut buffer = ByteBuffer::new();
-        buffer.write_bytes(&message_bytes);
-        while buffer.len() < 32 {
-            buffer.write_u8(0);
+        if signer::verify(&message, &signature.to_string(), &pk.to_string()) {
+            Ok(true)
+        } else {
+            Err(Error::IncorrectSignature)
         }
-        let new_message = buffer.to_bytes();
-        let message_hash = blake3::hash(&new_message);
-        let message_hash = Message::from_slice(message_hash.as_bytes())?;
-        let secp = Secp256k1::new();
-        let valid = secp.verify(&message_hash, &signature, &pk);
+    }
 
-        match valid {
-            Ok(()) => Ok(true),
-            _ => Err(Error::IncorrectSignature),
-        }
+    /// Every address of the wallet, including the multisig addresses it
+    /// cosigns.
+    pub fn all_addresses(&self) -> Vec<String> {
+        self.addresses
+            .iter()
+            .map(|(_, address)| address.clone())
+            .chain(self.multisig_policies.keys().cloned())
+            .collect()
     }
 
-    pub fn txns_in_block(&mut self, block: &Block) {
-        let _my_txns = {
-            let mut some_txn = false;
-            self.addresses.iter().for_each(|(_, address)| {
-                let mut cloned_data = block.txns.clone();
-                cloned_data.retain(|_, txn| {
-                    txn.receiver_address == address.clone() || txn.sender_address == address.clone()
-                });
-                if !cloned_data.is_empty() {
-                    some_txn = true;
-                }
-            });
-            some_txn
-        };
+    /// The txns in `block` sent from or to one of the wallet's addresses.
+    pub fn txns_in_block(&self, block: &Block) -> Vec<Txn> {
+        let addresses = self.all_addresses();
+        block
+            .txns
+            .iter()
+            .filter(|(_, txn)| {
+                addresses.contains(&txn.receiver_address) || addresses.contains(&txn.sender_address)
+            })
+            .map(|(_, txn)| txn.clone())
+            .collect()
     }
 
     pub fn send_txn(
@@ -271,38 +596,139 @@ impl WalletAccount {
         address_number: u32,
         receiver: String,
         amount: u128,
-    ) -> Result<Txn, Error> {
+        network_state: &NetworkState,
+        txn_pool: &Pool<String, Txn>,
+    ) -> Result<Txn, WalletError> {
+        self.ensure_can_sign()?;
+        Address::parse_for_network(&receiver, &self.address_hrp, &self.address_prefix)?;
+        let sender = self.get_address(address_number);
+        let nonce = self.get_txn_nonce(&sender, network_state, txn_pool);
         let txn = Txn::new(
             Arc::new(Mutex::new(self.clone())),
-            self.addresses.get(&address_number).unwrap().clone(),
+            address_number,
             receiver,
             amount,
-            self.txn_nonce,
-        );
-        self.txn_nonce += 1;
+            nonce,
+        )?;
+        self.set_txn_nonce(&sender, nonce + 1);
 
         Ok(txn)
     }
 
+    /// Signs a txn built elsewhere, e.g. by a networked node, with the key of
+    /// its sender address. Used to sign on a machine that never goes online.
+    pub fn sign_txn(&self, unsigned: UnsignedTxn) -> Result<Txn, WalletError> {
+        self.ensure_can_sign()?;
+        if unsigned.chain_id != self.chain_id {
+            return Err(WalletError::WrongChain {
+                expected: self.chain_id,
+                found: unsigned.chain_id,
+            });
+        }
+
+        if let Some(policy) = self.multisig_policies.get(&unsigned.sender_address) {
+            let mut txn = unsigned.into_multisig(policy.clone());
+            self.sign_multisig_txn(&mut txn)?;
+            return Ok(txn);
+        }
+
+        let address_number = self
+            .find_address_number(&unsigned.sender_address)
+            .ok_or_else(|| WalletError::UnknownAddress(unsigned.sender_address.clone()))?;
+        Ok(unsigned.sign(&self.address_signer(address_number)?)?)
+    }
+
+    /// Creates the M-of-N multisig address controlled by any `threshold` of
+    /// `pubkeys` and remembers it so the wallet can cosign its txns. One of
+    /// the pubkeys has to be a key of this wallet, see `get_address_pubkey`.
+    pub fn create_multisig_address(
+        &mut self,
+        threshold: usize,
+        pubkeys: Vec<String>,
+    ) -> Result<String, WalletError> {
+        self.ensure_can_sign()?;
+        let policy = MultisigPolicy::new(threshold, pubkeys)?;
+        if self.find_cosigner_key(&policy).is_none() {
+            return Err(WalletError::Multisig(MultisigError::NotACosigner(
+                self.pubkey.clone(),
+            )));
+        }
+
+        let address = Address::from_multisig(&self.address_hrp, &policy).to_string();
+        self.multisig_policies.insert(address.clone(), policy);
+        Ok(address)
+    }
+
+    /// Adds this wallet's signature to a multisig txn. Once enough cosigners
+    /// have signed, `Txn::finalize` succeeds and the txn can be broadcast.
+    pub fn sign_multisig_txn(&self, txn: &mut Txn) -> Result<(), WalletError> {
+        self.ensure_can_sign()?;
+        let policy = txn
+            .multisig
+            .clone()
+            .ok_or_else(|| WalletError::UnknownAddress(txn.sender_address.clone()))?;
+        let address_number = self
+            .find_cosigner_key(&policy)
+            .ok_or_else(|| MultisigError::NotACosigner(self.pubkey.clone()))?;
+
+        let signer = self.address_signer(address_number)?;
+        let signature = signer.sign(&txn.txn_payload)?;
+        txn.add_signature(&signer.pubkey(), &signature.to_string())?;
+        Ok(())
+    }
+
+    /// The address number whose key is one of the cosigners of `policy`.
+    fn find_cosigner_key(&self, policy: &MultisigPolicy) -> Option<u32> {
+        let last_known = self.addresses.keys().max().copied().unwrap_or(0);
+        (1..=last_known + ADDRESS_GAP_LIMIT).find(|n| {
+            self.get_address_pubkey(*n)
+                .map_or(false, |pubkey| policy.is_cosigner(&pubkey))
+        })
+    }
+
+    /// Looks `address` up in the wallet's addresses, then derives addresses past
+    /// the last known one up to the gap limit.
+    pub fn find_address_number(&self, address: &str) -> Option<u32> {
+        if let Some((address_number, _)) = self.addresses.iter().find(|(_, a)| *a == address) {
+            return Some(*address_number);
+        }
+
+        let last_known = self.addresses.keys().max().copied().unwrap_or(0);
+        (1..=last_known + ADDRESS_GAP_LIMIT)
+            .find(|n| self.derive_address(*n).map_or(false, |a| a == address))
+    }
+
+    /// The address with number `address_number`, generating the addresses up
+    /// to it if needed. A watch-only wallet can't generate addresses, it gives
+    /// an empty string for numbers it doesn't follow.
     pub fn get_address(&mut self, address_number: u32) -> String {
-        if let Some(address) = self.addresses.get(&address_number) {
-            return address.to_string();
-        } else {
+        if !self.watch_only {
             while self.addresses.len() < address_number as usize {
                 self.generate_new_address()
             }
-            self.get_address(address_number)
         }
+
+        self.addresses
+            .get(&address_number)
+            .cloned()
+            .unwrap_or_default()
     }
 
     pub fn generate_new_address(&mut self) {
-        let uid = Uuid::new_v4().to_string();
         let address_number: u32 = self.addresses.len() as u32 + 1u32;
-        let payload = format!("{},{},{}", &address_number, &uid, &self.pubkey);
-        let address = digest_bytes(payload.as_bytes());
+        let address = self
+            .derive_address(address_number)
+            .expect("Address numbers below the hardened offset always derive");
         self.addresses.insert(address_number, address);
     }
 
+    fn ensure_can_sign(&self) -> Result<(), WalletError> {
+        if self.watch_only {
+            return Err(WalletError::WatchOnly);
+        }
+        Ok(())
+    }
+
     pub fn as_bytes(&self) -> Vec<u8> {
         let as_string = serde_json::to_string(self).unwrap();
         as_string.as_bytes().iter().copied().collect()
@@ -316,6 +742,19 @@ impl WalletAccount {
     }
 }
 
+/// Derives the BIP32 master secret key and chain code from a BIP39 seed.
+fn master_key_from_seed(seed: &[u8]) -> Result<(SecretKey, [u8; 32]), WalletError> {
+    let mut mac =
+        HmacSha512::new_from_slice(MASTER_KEY_HMAC_KEY).expect("HMAC accepts keys of any length");
+    mac.update(seed);
+    let result = mac.finalize().into_bytes();
+
+    let mut chain_code = [0u8; 32];
+    chain_code.copy_from_slice(&result[32..]);
+
+    Ok((SecretKey::from_slice(&result[..32])?, chain_code))
+}
+
 impl fmt::Display for WalletAccount {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
@@ -337,13 +776,247 @@ impl Clone for WalletAccount {
     fn clone(&self) -> WalletAccount {
         WalletAccount {
             secretkey: self.secretkey.clone(),
+            chain_code: self.chain_code,
             welcome_message: self.welcome_message.clone(),
             pubkey: self.pubkey.clone(),
             addresses: self.addresses.clone(),
             total_balances: self.total_balances.clone(),
             available_balances: self.available_balances.clone(),
             claims: self.claims.clone(),
-            txn_nonce: self.txn_nonce.clone(),
+            txn_nonces: self.txn_nonces.clone(),
+            chain_id: self.chain_id,
+            address_prefix: self.address_prefix.clone(),
+            address_hrp: self.address_hrp.clone(),
+            multisig_policies: self.multisig_policies.clone(),
+            watch_only: self.watch_only,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::network_config::{
+        TESTNET_ADDRESS_HRP, TESTNET_ADDRESS_PREFIX, TESTNET_CHAIN_ID,
+    };
+    use crate::test_utils::TempDb;
+
+    #[test]
+    fn test_child_keys_match_bip32_test_vector() {
+        let seed = (0u8..16).collect::<Vec<u8>>();
+        let (secretkey, chain_code) = master_key_from_seed(&seed).unwrap();
+        let wallet = WalletAccount::from_secret_key(
+            secretkey,
+            chain_code,
+            TESTNET_CHAIN_ID,
+            TESTNET_ADDRESS_PREFIX.to_string(),
+            TESTNET_ADDRESS_HRP.to_string(),
+        );
+
+        assert_eq!(
+            wallet.get_secretkey(),
+            "e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35"
+        );
+        assert_eq!(
+            wallet.derive_child_key(0).unwrap().to_string(),
+            "edb2e14f9ee77d26dd93b4ecede8d16ed408ce149b6cd80b0715a2d911a0afea"
+        );
+    }
+
+    #[test]
+    fn test_mnemonic_restores_the_same_addresses() {
+        let network_config = NetworkConfig::testnet();
+        let phrase = WalletAccount::generate_mnemonic(24).unwrap().to_string();
+        let wallet = WalletAccount::from_mnemonic(&phrase, "passphrase", &network_config).unwrap();
+        let restored =
+            WalletAccount::from_mnemonic(&phrase, "passphrase", &network_config).unwrap();
+
+        assert_eq!(wallet.pubkey, restored.pubkey);
+        assert_eq!(
+            wallet.derive_address(7).unwrap(),
+            restored.derive_address(7).unwrap()
+        );
+        assert_ne!(
+            wallet.derive_address(1).unwrap(),
+            wallet.derive_address(2).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_keystore_round_trip_and_password_change() {
+        let keystore = TempDb::new("keystore");
+        let path = keystore.path();
+        let mut wallet = WalletAccount::new(&NetworkConfig::testnet());
+        let address = wallet.get_address(1);
+        wallet.set_txn_nonce(&address, 3);
+        wallet.save(path, "old password").unwrap();
+
+        assert!(matches!(
+            WalletAccount::load(path, "wrong password"),
+            Err(KeystoreError::IncorrectPassword)
+        ));
+
+        WalletAccount::change_password(path, "old password", "new password").unwrap();
+        let restored = WalletAccount::load(path, "new password").unwrap();
+        assert_eq!(restored.get_secretkey(), wallet.get_secretkey());
+        assert_eq!(restored.txn_nonce(&address), 3);
+        assert_eq!(restored.addresses, wallet.addresses);
+    }
+    #[test]
+    fn test_unsigned_txn_signed_offline_is_valid() {
+        use crate::verifiable::Verifiable;
+
+        let wallet = WalletAccount::new(&NetworkConfig::testnet());
+        let sender = wallet.derive_address(3).unwrap();
+        let receiver = wallet.derive_address(5).unwrap();
+        let unsigned = UnsignedTxn::new(wallet.chain_id, sender.clone(), receiver, 10, 4);
+
+        let imported = UnsignedTxn::import(&unsigned.export()).unwrap();
+        let txn = wallet.sign_txn(imported).unwrap();
+        let txn = Txn::import(&txn.export()).unwrap();
+
+        assert_eq!(txn.sender_address, sender);
+        assert_eq!(txn.nonce, 4);
+        assert!(txn.valid_addresses(TESTNET_ADDRESS_HRP, TESTNET_ADDRESS_PREFIX));
+        assert!(!txn.valid_addresses("vrrb", TESTNET_ADDRESS_PREFIX));
+        assert!(txn.valid_txn_signature());
+    }
+
+    #[test]
+    fn test_two_of_three_multisig_txn() {
+        use crate::verifiable::Verifiable;
+
+        let network_config = NetworkConfig::testnet();
+        let cosigners = (0..3)
+            .map(|_| WalletAccount::new(&network_config))
+            .collect::<Vec<_>>();
+        let pubkeys = cosigners
+            .iter()
+            .map(|wallet| wallet.get_address_pubkey(1).unwrap())
+            .collect::<Vec<_>>();
+
+        let mut treasury = cosigners[0].clone();
+        let address = treasury.create_multisig_address(2, pubkeys).unwrap();
+        let receiver = cosigners[1].derive_address(1).unwrap();
+        let unsigned = UnsignedTxn::new(treasury.chain_id, address, receiver, 10, 0);
+
+        let mut txn = treasury.sign_txn(unsigned).unwrap();
+        assert!(txn.finalize().is_err());
+        assert!(!txn.valid_txn_signature());
+
+        cosigners[2].sign_multisig_txn(&mut txn).unwrap();
+        assert!(txn.finalize().is_ok());
+        assert!(txn.valid_addresses(TESTNET_ADDRESS_HRP, TESTNET_ADDRESS_PREFIX));
+        assert!(txn.valid_txn_signature());
+    }
+
+    #[test]
+    fn test_new_wallet_only_has_confirmed_coins_spendable() {
+        use crate::pool::{Pool, PoolKind};
+
+        let db = TempDb::new("wallet_state");
+        let network_state = NetworkState::restore(db.path());
+        let mut txn_pool = Pool::new(PoolKind::Txn);
+
+        let mut wallet = WalletAccount::new(&NetworkConfig::testnet());
+        let address = wallet.get_address(1);
+        let payer = WalletAccount::new(&NetworkConfig::testnet());
+        let payer_address = payer.derive_address(1).unwrap();
+        let unsigned = UnsignedTxn::new(
+            payer.chain_id,
+            payer_address.clone(),
+            address.clone(),
+            25,
+            0,
+        );
+        let txn = payer.sign_txn(unsigned).unwrap();
+        txn_pool.pending.insert(txn.txn_id.clone(), txn);
+
+        let service = BalanceService::new(&network_state, &txn_pool);
+        let balance = wallet.get_address_balance(&service, 1).unwrap();
+        assert_eq!(balance.confirmed, 0);
+        assert_eq!(balance.pending_in, 25);
+        assert_eq!(balance.spendable, 0);
+        assert_eq!(wallet.available_balances[&address][NATIVE_TOKEN], 0);
+        assert_eq!(service.balance(&payer_address, NATIVE_TOKEN).pending_out, 25);
+    }
+
+    #[test]
+    fn test_send_txn_uses_the_next_nonce_of_the_sender() {
+        use crate::pool::{Pool, PoolKind};
+
+        let db = TempDb::new("nonce_state");
+        let mut network_state = NetworkState::restore(db.path());
+        let mut txn_pool = Pool::new(PoolKind::Txn);
+        let mut wallet = WalletAccount::new(&NetworkConfig::testnet());
+        let sender = wallet.get_address(1);
+        let receiver = wallet.derive_address(2).unwrap();
+
+        // A restored wallet starts counting at 0, the ledger knows better.
+        let mut ledger = network_state.db_to_ledger();
+        ledger.nonces.insert(sender.clone(), 3);
+        network_state.update_ledger(ledger, network_state.reward_state);
+        assert_eq!(wallet.get_txn_nonce(&sender, &network_state, &txn_pool), 3);
+
+        let txn = wallet
+            .send_txn(1, receiver.clone(), 1, &network_state, &txn_pool)
+            .unwrap();
+        assert_eq!(txn.nonce, 3);
+        txn_pool.pending.insert(txn.txn_id.clone(), txn);
+        let txn = wallet
+            .send_txn(1, receiver.clone(), 1, &network_state, &txn_pool)
+            .unwrap();
+        assert_eq!(txn.nonce, 4);
+        assert_eq!(wallet.txn_nonce(&sender), 5);
+
+        // Other addresses have nonces of their own, also after the sender's
+        // txns left the pool.
+        txn_pool.pending.clear();
+        let other = wallet.get_address(2);
+        assert_eq!(wallet.get_txn_nonce(&other, &network_state, &txn_pool), 0);
+        let txn = wallet
+            .send_txn(2, receiver, 1, &network_state, &txn_pool)
+            .unwrap();
+        assert_eq!(txn.nonce, 0);
+        assert_eq!(wallet.txn_nonce(&sender), 5);
+        assert_eq!(wallet.txn_nonce(&other), 1);
+    }
+
+    #[test]
+    fn test_watch_only_wallet_follows_addresses_but_refuses_to_sign() {
+        let network_config = NetworkConfig::testnet();
+        let wallet = WalletAccount::new(&network_config);
+        let entries = vec![
+            wallet.get_address_pubkey(1).unwrap(),
+            wallet.derive_address(2).unwrap(),
+        ];
+
+        let watch_only = WalletAccount::watch_only(&entries, &network_config).unwrap();
+        assert_eq!(
+            watch_only.all_addresses(),
+            vec![
+                wallet.derive_address(1).unwrap(),
+                wallet.derive_address(2).unwrap()
+            ]
+        );
+
+        let imported = WalletAccount::import_watch_only(&wallet.export_watch_only()).unwrap();
+        assert!(imported.watch_only);
+        assert_eq!(imported.addresses, wallet.addresses);
+
+        let unsigned = UnsignedTxn::new(
+            wallet.chain_id,
+            wallet.derive_address(1).unwrap(),
+            wallet.derive_address(2).unwrap(),
+            10,
+            0,
+        );
+        assert!(matches!(
+            imported.sign_txn(unsigned),
+            Err(WalletError::WatchOnly)
+        ));
+        assert!(matches!(imported.signer(), Err(WalletError::WatchOnly)));
+        assert!(WalletAccount::watch_only(&["not an address".to_string()], &network_config)
+            .is_err());
+    }
+}